@@ -0,0 +1,243 @@
+// 在合成的文件树上比较索引的存储方式，不需要真实的卷
+use criterion::{black_box, criterion_group, criterion_main, Criterion, SamplingMode, Throughput};
+use std::{
+    collections::HashMap,
+    mem::size_of,
+    path::MAIN_SEPARATOR_STR,
+    time::{Duration, Instant},
+};
+
+use ffd::{Cursor, FileAttributes, FileInfo, Index, ParOptions, UsnRecord};
+
+const N: usize = 100_0000;
+/// 平均每个目录中的文件数
+const FANOUT: usize = 20;
+const ROOT: u128 = 5;
+
+/// 固定种子的文件树，目录总是出现在它的子项之后，和枚举 MFT 时一样乱序
+fn synthetic(n: usize) -> Vec<UsnRecord> {
+    const WORDS: [&str; 16] = [
+        "project",
+        "src",
+        "main",
+        "test",
+        "Report",
+        "data",
+        "backup",
+        "image",
+        "video",
+        "music",
+        "新建文件夹",
+        "文档",
+        "config",
+        "build",
+        "target",
+        "README",
+    ];
+    const EXTS: [&str; 8] = ["", ".rs", ".txt", ".docx", ".jpg", ".mp4", ".json", ".md"];
+
+    let mut seed: u64 = 0x2545_F491_4F6C_DD1D;
+    let mut next = move || {
+        seed ^= seed << 13;
+        seed ^= seed >> 7;
+        seed ^= seed << 17;
+        seed
+    };
+
+    let dirs = n / FANOUT;
+    let mut records: Vec<_> = (0..n)
+        .map(|i| {
+            let frn = 100 + i as u128;
+            // 前 dirs 个是目录，父目录的编号总是更小
+            let parent_frn = match i {
+                0 => ROOT,
+                _ => 100 + (next() as usize % i.min(dirs)) as u128,
+            };
+            let r = next() as usize;
+            let filename = format!(
+                "{} {}{}",
+                WORDS[r % WORDS.len()],
+                r % 1000,
+                if i < dirs {
+                    ""
+                } else {
+                    EXTS[r / 7 % EXTS.len()]
+                }
+            );
+            UsnRecord {
+                frn,
+                parent_frn,
+                filename,
+                attributes: if i < dirs {
+                    FileAttributes::DIRECTORY
+                } else {
+                    FileAttributes::ARCHIVE
+                },
+                ..Default::default()
+            }
+        })
+        .collect();
+    records.reverse();
+    records
+}
+
+/// 之前的存储方式：每个文件一个 HashMap 项，名称单独分配
+struct HashMapIndex {
+    map: HashMap<u128, ((u128, Box<str>), FileInfo)>,
+}
+
+impl HashMapIndex {
+    fn build(records: &[UsnRecord]) -> Self {
+        let mut map = HashMap::with_capacity(10_0000);
+        for r in records {
+            let info = FileInfo::from(r);
+            map.insert(r.frn, ((r.parent_frn, r.filename.as_str().into()), info));
+        }
+        Self { map }
+    }
+
+    fn find(&self, sub: &str) -> usize {
+        let sub = sub.to_lowercase();
+        let mut count = 0;
+        for ((parent_frn, name), _) in self.map.values() {
+            if name.to_lowercase().contains(&sub) {
+                let mut parts = vec![&**name];
+                let mut frn = *parent_frn;
+                while let Some(((parent_frn, name), _)) = self.map.get(&frn) {
+                    parts.push(name);
+                    frn = *parent_frn;
+                }
+                parts.push("X:");
+                parts.reverse();
+                black_box(parts.join(MAIN_SEPARATOR_STR));
+                count += 1;
+            }
+        }
+        count
+    }
+
+    /// 按容量估算，名称按 8 字节对齐的分配计算
+    fn memory_usage(&self) -> usize {
+        let entries = self.map.capacity() * (size_of::<(u128, ((u128, Box<str>), FileInfo))>() + 1);
+        let names: usize = self
+            .map
+            .values()
+            .map(|((_, name), _)| name.len().next_multiple_of(8))
+            .sum();
+        entries + names
+    }
+}
+
+fn build_arena(records: &[UsnRecord]) -> Index {
+    let mut index = Index::with_capacity("X:".to_string(), Cursor { id: 0, usn: 0 }, 10_0000);
+    for r in records {
+        index.add_link(r.clone());
+    }
+    index.shrink_to_fit();
+    index
+}
+
+fn storage(c: &mut Criterion) {
+    let records = synthetic(N);
+    let arena = build_arena(&records);
+    let hash_map = HashMapIndex::build(&records);
+    let mib = |bytes: usize| bytes as f64 / (1024.0 * 1024.0);
+    println!("arena：{}", arena.memory_usage());
+    println!("hash_map：{:.1}MiB", mib(hash_map.memory_usage()));
+
+    let mut group = c.benchmark_group("storage/build");
+    group.sampling_mode(SamplingMode::Flat);
+    group.sample_size(10);
+    group.warm_up_time(Duration::from_secs(1));
+    group.throughput(Throughput::Elements(N as _));
+    group.bench_function("arena", |b| b.iter(|| build_arena(&records)));
+    group.bench_function("hash_map", |b| b.iter(|| HashMapIndex::build(&records)));
+    group.finish();
+
+    let mut group = c.benchmark_group("storage/find");
+    group.sampling_mode(SamplingMode::Flat);
+    group.sample_size(10);
+    group.throughput(Throughput::Elements(N as _));
+    // 之前的方式只支持单个关键词
+    for sub in [".docx", "文档"] {
+        assert_eq!(arena.find_iter(sub).count(), hash_map.find(sub));
+        group.bench_function(format!("arena/{sub}"), |b| {
+            b.iter(|| arena.find_iter(sub).count())
+        });
+        group.bench_function(format!("hash_map/{sub}"), |b| b.iter(|| hash_map.find(sub)));
+    }
+    group.finish();
+}
+
+/// 不同规模下的单线程和多线程查找：没有结果的关键词只有扫描折叠名称区的开销，
+/// 常见的关键词则主要是拼接路径
+fn search(c: &mut Criterion) {
+    let mut group = c.benchmark_group("search");
+    group.sampling_mode(SamplingMode::Flat);
+    group.sample_size(10);
+    for n in [10_0000, N] {
+        let index = build_arena(&synthetic(n));
+        group.throughput(Throughput::Elements(n as _));
+        for sub in [
+            "not found",
+            "readme 99",
+            "REPORT",
+            ".rs",
+            "regex:not found",
+            "path:not found",
+            "path:REPORT",
+            "pinyin:wd",
+        ] {
+            group.bench_function(format!("{n}/{sub}"), |b| {
+                b.iter(|| index.find_iter(black_box(sub)).count())
+            });
+            for ordered in [false, true] {
+                let options = ParOptions {
+                    threads: 0,
+                    ordered,
+                };
+                let name = if ordered { "par_ordered" } else { "par" };
+                group.bench_function(format!("{n}/{sub}/{name}"), |b| {
+                    b.iter(|| {
+                        let mut count = 0;
+                        index.par_find(black_box(sub), options, |_| {
+                            count += 1;
+                            true
+                        });
+                        count
+                    })
+                });
+            }
+        }
+    }
+    group.finish();
+}
+
+/// 500 万个名称上扫描与倒排表的对比，短于三个字节的关键词两者都是扫描
+fn trigram(c: &mut Criterion) {
+    let mut index = build_arena(&synthetic(5 * N));
+    let mut group = c.benchmark_group("trigram");
+    group.sampling_mode(SamplingMode::Flat);
+    group.sample_size(10);
+    group.throughput(Throughput::Elements(5 * N as u64));
+    let queries = ["not found", "readme 99", "文档 12", "REPORT", "e 9", "文"];
+    for trigrams in [false, true] {
+        let start = Instant::now();
+        index.set_trigrams(trigrams);
+        println!(
+            "倒排表 {trigrams}：{:?}，{}",
+            start.elapsed(),
+            index.memory_usage()
+        );
+        let name = if trigrams { "trigram" } else { "scan" };
+        for sub in queries {
+            group.bench_function(format!("{sub}/{name}"), |b| {
+                b.iter(|| index.find_iter(black_box(sub)).count())
+            });
+        }
+    }
+    group.finish();
+}
+
+criterion_group!(benches, storage, search, trigram);
+criterion_main!(benches);
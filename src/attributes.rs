@@ -0,0 +1,103 @@
+use std::{
+    fmt::Debug,
+    ops::{BitOr, BitOrAssign},
+};
+
+/// 文件属性，与 Windows 的 `FILE_ATTRIBUTE_*` 相同。
+///
+/// Linux 上只会设置 [`DIRECTORY`](Self::DIRECTORY) 和 [`HIDDEN`](Self::HIDDEN)。
+// https://learn.microsoft.com/zh-cn/windows/win32/fileio/file-attribute-constants
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub struct FileAttributes(u32);
+
+impl FileAttributes {
+    pub const READONLY: Self = Self(0x0000_0001);
+    pub const HIDDEN: Self = Self(0x0000_0002);
+    pub const SYSTEM: Self = Self(0x0000_0004);
+    pub const DIRECTORY: Self = Self(0x0000_0010);
+    pub const ARCHIVE: Self = Self(0x0000_0020);
+    pub const DEVICE: Self = Self(0x0000_0040);
+    pub const NORMAL: Self = Self(0x0000_0080);
+    pub const TEMPORARY: Self = Self(0x0000_0100);
+    pub const SPARSE_FILE: Self = Self(0x0000_0200);
+    pub const REPARSE_POINT: Self = Self(0x0000_0400);
+    pub const COMPRESSED: Self = Self(0x0000_0800);
+    pub const OFFLINE: Self = Self(0x0000_1000);
+    pub const NOT_CONTENT_INDEXED: Self = Self(0x0000_2000);
+    pub const ENCRYPTED: Self = Self(0x0000_4000);
+    pub const INTEGRITY_STREAM: Self = Self(0x0000_8000);
+    pub const VIRTUAL: Self = Self(0x0001_0000);
+    pub const NO_SCRUB_DATA: Self = Self(0x0002_0000);
+    pub const RECALL_ON_OPEN: Self = Self(0x0004_0000);
+    pub const PINNED: Self = Self(0x0008_0000);
+    pub const UNPINNED: Self = Self(0x0010_0000);
+    pub const RECALL_ON_DATA_ACCESS: Self = Self(0x0040_0000);
+
+    pub const fn empty() -> Self {
+        Self(0)
+    }
+
+    pub const fn from_bits(bits: u32) -> Self {
+        Self(bits)
+    }
+
+    pub const fn bits(self) -> u32 {
+        self.0
+    }
+
+    /// 是否包含 `other` 中的所有属性
+    pub const fn contains(self, other: Self) -> bool {
+        self.0 & other.0 == other.0
+    }
+
+    /// 是否包含 `other` 中的任意一个属性
+    pub const fn intersects(self, other: Self) -> bool {
+        self.0 & other.0 != 0
+    }
+
+    /// 与 `|` 相同，可以用在常量中
+    pub const fn union(self, other: Self) -> Self {
+        Self(self.0 | other.0)
+    }
+
+    pub const fn is_dir(self) -> bool {
+        self.contains(Self::DIRECTORY)
+    }
+
+    /// 是否带有隐藏或系统属性，Everything 默认不显示这类文件
+    pub const fn is_hidden(self) -> bool {
+        self.intersects(Self::HIDDEN.union(Self::SYSTEM))
+    }
+}
+
+impl Debug for FileAttributes {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "FileAttributes({:#x})", self.0)
+    }
+}
+
+impl From<u32> for FileAttributes {
+    fn from(bits: u32) -> Self {
+        Self(bits)
+    }
+}
+
+impl From<FileAttributes> for u32 {
+    fn from(attributes: FileAttributes) -> Self {
+        attributes.0
+    }
+}
+
+impl BitOr for FileAttributes {
+    type Output = Self;
+
+    fn bitor(self, rhs: Self) -> Self::Output {
+        Self(self.0 | rhs.0)
+    }
+}
+
+impl BitOrAssign for FileAttributes {
+    fn bitor_assign(&mut self, rhs: Self) {
+        self.0 |= rhs.0;
+    }
+}
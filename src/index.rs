@@ -1,86 +1,308 @@
+mod arena;
+mod dirs;
 mod find;
+mod fold;
+mod glob;
+mod parallel;
+mod pinyin;
+mod query;
+mod session;
+mod snapshot;
+mod trigram;
 
 use anyhow::Result;
-use log::debug;
-use std::collections::HashMap;
-use windows::Win32::System::Ioctl::{
-    USN_REASON_CLOSE, USN_REASON_FILE_CREATE, USN_REASON_FILE_DELETE, USN_REASON_RENAME_NEW_NAME,
+use log::{debug, trace};
+use std::{
+    collections::HashSet,
+    sync::{Arc, Mutex},
 };
 
-use crate::ntfs::{UsnRecord, Volume};
-pub use find::{FindIter, FullPath};
+use crate::{
+    attributes::FileAttributes,
+    reason::UsnReason,
+    record::UsnRecord,
+    source::{Changes, Cursor, IndexStale, RecordSource},
+};
+use arena::Arena;
+pub use arena::MemoryUsage;
+use dirs::DirPaths;
+pub use find::{FindIter, FullPath, Kind};
+pub use parallel::ParOptions;
+pub use query::{Expr, ParseError, ParseErrorKind, Query};
+pub use session::Session;
+
+type V = (u128, Box<str>);
+
+/// 文件的元数据，取自最近一条关于它的记录
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct FileInfo {
+    pub usn: i64,
+    /// FILETIME，没有时为 0
+    pub timestamp: i64,
+    pub attributes: FileAttributes,
+    pub source_info: u32,
+    pub security_id: u32,
+}
 
-type V = (u64, Box<str>);
+impl From<&UsnRecord> for FileInfo {
+    fn from(record: &UsnRecord) -> Self {
+        Self {
+            usn: record.usn,
+            timestamp: record.timestamp,
+            attributes: record.attributes,
+            source_info: record.source_info,
+            security_id: record.security_id,
+        }
+    }
+}
 
 pub struct Index {
     driver: String,
-    map: HashMap<u64, V>,
-    usn: i64,
+    arena: Arena,
+    /// 已经移除旧名称、等待新名称的硬链接文件
+    renaming: HashSet<u128>,
+    cursor: Cursor,
+    /// 所有目录的完整路径，第一次匹配完整路径时拼接
+    dir_paths: Mutex<Option<Arc<DirPaths>>>,
 }
 
 impl Index {
-    pub fn with_capacity(driver: String, usn: i64, capacity: usize) -> Self {
+    pub fn with_capacity(driver: String, cursor: Cursor, capacity: usize) -> Self {
         Self {
             driver,
-            map: HashMap::with_capacity(capacity),
-            usn,
+            arena: Arena::with_capacity(capacity),
+            renaming: HashSet::new(),
+            cursor,
+            dir_paths: Mutex::new(None),
         }
     }
 
-    pub fn try_from_volume(vol: &Volume) -> Result<Self> {
-        let usn = vol.usn_journal_data()?.next_usn;
-        let mut index = Self::with_capacity(vol.driver().to_string(), usn, 10_0000);
+    pub fn try_from_source<S: RecordSource>(src: &S) -> Result<Self> {
+        // 先记下变更流的位置，枚举期间发生的变更会在之后的同步中补上
+        let cursor = src.cursor()?;
+        let mut index = Self::with_capacity(src.driver().to_string(), cursor, 10_0000);
         let mut count: u64 = 0;
-        for record in vol.file_records::<{ 64 * 1024 }>() {
-            index.insert(record?);
+        for record in src.records() {
+            // FSCTL_ENUM_USN_DATA 每个文件只给出一个名称，其余硬链接要等变更记录补上；
+            // 镜像和 Linux 的遍历中硬链接的每个名称各有一条记录
+            index.add_link(record?);
             count += 1;
         }
-        debug!("{} 盘文件记录 {count} 条", vol.driver());
+        index.shrink_to_fit();
+        debug!(
+            "{} 盘文件记录 {count} 条，占用 {}",
+            src.driver(),
+            index.memory_usage()
+        );
         Ok(index)
     }
 
+    /// 将记录中的名称设为文件唯一的名称，返回原来的主名称。
     pub fn insert(&mut self, record: UsnRecord) -> Option<V> {
-        self.map
-            .insert(record.frn, (record.parent_frn, record.filename.into()))
+        let info = FileInfo::from(&record);
+        self.arena
+            .insert(record.frn, record.parent_frn, &record.filename, info)
+    }
+
+    /// 为文件添加一个名称，名称已存在时返回 `false`。文件的元数据总会被更新。
+    pub fn add_link(&mut self, record: UsnRecord) -> bool {
+        let info = FileInfo::from(&record);
+        self.arena
+            .add_link(record.frn, record.parent_frn, &record.filename, info)
+    }
+
+    /// 删除文件的一个名称，最后一个名称被删除时文件也随之删除。名称不存在时返回 `false`。
+    pub fn remove_link(&mut self, frn: u128, parent_frn: u128, filename: &str) -> bool {
+        self.arena.remove_link(frn, parent_frn, filename)
+    }
+
+    /// 删除文件的所有名称。
+    pub fn remove(&mut self, frn: u128) -> Option<V> {
+        self.arena.remove(frn)
+    }
+
+    /// 删除文件的所有名称；是目录时目录中剩下的内容也一并删除。
+    ///
+    /// NTFS 上只能删除空目录，目录被移出监听范围时（如 inotify）才会剩下内容。
+    fn remove_tree(&mut self, frn: u128) {
+        for (frn, parent_frn, name) in self.arena.descendants(frn).into_iter().rev() {
+            self.arena.remove_link(frn, parent_frn, &name);
+        }
+        self.remove(frn);
+    }
+
+    /// 文件的所有名称 `(父目录, 名称)`，第一个是主名称。
+    pub fn names(&self, frn: u128) -> impl Iterator<Item = (u128, &str)> {
+        self.arena.names(frn)
+    }
+
+    /// 文件的元数据。
+    pub fn info(&self, frn: u128) -> Option<&FileInfo> {
+        self.arena.info(frn)
+    }
+
+    /// 文件数，硬链接只算一个。
+    pub fn len(&self) -> usize {
+        self.arena.len().0
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// 索引占用的内存。
+    pub fn memory_usage(&self) -> MemoryUsage {
+        self.arena.memory_usage()
+    }
+
+    /// 整理名称区并释放多余的容量，适合在建立索引后调用。
+    pub fn shrink_to_fit(&mut self) {
+        self.arena.compact();
+    }
+
+    /// 建立或丢弃名称的三字节组倒排表。
+    ///
+    /// 建立后查找只检查包含关键词所有三字节组的名称，短于三个字节的关键词仍然扫描；
+    /// 倒排表随名称的增删更新，但会占用更多内存，也不会保存到快照中。
+    pub fn set_trigrams(&mut self, enabled: bool) {
+        self.arena.set_trigrams(enabled);
+    }
+
+    pub fn has_trigrams(&self) -> bool {
+        self.arena.has_trigrams()
     }
 
     pub fn driver(&self) -> &str {
         &self.driver
     }
 
+    pub fn cursor(&self) -> Cursor {
+        self.cursor
+    }
+
+    /// 按 [`Query`] 的语法查找，如 `invoice 2024`、`(jpg|png) -thumbnail`、`*.rs`、`path:src main`、`pinyin:bgs`、`regex:^v\d+`。
+    ///
+    /// 语法错误时把整个输入作为一个关键词，需要报告错误时先用 [`Query::parse`] 解析。
     pub fn find_iter<'a>(&'a self, sub: &'a str) -> FindIter<'a> {
         FindIter::new(self, sub)
     }
 
-    pub fn sync(&mut self, vol: &Volume) -> Result<()> {
-        let id = vol.usn_journal_data()?.id;
-        let mut usn_records = vol.usn_records_from::<4096>(id, self.usn);
-        for res in &mut usn_records {
-            let record = res?;
-            // 只匹配文件关闭时的事件
-            match record.reason ^ USN_REASON_CLOSE {
-                USN_REASON_FILE_CREATE => {
-                    debug!("Index({:?})：创建 {:?}", self.driver(), record.filename);
-                    self.insert(record);
-                }
-                USN_REASON_FILE_DELETE => {
-                    self.map.remove(&record.frn);
-                    debug!("Index({:?})：删除 {:?}", self.driver(), record.filename);
+    pub fn find_query<'a>(&'a self, query: &Query) -> FindIter<'a> {
+        FindIter::query(self, query)
+    }
+
+    /// 从上次的位置同步变更。
+    ///
+    /// 中间的变更已经丢失时返回 [`IndexStale`]，此时索引保持不变。
+    pub fn sync<S: RecordSource>(&mut self, src: &S) -> Result<()> {
+        src.range()?.check(self.cursor)?;
+        let mut changes = src.changes_from(self.cursor);
+        for res in &mut changes {
+            self.apply(res?);
+        }
+        self.cursor = changes.cursor();
+        Ok(())
+    }
+
+    /// 同步变更，索引已过期时重新建立，重建的索引保留原来是否建立倒排表的设置。
+    pub fn sync_or_rebuild<S: RecordSource>(&mut self, src: &S) -> Result<()> {
+        match self.sync(src) {
+            Err(e) if e.is::<IndexStale>() => {
+                debug!("Index({:?})：{e}，重新建立", self.driver());
+                let trigrams = self.has_trigrams();
+                *self = Self::try_from_source(src)?;
+                self.set_trigrams(trigrams);
+                Ok(())
+            }
+            res => res,
+        }
+    }
+
+    /// 将一条变更记录应用到索引上，可用于重放来自其他途径的记录（如导出的 $J）。
+    ///
+    /// 关闭时的记录带有这次打开期间累积的所有原因，所以按原因的组合处理，
+    /// 记录中的名称总是文件当前的名称。FRN 为 [`UsnRecord::UNKNOWN_FRN`] 时
+    /// 按父目录和名称找到文件，找不到时忽略这条记录。
+    pub fn apply(&mut self, mut record: UsnRecord) {
+        if record.frn == UsnRecord::UNKNOWN_FRN {
+            match self.arena.child(record.parent_frn, &record.filename) {
+                Some(frn) => record.frn = frn,
+                None => {
+                    trace!(
+                        "Index({:?})：{:?} 不在索引中",
+                        self.driver(),
+                        record.filename
+                    );
+                    return;
                 }
-                USN_REASON_RENAME_NEW_NAME => {
-                    let frn = record.frn;
-                    if let Some((_, old)) = self.insert(record) {
-                        debug!(
-                            "Index({:?})：重命名 {old:?} => {:?}",
-                            self.driver(),
-                            self.map[&frn].1
-                        );
-                    }
+            }
+        }
+        let reason = record.reason;
+        let has = |flag| reason.intersects(flag);
+        let frn = record.frn;
+        trace!("Index({:?})：{reason} {:?}", self.driver(), record.filename);
+
+        if !has(UsnReason::CLOSE) {
+            // 中间记录中只有重命名前的旧名称有用。文件只有一个名称时，
+            // 关闭时的记录会直接替换它，不需要提前删除
+            if has(UsnReason::RENAME_OLD_NAME)
+                && !has(UsnReason::RENAME_NEW_NAME)
+                && self.arena.has_links(frn)
+                && self.remove_link(frn, record.parent_frn, &record.filename)
+            {
+                debug!(
+                    "Index({:?})：移除旧名称 {:?}",
+                    self.driver(),
+                    record.filename
+                );
+                self.renaming.insert(frn);
+            }
+            return;
+        }
+
+        let renaming = self.renaming.remove(&frn);
+        let info = FileInfo::from(&record);
+        if has(UsnReason::FILE_DELETE) {
+            debug!("Index({:?})：删除 {:?}", self.driver(), record.filename);
+            // 还有其他名称时只删除这一个，否则整个文件都被删除了
+            if !(self.arena.has_links(frn)
+                && self.remove_link(frn, record.parent_frn, &record.filename))
+            {
+                self.remove_tree(frn);
+            }
+        } else if has(UsnReason::FILE_CREATE) {
+            debug!("Index({:?})：创建 {:?}", self.driver(), record.filename);
+            self.add_link(record);
+        } else if has(UsnReason::RENAME_NEW_NAME) {
+            if renaming || self.arena.has_links(frn) {
+                // 旧名称由之前的 RENAME_OLD_NAME 记录移除
+                debug!("Index({:?})：重命名为 {:?}", self.driver(), record.filename);
+                self.add_link(record);
+            } else {
+                let new = record.filename.clone();
+                if let Some((_, old)) = self.insert(record) {
+                    debug!("Index({:?})：重命名 {old:?} => {new:?}", self.driver());
                 }
-                _ => {}
+            }
+        } else if has(UsnReason::HARD_LINK_CHANGE) {
+            // 记录中不区分添加还是删除，只能看名称是否已经存在
+            if self.remove_link(frn, record.parent_frn, &record.filename) {
+                debug!(
+                    "Index({:?})：删除硬链接 {:?}",
+                    self.driver(),
+                    record.filename
+                );
+            } else {
+                debug!(
+                    "Index({:?})：添加硬链接 {:?}",
+                    self.driver(),
+                    record.filename
+                );
+                self.add_link(record);
             }
         }
-        self.usn = usn_records.next_usn();
-        Ok(())
+
+        // 只涉及数据或属性的记录也会更新时间等元数据
+        self.arena.set_info(frn, info);
     }
 }
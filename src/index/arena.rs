@@ -0,0 +1,865 @@
+use memchr::memmem::Finder;
+use std::{
+    collections::{hash_map::DefaultHasher, HashMap, HashSet},
+    fmt::Display,
+    hash::{Hash, Hasher},
+    mem::size_of,
+    ops::Range,
+    sync::atomic::{AtomicU64, Ordering},
+};
+
+use super::{fold::fold_into, trigram::Trigrams, FileInfo};
+
+/// 槽位的编号，卷上的文件数远小于 2^32
+pub(super) type SlotId = u32;
+
+/// 没有父目录的槽位：尚未出现的目录或已被删除的文件
+const VACANT: SlotId = SlotId::MAX;
+
+/// `u128` 要求 16 字节对齐，拆成两个 `u64` 后映射中的每一项可以少占 8 字节
+type Key = [u64; 2];
+
+fn key(frn: u128) -> Key {
+    [frn as u64, (frn >> 64) as u64]
+}
+
+/// 名称区中的一段
+#[derive(Debug, Clone, Copy)]
+pub(super) struct NameRef {
+    start: u32,
+    len: u32,
+}
+
+/// 每个文件一个槽位，保存主名称和元数据。
+///
+/// 还没有出现的父目录也会占一个空槽位，子项可以先于父目录加入。
+struct Slot {
+    frn: u128,
+    info: FileInfo,
+    /// 父目录的槽位，空槽位为 [`VACANT`]
+    parent: SlotId,
+    name: NameRef,
+    /// 以此为父目录的名称数，不为 0 的槽位不会被回收
+    children: u32,
+}
+
+/// 硬链接的其余名称
+#[derive(Debug, Clone, Copy)]
+struct Link {
+    parent: SlotId,
+    name: NameRef,
+}
+
+/// 折叠名称区中的一个名称
+#[derive(Debug, Clone, Copy)]
+struct Cell {
+    /// 在折叠名称区中的起点
+    start: u32,
+    slot: SlotId,
+    /// 对应的原名称，与槽位中的名称都不相同时说明已被删除
+    name: NameRef,
+}
+
+/// 按父目录和名称查找名称的映射。
+///
+/// 以 `(父目录, 名称)` 的散列为键，散列相同时先加入的名称移到 `collisions` 中；
+/// 名称被释放时立即移除，所以其中只有现存的名称。
+#[derive(Default)]
+struct Lookup {
+    cells: HashMap<u64, u32>,
+    collisions: Vec<u32>,
+}
+
+fn lookup_key(parent: SlotId, name: &str) -> u64 {
+    let mut hasher = DefaultHasher::new();
+    (parent, name).hash(&mut hasher);
+    hasher.finish()
+}
+
+/// 紧凑的索引存储：所有名称连续存放在一个字符串中，文件按槽位编号存放在一张表中，
+/// 父目录也用槽位编号表示，另有一个 FRN 到槽位的映射。
+///
+/// 每个名称折叠为小写后另存一份，以 `\0` 分隔连续存放，查找时直接在上面扫描。
+/// 删除和重命名留下的旧名称在积累到一定程度后统一整理。
+pub(super) struct Arena {
+    slots: Vec<Slot>,
+    names: String,
+    /// 折叠名称区
+    folded: Vec<u8>,
+    /// 折叠名称区中每个名称的位置和所属，按起点递增
+    cells: Vec<Cell>,
+    /// 折叠名称的倒排表，默认不建立
+    trigrams: Option<Trigrams>,
+    epoch: u64,
+    /// 目录路径的版本
+    dirs_epoch: u64,
+    /// 名称区中不再使用的字节数
+    garbage: usize,
+    /// 可以复用的空槽位
+    free: Vec<SlotId>,
+    map: HashMap<Key, SlotId>,
+    links: HashMap<SlotId, Vec<Link>>,
+    /// 按父目录和名称的查找，第一次用到时建立
+    lookup: Option<Lookup>,
+    /// 文件数，即主名称不是空槽位的槽位数
+    file_count: usize,
+    /// 名称数，包括硬链接的其余名称
+    name_count: usize,
+}
+
+impl Arena {
+    /// 积累的旧名称超过这个大小并且超过名称区的一半时整理
+    const COMPACT_THRESHOLD: usize = 1 << 20;
+
+    pub fn with_capacity(capacity: usize) -> Self {
+        Self {
+            slots: Vec::with_capacity(capacity),
+            names: String::with_capacity(capacity * 16),
+            folded: Vec::with_capacity(capacity * 17),
+            cells: Vec::with_capacity(capacity),
+            trigrams: None,
+            epoch: next_epoch(),
+            dirs_epoch: 0,
+            garbage: 0,
+            free: Vec::new(),
+            map: HashMap::with_capacity(capacity),
+            links: HashMap::new(),
+            lookup: None,
+            file_count: 0,
+            name_count: 0,
+        }
+    }
+
+    /// 文件的槽位，空槽位视为不存在
+    fn get(&self, frn: u128) -> Option<SlotId> {
+        self.map
+            .get(&key(frn))
+            .copied()
+            .filter(|&id| self.slots[id as usize].parent != VACANT)
+    }
+
+    /// 文件的槽位，不存在时分配一个空槽位
+    fn slot(&mut self, frn: u128) -> SlotId {
+        if let Some(&id) = self.map.get(&key(frn)) {
+            return id;
+        }
+        let slot = Slot {
+            frn,
+            info: FileInfo::default(),
+            parent: VACANT,
+            name: NameRef { start: 0, len: 0 },
+            children: 0,
+        };
+        let id = match self.free.pop() {
+            Some(id) => {
+                self.slots[id as usize] = slot;
+                id
+            }
+            None => {
+                let id = SlotId::try_from(self.slots.len()).expect("文件数超出了槽位编号的范围");
+                self.slots.push(slot);
+                id
+            }
+        };
+        self.map.insert(key(frn), id);
+        id
+    }
+
+    pub fn name(&self, name: NameRef) -> &str {
+        slice_name(&self.names, name)
+    }
+
+    /// 追加槽位 `id` 的一个名称，同时追加折叠后的名称
+    fn push_name(&mut self, id: SlotId, name: &str) -> NameRef {
+        let name = push_name(&mut self.names, name);
+        push_folded(
+            &mut self.folded,
+            &mut self.cells,
+            id,
+            name,
+            slice_name(&self.names, name),
+        );
+        if let Some(trigrams) = &mut self.trigrams {
+            let cell = self.cells.len() - 1;
+            trigrams.add(cell as u32, cell_bytes(&self.folded, &self.cells, cell));
+        }
+        name
+    }
+
+    /// 让文件 `id` 在 `parent` 下多一个名称
+    fn new_name(&mut self, id: SlotId, parent_frn: u128, name: &str) -> Link {
+        let parent = self.slot(parent_frn);
+        self.slots[parent as usize].children += 1;
+        self.name_count += 1;
+        let link = Link {
+            parent,
+            name: self.push_name(id, name),
+        };
+        if let Some(mut lookup) = self.lookup.take() {
+            let cell = self.cells.len() as u32 - 1;
+            if let Some(old) = lookup.cells.insert(lookup_key(parent, name), cell) {
+                if self.hit(old, 0).is_some() {
+                    lookup.collisions.push(old);
+                }
+            }
+            self.lookup = Some(lookup);
+        }
+        link
+    }
+
+    /// 释放一个名称，父目录是空槽位并且不再有子项时一并回收
+    fn release(&mut self, link: Link) {
+        if let Some(lookup) = &mut self.lookup {
+            // 名称和折叠名称按相同的顺序追加，起点相同时空名称在前
+            let cell = self.cells.partition_point(|cell| {
+                (cell.name.start, cell.name.len) < (link.name.start, link.name.len)
+            }) as u32;
+            let key = lookup_key(link.parent, slice_name(&self.names, link.name));
+            if lookup.cells.get(&key) == Some(&cell) {
+                lookup.cells.remove(&key);
+            } else if let Some(pos) = lookup.collisions.iter().position(|&c| c == cell) {
+                lookup.collisions.swap_remove(pos);
+            }
+        }
+        self.garbage += link.name.len as usize;
+        self.slots[link.parent as usize].children -= 1;
+        self.name_count -= 1;
+        self.collect(link.parent);
+    }
+
+    /// 回收空槽位，已经回收过的不会重复加入 `free`
+    fn collect(&mut self, id: SlotId) {
+        let slot = &self.slots[id as usize];
+        if slot.parent == VACANT && slot.children == 0 && self.map.get(&key(slot.frn)) == Some(&id)
+        {
+            self.map.remove(&key(slot.frn));
+            self.free.push(id);
+        }
+    }
+
+    fn primary(&self, id: SlotId) -> Link {
+        let slot = &self.slots[id as usize];
+        Link {
+            parent: slot.parent,
+            name: slot.name,
+        }
+    }
+
+    fn set_primary(&mut self, id: SlotId, link: Link) {
+        let slot = &mut self.slots[id as usize];
+        // 目录的位置变了，其中所有文件的路径都随之改变
+        if slot.children > 0 || slot.info.attributes.is_dir() {
+            self.dirs_epoch += 1;
+        }
+        match (slot.parent == VACANT, link.parent == VACANT) {
+            (true, false) => self.file_count += 1,
+            (false, true) => self.file_count -= 1,
+            _ => {}
+        }
+        slot.parent = link.parent;
+        slot.name = link.name;
+    }
+
+    fn is(&self, link: Link, parent_frn: u128, name: &str) -> bool {
+        self.slots[link.parent as usize].frn == parent_frn && self.name(link.name) == name
+    }
+
+    fn named(&self, id: SlotId, link: Link) -> (u128, u128, Box<str>) {
+        let (parent_frn, name) = self.owned(link);
+        (self.slots[id as usize].frn, parent_frn, name)
+    }
+
+    fn owned(&self, link: Link) -> (u128, Box<str>) {
+        (
+            self.slots[link.parent as usize].frn,
+            self.name(link.name).into(),
+        )
+    }
+
+    /// 将名称设为文件唯一的名称，返回原来的主名称。
+    pub fn insert(
+        &mut self,
+        frn: u128,
+        parent_frn: u128,
+        name: &str,
+        info: FileInfo,
+    ) -> Option<(u128, Box<str>)> {
+        let id = self.slot(frn);
+        // 先加入新名称，父目录不变时不会被回收
+        let link = self.new_name(id, parent_frn, name);
+        for old in self.links.remove(&id).into_iter().flatten() {
+            self.release(old);
+        }
+        let old = match self.slots[id as usize].parent {
+            VACANT => None,
+            _ => {
+                let old = self.primary(id);
+                let res = self.owned(old);
+                self.release(old);
+                Some(res)
+            }
+        };
+        self.set_primary(id, link);
+        self.slots[id as usize].info = info;
+        self.maybe_compact();
+        old
+    }
+
+    /// 为文件添加一个名称，名称已存在时返回 `false`。
+    pub fn add_link(&mut self, frn: u128, parent_frn: u128, name: &str, info: FileInfo) -> bool {
+        let Some(id) = self.get(frn) else {
+            self.insert(frn, parent_frn, name, info);
+            return true;
+        };
+        self.slots[id as usize].info = info;
+        let exists = |link: &Link| self.is(*link, parent_frn, name);
+        if exists(&self.primary(id)) || self.links.get(&id).is_some_and(|l| l.iter().any(exists)) {
+            return false;
+        }
+        let link = self.new_name(id, parent_frn, name);
+        self.links.entry(id).or_default().push(link);
+        true
+    }
+
+    /// 删除文件的一个名称，最后一个名称被删除时文件也随之删除。
+    pub fn remove_link(&mut self, frn: u128, parent_frn: u128, name: &str) -> bool {
+        let Some(id) = self.get(frn) else {
+            return false;
+        };
+        let primary = self.primary(id);
+        if self.is(primary, parent_frn, name) {
+            // 由其余名称中的一个顶替
+            match self.links.get_mut(&id).and_then(|links| links.pop()) {
+                Some(link) => self.set_primary(id, link),
+                None => self.set_primary(
+                    id,
+                    Link {
+                        parent: VACANT,
+                        ..primary
+                    },
+                ),
+            }
+            self.release(primary);
+        } else {
+            let Some(links) = self.links.get_mut(&id) else {
+                return false;
+            };
+            let (slots, names) = (&self.slots, &self.names);
+            let Some(pos) = links.iter().position(|link| {
+                slots[link.parent as usize].frn == parent_frn
+                    && slice_name(names, link.name) == name
+            }) else {
+                return false;
+            };
+            let link = links.swap_remove(pos);
+            self.release(link);
+        }
+        if self.links.get(&id).is_some_and(|links| links.is_empty()) {
+            self.links.remove(&id);
+        }
+        self.collect(id);
+        self.maybe_compact();
+        true
+    }
+
+    /// 删除文件的所有名称，返回原来的主名称。
+    pub fn remove(&mut self, frn: u128) -> Option<(u128, Box<str>)> {
+        let id = self.get(frn)?;
+        for link in self.links.remove(&id).into_iter().flatten() {
+            self.release(link);
+        }
+        let primary = self.primary(id);
+        let res = self.owned(primary);
+        self.set_primary(
+            id,
+            Link {
+                parent: VACANT,
+                ..primary
+            },
+        );
+        self.release(primary);
+        // 还有子项时保留为空槽位
+        self.collect(id);
+        self.maybe_compact();
+        Some(res)
+    }
+
+    /// 父目录中名为 `name` 的文件。
+    ///
+    /// 第一次调用时建立按父目录和名称的映射，之后随名称的增删更新。
+    pub fn child(&mut self, parent_frn: u128, name: &str) -> Option<u128> {
+        let parent = self.get(parent_frn)?;
+        if self.slots[parent as usize].children == 0 {
+            return None;
+        }
+        if self.lookup.is_none() {
+            self.lookup = Some(self.build_lookup());
+        }
+        let lookup = self.lookup.as_ref()?;
+        let is = |cell: u32| {
+            self.hit(cell, 0)
+                .is_some_and(|hit| hit.parent == parent && hit.name == name)
+        };
+        let cell = lookup
+            .cells
+            .get(&lookup_key(parent, name))
+            .copied()
+            .filter(|&cell| is(cell))
+            .or_else(|| lookup.collisions.iter().copied().find(|&cell| is(cell)))?;
+        Some(self.slots[self.cells[cell as usize].slot as usize].frn)
+    }
+
+    fn build_lookup(&self) -> Lookup {
+        let mut lookup = Lookup::default();
+        for cell in 0..self.cells.len() as u32 {
+            let Some(hit) = self.hit(cell, 0) else {
+                continue;
+            };
+            if let Some(old) = lookup.cells.insert(lookup_key(hit.parent, hit.name), cell) {
+                lookup.collisions.push(old);
+            }
+        }
+        lookup
+    }
+
+    /// 目录中的所有名称 `(FRN, 父目录 FRN, 名称)`，包括子目录中的，父目录在子项之前。
+    ///
+    /// 每一层都要遍历所有槽位，只用于目录被整个移走的少见情况。
+    pub fn descendants(&self, frn: u128) -> Vec<(u128, u128, Box<str>)> {
+        let Some(&root) = self.map.get(&key(frn)) else {
+            return Vec::new();
+        };
+        if self.slots[root as usize].children == 0 {
+            return Vec::new();
+        }
+        let mut res = Vec::new();
+        let mut seen = HashSet::from([root]);
+        let mut level = vec![root];
+        while !level.is_empty() {
+            let dirs: HashSet<_> = level.drain(..).collect();
+            for (id, slot) in self.slots.iter().enumerate() {
+                let id = id as SlotId;
+                if slot.parent == VACANT {
+                    continue;
+                }
+                let links = self.links.get(&id).into_iter().flatten();
+                for link in [self.primary(id)].into_iter().chain(links.copied()) {
+                    if !dirs.contains(&link.parent) {
+                        continue;
+                    }
+                    res.push(self.named(id, link));
+                    if slot.children > 0 && seen.insert(id) {
+                        level.push(id);
+                    }
+                }
+            }
+        }
+        res
+    }
+
+    pub fn has_links(&self, frn: u128) -> bool {
+        self.get(frn).is_some_and(|id| self.links.contains_key(&id))
+    }
+
+    /// 文件的所有名称 `(父目录 FRN, 名称)`，第一个是主名称。
+    pub fn names(&self, frn: u128) -> impl Iterator<Item = (u128, &str)> {
+        let id = self.get(frn);
+        let primary = id.map(|id| self.primary(id));
+        let links = id.and_then(|id| self.links.get(&id)).into_iter().flatten();
+        primary
+            .into_iter()
+            .chain(links.copied())
+            .map(|link| (self.slots[link.parent as usize].frn, self.name(link.name)))
+    }
+
+    pub fn info(&self, frn: u128) -> Option<&FileInfo> {
+        self.get(frn).map(|id| &self.slots[id as usize].info)
+    }
+
+    pub fn set_info(&mut self, frn: u128, info: FileInfo) {
+        if let Some(id) = self.get(frn) {
+            self.slots[id as usize].info = info;
+        }
+    }
+
+    /// 不是空槽位的文件的主名称
+    pub fn entry(&self, id: SlotId) -> Option<(SlotId, &str)> {
+        let slot = self.slots.get(id as usize)?;
+        (slot.parent != VACANT).then(|| (slot.parent, self.name(slot.name)))
+    }
+
+    /// 从 `id` 开始逐级向上的 `(槽位, 主名称)`，到空槽位为止。
+    ///
+    /// 自己是自己的父目录等异常的记录会让目录成环，最多向上走槽位数那么多层。
+    pub fn ancestors(&self, id: SlotId) -> impl Iterator<Item = (SlotId, &str)> {
+        let mut next = id;
+        std::iter::from_fn(move || {
+            let id = next;
+            let (parent, name) = self.entry(id)?;
+            next = parent;
+            Some((id, name))
+        })
+        .take(self.slots.len())
+    }
+
+    /// 在折叠名称区的 `pos..end` 中查找，每个名称最多匹配一次。
+    ///
+    /// `end` 需要是名称的边界，返回匹配的名称和下一次查找的起点。
+    pub fn find(&self, finder: &Finder, mut pos: usize, end: usize) -> Option<(Hit<'_>, usize)> {
+        while pos < end {
+            let at = pos + finder.find(&self.folded[pos..end])?;
+            let i = self.cells.partition_point(|cell| cell.start as usize <= at) - 1;
+            let cell = self.cells[i];
+            // 从下一个名称开始继续查找
+            pos = self
+                .cells
+                .get(i + 1)
+                .map_or(self.folded.len(), |next| next.start as usize);
+            if let Some(hit) = self.hit(i as u32, at - cell.start as usize) {
+                return Some((hit, pos));
+            }
+        }
+        None
+    }
+
+    /// 折叠名称区的 `range` 中名称的序号，两端需要是名称的边界
+    pub fn cell_range(&self, range: Range<usize>) -> Range<usize> {
+        let index = |pos| {
+            self.cells
+                .partition_point(|cell| (cell.start as usize) < pos)
+        };
+        index(range.start)..index(range.end)
+    }
+
+    /// 检查序号为 `cell` 的名称是否匹配
+    pub fn find_cell(&self, finder: &Finder, cell: u32) -> Option<Hit<'_>> {
+        let offset = finder.find(cell_bytes(&self.folded, &self.cells, cell as usize))?;
+        self.hit(cell, offset)
+    }
+
+    /// 序号不小于 `from` 的名称中所有匹配的序号，有倒排表时只检查候选名称
+    pub fn find_cells(&self, finder: &Finder, from: u32) -> Vec<u32> {
+        if let Some(mut cells) = self.candidates(finder.needle()) {
+            cells.retain(|&cell| cell >= from && self.find_cell(finder, cell).is_some());
+            return cells;
+        }
+        let mut pos = self
+            .cells
+            .get(from as usize)
+            .map_or(self.folded.len(), |cell| cell.start as usize);
+        let mut res = Vec::new();
+        while let Some((hit, next)) = self.find(finder, pos, self.folded.len()) {
+            res.push(hit.cell);
+            pos = next;
+        }
+        res
+    }
+
+    /// 序号为 `cell` 的折叠名称
+    pub fn folded_name(&self, cell: u32) -> &[u8] {
+        cell_bytes(&self.folded, &self.cells, cell as usize)
+    }
+
+    /// 名称的个数，包括已被删除但还没有整理的
+    pub fn cells_len(&self) -> u32 {
+        self.cells.len() as u32
+    }
+
+    /// 名称序号的版本，整理后序号会改变，版本也随之改变。不同的存储之间也不会相同。
+    pub fn epoch(&self) -> u64 {
+        self.epoch
+    }
+
+    /// 目录路径的版本，目录被重命名、移动或删除时改变。
+    ///
+    /// 新建目录不会改变版本，它的槽位不在之前的路径中；槽位被复用时 FRN 会不同。
+    pub fn dirs_epoch(&self) -> u64 {
+        self.dirs_epoch
+    }
+
+    /// 槽位中文件的 FRN
+    pub fn frn(&self, id: SlotId) -> u128 {
+        self.slots[id as usize].frn
+    }
+
+    /// 有子项的槽位，即所有作为父目录的槽位
+    pub fn dirs(&self) -> impl Iterator<Item = SlotId> + '_ {
+        (0..self.slots.len() as SlotId).filter(|&id| self.slots[id as usize].children > 0)
+    }
+
+    fn hit(&self, i: u32, offset: usize) -> Option<Hit<'_>> {
+        let cell = self.cells[i as usize];
+        let (parent, info) = self.owner(cell)?;
+        Some(Hit {
+            cell: i,
+            parent,
+            name: self.name(cell.name),
+            info,
+            offset,
+        })
+    }
+
+    /// 建立或丢弃倒排表
+    pub fn set_trigrams(&mut self, enabled: bool) {
+        self.trigrams = enabled.then(|| build_trigrams(&self.folded, &self.cells));
+    }
+
+    pub fn has_trigrams(&self) -> bool {
+        self.trigrams.is_some()
+    }
+
+    /// 可能包含 `needle` 的名称的序号；没有倒排表或者关键词太短时返回 `None`
+    pub fn candidates(&self, needle: &[u8]) -> Option<Vec<u32>> {
+        let trigrams = self.trigrams.as_ref()?;
+        (needle.len() >= Trigrams::MIN_LEN).then(|| trigrams.candidates(needle))
+    }
+
+    /// 折叠名称区的长度
+    pub fn folded_len(&self) -> usize {
+        self.folded.len()
+    }
+
+    /// 将折叠名称区按名称的边界分成至多 `n` 段，每段不小于 `min` 字节
+    pub fn split(&self, n: usize, min: usize) -> Vec<Range<usize>> {
+        let len = self.folded.len();
+        let n = n.min(len / min.max(1)).max(1);
+        let mut res = Vec::with_capacity(n);
+        let mut start = 0;
+        for i in 1..=n {
+            let end = if i == n {
+                len
+            } else {
+                let target = len / n * i;
+                let j = self
+                    .cells
+                    .partition_point(|cell| (cell.start as usize) < target);
+                self.cells.get(j).map_or(len, |cell| cell.start as usize)
+            };
+            if end > start {
+                res.push(start..end);
+                start = end;
+            }
+        }
+        res
+    }
+
+    /// 折叠名称所属的名称仍然存在时，返回它的父目录槽位和元数据
+    fn owner(&self, cell: Cell) -> Option<(SlotId, &FileInfo)> {
+        let slot = &self.slots[cell.slot as usize];
+        if slot.parent == VACANT {
+            return None;
+        }
+        let same = |name: NameRef| name.start == cell.name.start && name.len == cell.name.len;
+        let parent = if same(slot.name) {
+            slot.parent
+        } else {
+            self.links
+                .get(&cell.slot)?
+                .iter()
+                .find(|link| same(link.name))?
+                .parent
+        };
+        Some((parent, &slot.info))
+    }
+
+    /// 所有名称 `(FRN, 父目录 FRN, 名称, 元数据)`，同一文件的名称相邻，主名称在前
+    pub fn entries(&self) -> impl Iterator<Item = (u128, u128, &str, &FileInfo)> {
+        self.slots
+            .iter()
+            .enumerate()
+            .filter(|(_, slot)| slot.parent != VACANT)
+            .flat_map(move |(id, slot)| {
+                let primary = Link {
+                    parent: slot.parent,
+                    name: slot.name,
+                };
+                let links = self.links.get(&(id as SlotId)).into_iter().flatten();
+                [primary]
+                    .into_iter()
+                    .chain(links.copied())
+                    .map(move |link| {
+                        let parent_frn = self.slots[link.parent as usize].frn;
+                        (slot.frn, parent_frn, self.name(link.name), &slot.info)
+                    })
+            })
+    }
+
+    /// 文件数和名称数
+    pub fn len(&self) -> (usize, usize) {
+        (self.file_count, self.name_count)
+    }
+
+    fn maybe_compact(&mut self) {
+        if self.garbage > Self::COMPACT_THRESHOLD && self.garbage * 2 > self.names.len() {
+            self.compact();
+        }
+    }
+
+    /// 丢弃名称区中的旧名称，并释放多余的容量。
+    pub fn compact(&mut self) {
+        let len = self.names.len() - self.garbage;
+        let (_, count) = self.len();
+        let mut names = String::with_capacity(len);
+        let mut folded = Vec::with_capacity(len + count);
+        let mut cells = Vec::with_capacity(count);
+        let mut copy = |id: usize, name: &mut NameRef| {
+            let old = slice_name(&self.names, *name);
+            *name = push_name(&mut names, old);
+            push_folded(&mut folded, &mut cells, id as SlotId, *name, old);
+        };
+        for (id, slot) in self.slots.iter_mut().enumerate() {
+            if slot.parent != VACANT {
+                copy(id, &mut slot.name);
+            }
+        }
+        for (&id, links) in self.links.iter_mut() {
+            for link in links {
+                copy(id as usize, &mut link.name);
+            }
+        }
+        self.names = names;
+        self.folded = folded;
+        self.cells = cells;
+        self.epoch = next_epoch();
+        if self.trigrams.is_some() {
+            self.set_trigrams(true);
+        }
+        if self.lookup.is_some() {
+            self.lookup = Some(self.build_lookup());
+        }
+        self.garbage = 0;
+        self.slots.shrink_to_fit();
+        self.map.shrink_to_fit();
+        self.links.shrink_to_fit();
+    }
+
+    pub fn memory_usage(&self) -> MemoryUsage {
+        let (files, names) = self.len();
+        MemoryUsage {
+            files,
+            names,
+            slots: self.slots.capacity() * size_of::<Slot>() + self.free.capacity() * 4,
+            name_bytes: self.names.capacity(),
+            folded: self.folded.capacity() + self.cells.capacity() * size_of::<Cell>(),
+            trigrams: self.trigrams.as_ref().map_or(0, Trigrams::memory_usage),
+            garbage: self.garbage,
+            map: hash_map_bytes(self.map.capacity(), size_of::<(Key, SlotId)>())
+                + self.lookup.as_ref().map_or(0, |lookup| {
+                    hash_map_bytes(lookup.cells.capacity(), size_of::<(u64, u32)>())
+                        + lookup.collisions.capacity() * 4
+                }),
+            links: hash_map_bytes(self.links.capacity(), size_of::<(SlotId, Vec<Link>)>())
+                + self
+                    .links
+                    .values()
+                    .map(|links| links.capacity() * size_of::<Link>())
+                    .sum::<usize>(),
+        }
+    }
+}
+
+fn slice_name(names: &str, name: NameRef) -> &str {
+    &names[name.start as usize..(name.start + name.len) as usize]
+}
+
+fn push_name(names: &mut String, name: &str) -> NameRef {
+    let start = u32::try_from(names.len()).expect("名称区超过了 4GB");
+    names.push_str(name);
+    NameRef {
+        start,
+        len: name.len() as u32,
+    }
+}
+
+fn next_epoch() -> u64 {
+    static EPOCH: AtomicU64 = AtomicU64::new(0);
+    EPOCH.fetch_add(1, Ordering::Relaxed)
+}
+
+/// 序号为 `i` 的折叠名称，不含结尾的 `\0`
+fn cell_bytes<'a>(folded: &'a [u8], cells: &[Cell], i: usize) -> &'a [u8] {
+    let end = cells
+        .get(i + 1)
+        .map_or(folded.len(), |next| next.start as usize);
+    &folded[cells[i].start as usize..end - 1]
+}
+
+fn build_trigrams(folded: &[u8], cells: &[Cell]) -> Trigrams {
+    let mut trigrams = Trigrams::default();
+    for i in 0..cells.len() {
+        trigrams.add(i as u32, cell_bytes(folded, cells, i));
+    }
+    trigrams
+}
+
+fn push_folded(folded: &mut Vec<u8>, cells: &mut Vec<Cell>, slot: SlotId, name: NameRef, s: &str) {
+    let start = u32::try_from(folded.len()).expect("折叠名称区超过了 4GB");
+    cells.push(Cell { start, slot, name });
+    fold_into(s, folded);
+    folded.push(0);
+}
+
+/// hashbrown 每个桶另有 1 字节的控制信息，实际桶数是容量的 8/7 倍并向上取到 2 的幂，
+/// 这里只按容量估算
+pub(super) fn hash_map_bytes(capacity: usize, entry: usize) -> usize {
+    capacity * (entry + 1)
+}
+
+/// 查找到的一个名称
+pub(super) struct Hit<'a> {
+    /// 名称的序号
+    pub cell: u32,
+    pub parent: SlotId,
+    pub name: &'a str,
+    pub info: &'a FileInfo,
+    /// 匹配在折叠后名称中的偏移
+    pub offset: usize,
+}
+
+/// 索引占用的内存，单位为字节
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct MemoryUsage {
+    /// 文件数
+    pub files: usize,
+    /// 名称数，硬链接的每个名称各算一个
+    pub names: usize,
+    /// 槽位表
+    pub slots: usize,
+    /// 名称区，包括其中的旧名称
+    pub name_bytes: usize,
+    /// 名称区中等待整理的旧名称
+    pub garbage: usize,
+    /// 折叠后的名称，供查找使用
+    pub folded: usize,
+    /// 折叠名称的倒排表，没有建立时为 0
+    pub trigrams: usize,
+    /// FRN 到槽位的映射，以及按父目录和名称查找的映射
+    pub map: usize,
+    /// 硬链接的其余名称
+    pub links: usize,
+}
+
+impl MemoryUsage {
+    pub fn total(&self) -> usize {
+        self.slots + self.name_bytes + self.folded + self.trigrams + self.map + self.links
+    }
+}
+
+/// 形如 `12.3MiB（123456 个文件，槽位 … 名称 … 折叠 … 倒排 … 映射 … 硬链接 …）`
+impl Display for MemoryUsage {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let mib = |bytes: usize| bytes as f64 / (1024.0 * 1024.0);
+        write!(
+            f,
+            "{:.1}MiB（{} 个文件，槽位 {:.1}MiB，名称 {:.1}MiB，折叠 {:.1}MiB，倒排 {:.1}MiB，映射 {:.1}MiB，硬链接 {:.1}MiB）",
+            mib(self.total()),
+            self.files,
+            mib(self.slots),
+            mib(self.name_bytes),
+            mib(self.folded),
+            mib(self.trigrams),
+            mib(self.map),
+            mib(self.links),
+        )
+    }
+}
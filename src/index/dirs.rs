@@ -0,0 +1,212 @@
+use std::{
+    collections::HashMap,
+    ops::Range,
+    path::{MAIN_SEPARATOR, MAIN_SEPARATOR_STR},
+    sync::Arc,
+};
+
+use super::{arena::SlotId, fold::fold_into, query::Matcher, Index};
+
+/// 目录折叠前后的完整路径，连续存放。
+#[derive(Default)]
+pub(super) struct DirPaths {
+    /// 拼接时目录路径的版本
+    epoch: u64,
+    folded: Vec<u8>,
+    original: String,
+    /// 目录的槽位到它的 FRN 和路径，槽位被复用后 FRN 不同，路径不再有效
+    dirs: HashMap<SlotId, (u128, DirPath)>,
+    /// driver
+    root: Option<DirPath>,
+}
+
+#[derive(Clone)]
+struct DirPath {
+    /// 折叠后的路径在 `folded` 中的范围
+    folded: Range<usize>,
+    /// 折叠前的路径在 `original` 中的范围
+    original: Range<usize>,
+}
+
+impl DirPaths {
+    /// 拼接索引中所有目录的路径
+    fn build(index: &Index) -> Self {
+        let mut paths = Self {
+            epoch: index.arena.dirs_epoch(),
+            ..Default::default()
+        };
+        for slot in index.arena.dirs() {
+            paths.dir(index, None, slot);
+        }
+        paths
+    }
+
+    fn get(&self, index: &Index, slot: SlotId) -> Option<DirPath> {
+        match self.dirs.get(&slot) {
+            Some((frn, dir)) if *frn == index.arena.frn(slot) => Some(dir.clone()),
+            _ => None,
+        }
+    }
+
+    fn start(&self) -> (usize, usize) {
+        (self.folded.len(), self.original.len())
+    }
+
+    /// 记录 `start` 之后的路径
+    fn end(&self, (folded, original): (usize, usize)) -> DirPath {
+        DirPath {
+            folded: folded..self.folded.len(),
+            original: original..self.original.len(),
+        }
+    }
+
+    fn insert(&mut self, index: &Index, slot: SlotId, start: (usize, usize)) -> DirPath {
+        let dir = self.end(start);
+        self.dirs.insert(slot, (index.arena.frn(slot), dir.clone()));
+        dir
+    }
+
+    fn root(&mut self, index: &Index) -> DirPath {
+        if let Some(root) = &self.root {
+            return root.clone();
+        }
+        let start = self.start();
+        let driver = index.driver.trim_end_matches(MAIN_SEPARATOR);
+        fold_into(driver, &mut self.folded);
+        self.original.push_str(driver);
+        self.root.insert(self.end(start)).clone()
+    }
+
+    /// 目录的路径，没有时拼接它和缺少的上级目录，`base` 中已有的目录从那里复制
+    fn dir(&mut self, index: &Index, base: Option<&DirPaths>, slot: SlotId) -> DirPath {
+        // 向上找到已经拼接过的目录或根目录，再依次拼接下来
+        let mut missing = Vec::new();
+        let mut dir = 'found: {
+            for (slot, name) in index.arena.ancestors(slot) {
+                if let Some(dir) = self.get(index, slot) {
+                    break 'found dir;
+                }
+                if let Some((base, dir)) =
+                    base.and_then(|base| Some((base, base.get(index, slot)?)))
+                {
+                    let start = self.start();
+                    self.folded.extend_from_slice(&base.folded[dir.folded]);
+                    self.original.push_str(&base.original[dir.original]);
+                    break 'found self.insert(index, slot, start);
+                }
+                missing.push((slot, name));
+            }
+            self.root(index)
+        };
+        for (slot, name) in missing.into_iter().rev() {
+            let start = self.start();
+            self.folded.extend_from_within(dir.folded);
+            self.folded.extend_from_slice(MAIN_SEPARATOR_STR.as_bytes());
+            fold_into(name, &mut self.folded);
+            self.original.extend_from_within(dir.original);
+            self.original.push_str(MAIN_SEPARATOR_STR);
+            self.original.push_str(name);
+            dir = self.insert(index, slot, start);
+        }
+        dir
+    }
+}
+
+impl Index {
+    /// 所有目录的完整路径，目录被重命名、移动或删除后第一次用到时重新拼接
+    fn dir_paths(&self) -> Arc<DirPaths> {
+        let mut paths = self.dir_paths.lock().unwrap();
+        match &*paths {
+            Some(p) if p.epoch == self.arena.dirs_epoch() => p.clone(),
+            _ => paths.insert(Arc::new(DirPaths::build(self))).clone(),
+        }
+    }
+}
+
+/// 匹配完整路径时用到的目录路径。
+///
+/// 所有目录的路径由索引保存，不同的查找和并行查找的各段共用，只在目录变化后重新拼接；
+/// 之后新建的目录在每次查找中另外拼接。
+///
+/// 同时记录每个目录的路径中已经包含哪些必须包含的关键词，
+/// 其余的关键词只需要在目录末尾的几个字节、分隔符和名称中查找，
+/// 通过预筛选的名称才拼接完整路径。
+pub(super) struct Dirs {
+    shared: Arc<DirPaths>,
+    /// 共用的路径中没有的目录
+    local: DirPaths,
+    /// 目录的路径中已经包含的关键词，第 i 位对应第 i 个必须包含的关键词
+    found: HashMap<SlotId, u64>,
+    /// 预筛选的片段和完整路径
+    path: Vec<u8>,
+    /// 折叠前的完整路径
+    original: String,
+}
+
+impl Dirs {
+    pub fn new(index: &Index) -> Self {
+        Self {
+            shared: index.dir_paths(),
+            local: DirPaths::default(),
+            found: HashMap::new(),
+            path: Vec::new(),
+            original: String::new(),
+        }
+    }
+
+    /// 按折叠后的完整路径检查名称，匹配时返回在完整路径中高亮的范围
+    pub fn eval(
+        &mut self,
+        index: &Index,
+        matcher: &Matcher,
+        parent: SlotId,
+        folded_name: &[u8],
+        name: &str,
+    ) -> Option<Vec<Range<usize>>> {
+        let required = &matcher.literals()[..matcher.literals().len().min(u64::BITS as usize)];
+        let (paths, dir) = match self.shared.get(index, parent) {
+            Some(dir) => (&*self.shared, dir),
+            None => {
+                let dir = self.local.dir(index, Some(&self.shared), parent);
+                (&self.local, dir)
+            }
+        };
+        let dir_bytes = &paths.folded[dir.folded.clone()];
+        let found = *self.found.entry(parent).or_insert_with(|| {
+            required
+                .iter()
+                .enumerate()
+                .filter(|(_, finder)| finder.find(dir_bytes).is_some())
+                .fold(0, |found, (i, _)| found | 1 << i)
+        });
+
+        // 不完全在目录中的关键词只能从目录末尾 `len - 1` 个字节以内开始
+        let tail = required
+            .iter()
+            .map(|finder| finder.needle().len().saturating_sub(1))
+            .max()
+            .unwrap_or(0)
+            .min(dir_bytes.len());
+        self.path.clear();
+        self.path
+            .extend_from_slice(&dir_bytes[dir_bytes.len() - tail..]);
+        self.path.extend_from_slice(MAIN_SEPARATOR_STR.as_bytes());
+        self.path.extend_from_slice(folded_name);
+        for (i, finder) in required.iter().enumerate() {
+            let skip = tail - tail.min(finder.needle().len().saturating_sub(1));
+            if found & 1 << i == 0 && finder.find(&self.path[skip..]).is_none() {
+                return None;
+            }
+        }
+
+        self.path.clear();
+        self.path.extend_from_slice(dir_bytes);
+        self.path.extend_from_slice(MAIN_SEPARATOR_STR.as_bytes());
+        self.path.extend_from_slice(folded_name);
+        self.original.clear();
+        self.original.push_str(&paths.original[dir.original]);
+        self.original.push_str(MAIN_SEPARATOR_STR);
+        self.original.push_str(name);
+        matcher.eval(&self.path, &self.original, 0)
+    }
+}
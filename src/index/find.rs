@@ -1,28 +1,58 @@
 use memchr::memmem::Finder;
 use std::{
-    collections::hash_map::Values,
+    borrow::Cow,
     fmt::Display,
-    path::{Path, MAIN_SEPARATOR_STR},
+    ops::Range,
+    path::{Path, MAIN_SEPARATOR, MAIN_SEPARATOR_STR},
 };
 
-use super::Index;
+use super::{
+    dirs::Dirs,
+    query::{Matcher, Query},
+    Index,
+};
 
 pub struct FullPath {
     pub inner: String,
-    sub_start: usize,
-    sub_end: usize,
+    /// 高亮的范围，按顺序排列，互不重叠
+    spans: Vec<Range<usize>>,
+    is_dir: bool,
 }
 
 impl FullPath {
-    /// 将路径按照查找时的关键词分割为三个部分，
-    /// 其中中间的部分为匹配到的关键词。
+    pub fn is_dir(&self) -> bool {
+        self.is_dir
+    }
+
+    /// 将路径按照第一个高亮的范围分割为三个部分，
+    /// 其中中间的部分为匹配到的关键词，没有高亮时后两部分为空。
     pub fn split(&self) -> (&str, &str, &str) {
+        let len = self.inner.len();
+        let span = self.spans.first().cloned().unwrap_or(len..len);
         (
-            &self.inner[..self.sub_start],
-            &self.inner[self.sub_start..self.sub_end],
-            &self.inner[self.sub_end..],
+            &self.inner[..span.start],
+            &self.inner[span.clone()],
+            &self.inner[span.end..],
         )
     }
+
+    /// 所有高亮的范围，如 `invoice 2024` 中两个关键词各自的位置
+    pub fn spans(&self) -> &[Range<usize>] {
+        &self.spans
+    }
+
+    /// 将路径分割为若干段，每段带有是否高亮的标记
+    pub fn segments(&self) -> impl Iterator<Item = (&str, bool)> {
+        let mut pos = 0;
+        let mut res = Vec::with_capacity(self.spans.len() * 2 + 1);
+        for span in &self.spans {
+            res.push((&self.inner[pos..span.start], false));
+            res.push((&self.inner[span.clone()], true));
+            pos = span.end;
+        }
+        res.push((&self.inner[pos..], false));
+        res.into_iter().filter(|(s, _)| !s.is_empty())
+    }
 }
 
 impl Display for FullPath {
@@ -37,22 +67,109 @@ impl AsRef<Path> for FullPath {
     }
 }
 
+/// 结果的类型，由关键词前的 `file:` 或 `folder:` 指定
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Kind {
+    #[default]
+    Any,
+    File,
+    Folder,
+}
+
+impl Kind {
+    /// 去掉关键词开头的类型修饰符，多个修饰符时以最后一个为准。修饰符不区分大小写。
+    pub fn parse(mut sub: &str) -> (Self, &str) {
+        let mut kind = Self::Any;
+        loop {
+            let (next, rest) = match sub.split_once(':') {
+                Some((m, rest)) if m.eq_ignore_ascii_case("file") => (Self::File, rest),
+                Some((m, rest)) if m.eq_ignore_ascii_case("folder") => (Self::Folder, rest),
+                _ => return (kind, sub),
+            };
+            kind = next;
+            sub = rest.trim_start();
+        }
+    }
+
+    pub(super) fn matches(self, is_dir: bool) -> bool {
+        match self {
+            Self::Any => true,
+            Self::File => !is_dir,
+            Self::Folder => is_dir,
+        }
+    }
+}
+
 pub struct FindIter<'a> {
     index: &'a Index,
-    sub: &'a str,
-    finder: Finder<'a>,
-    values: Values<'a, u64, (u64, Box<str>)>,
+    matcher: Matcher,
+    /// 预筛选用的关键词
+    finder: Finder<'static>,
+    /// 在折叠名称区中下一次查找的起点，使用候选名称时为下一个候选的位置
+    pos: usize,
+    end: usize,
+    /// 预筛选的关键词为空，逐个检查名称，此时 `pos` 和 `end` 是名称的序号
+    every: bool,
+    /// 倒排表给出的候选名称，有时不再扫描折叠名称区
+    cells: Option<Cow<'a, [u32]>>,
+    /// 匹配完整路径时用到的目录路径
+    dirs: Option<Dirs>,
 }
 
 impl<'a> FindIter<'a> {
-    pub fn new(index: &'a Index, sub: &'a str) -> Self {
+    /// 按 [`Query`] 的语法查找，语法错误时把整个输入作为一个关键词。
+    pub fn new(index: &'a Index, sub: &str) -> Self {
+        Self::with_matcher(index, Matcher::parse(sub))
+    }
+
+    pub fn query(index: &'a Index, query: &Query) -> Self {
+        Self::with_matcher(index, Matcher::new(query))
+    }
+
+    /// 建立了倒排表并且必须包含的关键词足够长时只检查候选名称，否则扫描整个折叠名称区。
+    fn with_matcher(index: &'a Index, matcher: Matcher) -> Self {
+        let mut iter = Self::scan(index, matcher, 0..index.arena.folded_len());
+        iter.cells = index.arena.candidates(iter.finder.needle()).map(Cow::Owned);
+        iter
+    }
+
+    fn scan(index: &'a Index, matcher: Matcher, range: Range<usize>) -> Self {
+        let finder = matcher.prefilter();
+        // 每个名称都匹配空关键词，不必在折叠名称区中逐个定位
+        let every = finder.needle().is_empty();
+        let range = match every {
+            true => index.arena.cell_range(range),
+            false => range,
+        };
+        let dirs = matcher.path.then(|| Dirs::new(index));
         Self {
             index,
-            sub,
-            finder: Finder::new(&sub.to_lowercase()).into_owned(),
-            values: index.map.values(),
+            finder,
+            matcher,
+            pos: range.start,
+            end: range.end,
+            every,
+            cells: None,
+            dirs,
         }
     }
+
+    /// 只查找折叠名称区中的一段，两端需要是名称的边界
+    pub(super) fn with_range(index: &'a Index, sub: &str, range: Range<usize>) -> Self {
+        Self::scan(index, Matcher::parse(sub), range)
+    }
+
+    /// 只检查给定的候选名称
+    pub(super) fn with_cells(index: &'a Index, sub: &str, cells: &'a [u32]) -> Self {
+        let mut iter = Self::with_range(index, sub, 0..0);
+        iter.cells = Some(Cow::Borrowed(cells));
+        iter
+    }
+
+    /// 倒排表给出的候选名称，扫描时为 `None`
+    pub(super) fn into_cells(self) -> Option<Vec<u32>> {
+        self.cells.map(Cow::into_owned)
+    }
 }
 
 impl<'a> Iterator for FindIter<'a> {
@@ -60,28 +177,80 @@ impl<'a> Iterator for FindIter<'a> {
 
     fn next(&mut self) -> Option<Self::Item> {
         loop {
-            let (parent_frn, name) = self.values.next()?;
-            if let Some(mid) = self.finder.find(name.to_lowercase().as_bytes()) {
-                let mut parts: Vec<&str> = Vec::new();
-                let mut frn = *parent_frn;
-                while let Some((parent_frn, name)) = self.index.map.get(&frn) {
-                    parts.push(name);
-                    frn = *parent_frn;
+            let arena = &self.index.arena;
+            let hit = match &self.cells {
+                Some(cells) => {
+                    let &cell = cells.get(self.pos)?;
+                    self.pos += 1;
+                    match arena.find_cell(&self.finder, cell) {
+                        Some(hit) => hit,
+                        None => continue,
+                    }
                 }
-                parts.push(&self.index.driver);
-                parts.reverse();
-                parts.push(&name);
-
-                let path = parts.join(MAIN_SEPARATOR_STR);
-                let sub_start = path.len() - name.len() + mid;
-                let sub_end = sub_start + self.sub.len();
-
-                return Some(FullPath {
-                    inner: path,
-                    sub_start,
-                    sub_end,
-                });
+                None if self.every => {
+                    if self.pos >= self.end {
+                        return None;
+                    }
+                    self.pos += 1;
+                    match arena.find_cell(&self.finder, self.pos as u32 - 1) {
+                        Some(hit) => hit,
+                        None => continue,
+                    }
+                }
+                None => {
+                    let (hit, pos) = arena.find(&self.finder, self.pos, self.end)?;
+                    self.pos = pos;
+                    hit
+                }
+            };
+            let is_dir = hit.info.attributes.is_dir();
+            if !self.matcher.kind.matches(is_dir) {
+                continue;
             }
+            let folded = arena.folded_name(hit.cell);
+            let spans = match &mut self.dirs {
+                Some(dirs) => dirs.eval(self.index, &self.matcher, hit.parent, folded, hit.name),
+                None => self.matcher.eval(folded, hit.name, hit.offset),
+            };
+            let Some(spans) = spans else {
+                continue;
+            };
+
+            let mut parts: Vec<&str> = arena.ancestors(hit.parent).map(|(_, name)| name).collect();
+            // 根目录形式的 driver（如 "/"）自带分隔符
+            parts.push(self.index.driver.trim_end_matches(MAIN_SEPARATOR));
+            parts.reverse();
+            parts.push(hit.name);
+
+            let path = parts.join(MAIN_SEPARATOR_STR);
+            // 只匹配名称时高亮的范围在名称中
+            let base = match self.matcher.path {
+                true => 0,
+                false => path.len() - hit.name.len(),
+            };
+            let spans = spans
+                .into_iter()
+                .map(|span| base + span.start..base + span.end);
+
+            return Some(FullPath {
+                spans: merge(spans),
+                inner: path,
+                is_dir,
+            });
+        }
+    }
+}
+
+/// 排序并合并重叠或相邻的范围
+fn merge(spans: impl Iterator<Item = Range<usize>>) -> Vec<Range<usize>> {
+    let mut spans: Vec<_> = spans.collect();
+    spans.sort_by_key(|span| span.start);
+    let mut res: Vec<Range<usize>> = Vec::with_capacity(spans.len());
+    for span in spans {
+        match res.last_mut() {
+            Some(last) if span.start <= last.end => last.end = last.end.max(span.end),
+            _ => res.push(span),
         }
     }
+    res
 }
@@ -0,0 +1,264 @@
+use std::ops::Range;
+
+use super::fold::{fold_char, folded_len};
+
+/// 通配符模式，与 Everything 一样需要匹配整个名称。
+///
+/// 只有含 `*` 或 `?` 的关键词才是通配符模式，这样名称中常见的 `[2024]` 不必转义。
+/// `*` 匹配任意个字符，`?` 匹配一个字符，`[abc]`、`[a-z]` 匹配其中的一个字符，
+/// `[!a]` 或 `[^a]` 匹配不在其中的字符；没有闭合的 `[` 是普通字符。
+///
+/// 在折叠后的名称上匹配，但 `?` 和字符类总是匹配一个原字符（`?` 匹配整个 `ß`，
+/// 而不是折叠出的一个 `s`），普通字符也只能匹配完整的原字符。
+pub(super) struct Glob {
+    segments: Vec<Vec<Token>>,
+    /// 以 `*` 开头，第一段不必从名称开头匹配
+    star_start: bool,
+    /// 以 `*` 结尾，最后一段不必匹配到名称结尾
+    star_end: bool,
+}
+
+enum Token {
+    /// 折叠后的普通字符
+    Literal(String),
+    One,
+    Class {
+        negated: bool,
+        ranges: Vec<(char, char)>,
+    },
+}
+
+/// 折叠后的名称中每个原字符开始的位置
+struct Bounds {
+    len: usize,
+    /// 包括末尾，名称是 ASCII 时每个字节都是边界，为 `None`
+    starts: Option<Vec<usize>>,
+}
+
+impl Bounds {
+    fn new(original: &str) -> Self {
+        let starts = (!original.is_ascii()).then(|| {
+            let mut folded = 0;
+            let mut starts: Vec<_> = original
+                .chars()
+                .map(|c| {
+                    let start = folded;
+                    folded += folded_len(c);
+                    start
+                })
+                .collect();
+            starts.push(folded);
+            starts
+        });
+        Self {
+            len: starts
+                .as_ref()
+                .and_then(|s| s.last().copied())
+                .unwrap_or(original.len()),
+            starts,
+        }
+    }
+
+    fn contains(&self, at: usize) -> bool {
+        match &self.starts {
+            Some(starts) => starts.binary_search(&at).is_ok(),
+            None => at <= self.len,
+        }
+    }
+
+    /// `at` 处的原字符折叠后结束的位置
+    fn next(&self, at: usize) -> Option<usize> {
+        match &self.starts {
+            Some(starts) => starts.get(starts.binary_search(&at).ok()? + 1).copied(),
+            None => (at < self.len).then_some(at + 1),
+        }
+    }
+
+    /// 从 `pos` 开始的所有边界
+    fn from(&self, pos: usize) -> impl Iterator<Item = usize> + '_ {
+        (pos..=self.len).filter(|&at| self.contains(at))
+    }
+}
+
+impl Glob {
+    /// 含有 `*` 或 `?` 的关键词按通配符匹配，只有 `[…]` 时是普通字符
+    pub fn is_glob(term: &str) -> bool {
+        term.contains(['*', '?'])
+    }
+
+    pub fn new(pattern: &str) -> Self {
+        let mut segments = vec![Vec::new()];
+        let mut literal = String::new();
+        let mut chars = pattern.char_indices().peekable();
+        while let Some((i, c)) = chars.next() {
+            let token = match c {
+                '*' => {
+                    flush(&mut literal, &mut segments);
+                    // 连续的 `*` 与一个相同
+                    while chars.next_if(|&(_, c)| c == '*').is_some() {}
+                    segments.push(Vec::new());
+                    continue;
+                }
+                '?' => Token::One,
+                '[' => match class(&pattern[i + 1..]) {
+                    Some((token, len)) => {
+                        while chars.next_if(|&(j, _)| j <= i + len).is_some() {}
+                        token
+                    }
+                    None => {
+                        literal.push('[');
+                        continue;
+                    }
+                },
+                c => {
+                    literal.extend(fold_char(c));
+                    continue;
+                }
+            };
+            flush(&mut literal, &mut segments);
+            segments.last_mut().unwrap().push(token);
+        }
+        flush(&mut literal, &mut segments);
+        Self {
+            segments,
+            star_start: pattern.starts_with('*'),
+            star_end: pattern.ends_with('*'),
+        }
+    }
+
+    /// 最长的普通字符，所有匹配的名称都包含它
+    pub fn longest_literal(&self) -> &str {
+        self.segments
+            .iter()
+            .flatten()
+            .filter_map(|token| match token {
+                Token::Literal(s) => Some(s.as_str()),
+                _ => None,
+            })
+            .max_by_key(|s| s.len())
+            .unwrap_or_default()
+    }
+
+    /// 检查折叠后的名称，`original` 是折叠前的名称，匹配时把普通字符的位置加入 `spans`
+    pub fn matches(&self, name: &str, original: &str, spans: &mut Vec<Range<usize>>) -> bool {
+        let mark = spans.len();
+        let bounds = Bounds::new(original);
+        let matched = bounds.len == name.len() && self.matches_inner(name, &bounds, spans);
+        if !matched {
+            spans.truncate(mark);
+        }
+        matched
+    }
+
+    fn matches_inner(&self, name: &str, bounds: &Bounds, spans: &mut Vec<Range<usize>>) -> bool {
+        let last = self.segments.len() - 1;
+        let mut pos = 0;
+        for (i, segment) in self.segments.iter().enumerate() {
+            let at_start = i == 0 && !self.star_start;
+            let at_end = i == last && !self.star_end;
+            if at_start {
+                match match_segment(segment, name, bounds, 0, spans) {
+                    Some(end) if !at_end || end == name.len() => pos = end,
+                    _ => return false,
+                }
+            } else if at_end {
+                // 最后一段需要匹配到名称结尾
+                let found = bounds.from(pos).any(|at| {
+                    let mark = spans.len();
+                    let end = match_segment(segment, name, bounds, at, spans);
+                    if end != Some(name.len()) {
+                        spans.truncate(mark);
+                    }
+                    end == Some(name.len())
+                });
+                if !found {
+                    return false;
+                }
+            } else {
+                // 中间的段取最左边的位置，给之后的段留下最多的字符
+                let found = bounds
+                    .from(pos)
+                    .find_map(|at| match_segment(segment, name, bounds, at, spans));
+                match found {
+                    Some(end) => pos = end,
+                    None => return false,
+                }
+            }
+        }
+        true
+    }
+}
+
+fn flush(literal: &mut String, segments: &mut [Vec<Token>]) {
+    if !literal.is_empty() {
+        let token = Token::Literal(std::mem::take(literal));
+        segments.last_mut().unwrap().push(token);
+    }
+}
+
+/// 解析 `[` 之后的字符类，返回它和到 `]` 为止的字节数
+fn class(rest: &str) -> Option<(Token, usize)> {
+    let (negated, body) = match rest.strip_prefix(['!', '^']) {
+        Some(body) => (true, body),
+        None => (false, rest),
+    };
+    // 第一个字符即使是 `]` 也是普通字符，所以字符类不会为空
+    let first = body.chars().next()?.len_utf8();
+    let close = first + body[first..].find(']')?;
+    let mut ranges = Vec::new();
+    let mut chars = body[..close].chars().map(class_char).peekable();
+    while let Some(lo) = chars.next() {
+        if chars.peek() == Some(&'-') {
+            chars.next();
+            match chars.next() {
+                Some(hi) => ranges.push((lo, hi)),
+                None => ranges.extend([(lo, lo), ('-', '-')]),
+            }
+        } else {
+            ranges.push((lo, lo));
+        }
+    }
+    let len = rest.len() - body.len() + close + 1;
+    Some((Token::Class { negated, ranges }, len))
+}
+
+/// 字符类中的字符只取折叠后的第一个字符
+fn class_char(c: char) -> char {
+    fold_char(c).next().unwrap_or(c)
+}
+
+/// 从原字符的边界 `at` 开始匹配一段，返回结束的位置
+fn match_segment(
+    segment: &[Token],
+    name: &str,
+    bounds: &Bounds,
+    mut at: usize,
+    spans: &mut Vec<Range<usize>>,
+) -> Option<usize> {
+    let mark = spans.len();
+    for token in segment {
+        let rest = &name[at..];
+        let end = match token {
+            Token::Literal(s) if rest.starts_with(s.as_str()) => {
+                Some(at + s.len()).filter(|&end| bounds.contains(end))
+            }
+            Token::Literal(_) => None,
+            Token::One => bounds.next(at),
+            // 按原字符折叠后的第一个字符比较，与字符类中的字符一致
+            Token::Class { negated, ranges } => rest
+                .chars()
+                .next()
+                .filter(|&c| ranges.iter().any(|&(lo, hi)| lo <= c && c <= hi) != *negated)
+                .and_then(|_| bounds.next(at)),
+        };
+        let Some(end) = end else {
+            spans.truncate(mark);
+            return None;
+        };
+        if let Token::Literal(_) = token {
+            spans.push(at..end);
+        }
+        at = end;
+    }
+    Some(at)
+}
@@ -0,0 +1,174 @@
+use std::{
+    mem,
+    num::NonZeroUsize,
+    ops::Range,
+    sync::{
+        atomic::{AtomicBool, AtomicUsize, Ordering},
+        mpsc::channel,
+    },
+    thread,
+};
+
+use super::{find::FindIter, FullPath, Index};
+
+/// 并行查找的选项
+#[derive(Debug, Clone, Copy, Default)]
+pub struct ParOptions {
+    /// 线程数，为 0 时使用全部核心
+    pub threads: usize,
+    /// 按照与 [`Index::find_iter`] 相同的顺序产生结果，需要暂存先完成的分段
+    pub ordered: bool,
+}
+
+impl ParOptions {
+    /// 每个线程分到的段数，段越多负载越均衡
+    const CHUNKS_PER_THREAD: usize = 8;
+    /// 每段至少这么多字节的折叠名称，太小的索引不值得开线程
+    const MIN_CHUNK: usize = 64 << 10;
+    /// 使用倒排表时每段至少这么多候选名称
+    const MIN_CELLS: usize = 4096;
+
+    fn threads(&self) -> usize {
+        match self.threads {
+            0 => thread::available_parallelism().map_or(1, NonZeroUsize::get),
+            n => n,
+        }
+    }
+}
+
+/// 分给一个线程的一段
+enum Part<'a> {
+    /// 折叠名称区中的一段
+    Range(Range<usize>),
+    /// 一部分候选名称
+    Cells(&'a [u32]),
+}
+
+impl<'a> Part<'a> {
+    fn find_iter(&self, index: &'a Index, sub: &str) -> FindIter<'a> {
+        match self {
+            Self::Range(range) => FindIter::with_range(index, sub, range.clone()),
+            Self::Cells(cells) => FindIter::with_cells(index, sub, cells),
+        }
+    }
+}
+
+impl Index {
+    /// 多线程查找，结果在调用线程上依次交给 `f`。
+    ///
+    /// 折叠名称区（使用倒排表时为候选名称）被分成若干段，由各个线程轮流领取；
+    /// `f` 返回 `false` 时停止查找，此时返回 `false`。
+    pub fn par_find<F>(&self, sub: &str, options: ParOptions, f: F) -> bool
+    where
+        F: FnMut(FullPath) -> bool,
+    {
+        match FindIter::new(self, sub).into_cells() {
+            Some(cells) => self.par_find_cells(sub, &cells, options, f),
+            None => {
+                let n = options.threads() * ParOptions::CHUNKS_PER_THREAD;
+                let parts = self.arena.split(n, ParOptions::MIN_CHUNK);
+                self.par_find_parts(
+                    sub,
+                    parts.into_iter().map(Part::Range).collect(),
+                    options,
+                    f,
+                )
+            }
+        }
+    }
+
+    /// 只检查给定的名称
+    pub(super) fn par_find_cells<F>(
+        &self,
+        sub: &str,
+        cells: &[u32],
+        options: ParOptions,
+        f: F,
+    ) -> bool
+    where
+        F: FnMut(FullPath) -> bool,
+    {
+        let n = options.threads() * ParOptions::CHUNKS_PER_THREAD;
+        let size = (cells.len() / n).max(ParOptions::MIN_CELLS);
+        let parts = cells.chunks(size).map(Part::Cells).collect();
+        self.par_find_parts(sub, parts, options, f)
+    }
+
+    fn par_find_parts<F>(&self, sub: &str, parts: Vec<Part>, options: ParOptions, mut f: F) -> bool
+    where
+        F: FnMut(FullPath) -> bool,
+    {
+        let threads = options.threads();
+        if threads <= 1 || parts.len() <= 1 {
+            return parts
+                .iter()
+                .flat_map(|part| part.find_iter(self, sub))
+                .all(f);
+        }
+
+        let next = AtomicUsize::new(0);
+        let stop = AtomicBool::new(false);
+        thread::scope(|s| {
+            // 每段的结果之后跟一个 `None` 表示该段结束
+            let (tx, rx) = channel();
+            for _ in 0..threads.min(parts.len()) {
+                let tx = tx.clone();
+                let (parts, next, stop) = (&parts, &next, &stop);
+                s.spawn(move || loop {
+                    let i = next.fetch_add(1, Ordering::Relaxed);
+                    let Some(part) = parts.get(i) else {
+                        return;
+                    };
+                    for path in part.find_iter(self, sub) {
+                        if stop.load(Ordering::Relaxed) || tx.send((i, Some(path))).is_err() {
+                            return;
+                        }
+                    }
+                    if tx.send((i, None)).is_err() {
+                        return;
+                    }
+                });
+            }
+            drop(tx);
+
+            let mut emit = |path| {
+                let ok = f(path);
+                if !ok {
+                    stop.store(true, Ordering::Relaxed);
+                }
+                ok
+            };
+            if !options.ordered {
+                return rx.into_iter().flat_map(|(_, path)| path).all(emit);
+            }
+
+            // 当前输出的段，之后的段的结果先暂存
+            let mut current = 0;
+            let mut pending: Vec<Vec<FullPath>> = parts.iter().map(|_| Vec::new()).collect();
+            let mut done = vec![false; parts.len()];
+            for (i, path) in rx {
+                match path {
+                    Some(path) if i == current => {
+                        if !emit(path) {
+                            return false;
+                        }
+                    }
+                    Some(path) => pending[i].push(path),
+                    None => {
+                        done[i] = true;
+                        while current < parts.len() && done[current] {
+                            current += 1;
+                            let Some(paths) = pending.get_mut(current) else {
+                                break;
+                            };
+                            if !mem::take(paths).into_iter().all(&mut emit) {
+                                return false;
+                            }
+                        }
+                    }
+                }
+            }
+            true
+        })
+    }
+}
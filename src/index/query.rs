@@ -0,0 +1,552 @@
+use memchr::memmem::Finder;
+use regex::{Regex, RegexBuilder};
+use std::{error::Error, fmt::Display, ops::Range, str::FromStr};
+
+use super::{
+    find::Kind,
+    fold::{fold, unfold},
+    glob::Glob,
+    pinyin::Pinyin,
+};
+
+/// 解析后的查询。
+///
+/// 语法：空格分隔的关键词都要匹配，`|` 表示或，`!` 或 `-` 开头表示排除，
+/// 括号分组，双引号中的内容（包括空格）作为一个关键词。`|` 的优先级最低，
+/// 如 `report | invoice !draft` 等价于 `report | (invoice !draft)`。
+/// 含有 `*` 或 `?` 的关键词是通配符，如 `*.rs`、`IMG_????.jpg`，需要匹配整个名称。
+/// 开头可以加上 `file:` 或 `folder:`，见 [`Kind`]；加上 `path:` 时匹配完整路径而不只是名称，
+/// 如 `path:projects\ffd\src`、`path:node_modules lodash`；加上 `pinyin:` 时含有字母的关键词
+/// 还可以按拼音或拼音首字母匹配汉字，如 `pinyin:bgs` 和 `pinyin:baogao` 都能找到 `报告书.docx`。
+///
+/// 修饰符之后以 `regex:` 开头时其余部分是一个正则表达式，如 `file:regex:^v\d+\.\d+\.\d+\.zip$`。
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Query {
+    pub kind: Kind,
+    /// 匹配完整路径，高亮的范围可以在目录中
+    pub path: bool,
+    /// 关键词也按拼音匹配，对通配符和正则表达式没有作用
+    pub pinyin: bool,
+    pub expr: Expr,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Expr {
+    /// 名称（或完整路径）中包含这个关键词，不区分大小写；含有 `*` 或 `?` 时作为通配符匹配整个名称
+    Term(String),
+    /// 所有子项都匹配，没有子项时匹配所有名称
+    And(Vec<Expr>),
+    /// 任意一个子项匹配
+    Or(Vec<Expr>),
+    Not(Box<Expr>),
+    /// 正则表达式，在原名称上匹配，不区分大小写，只能作为整个查询
+    Regex(String),
+}
+
+/// 查询的语法错误
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ParseError {
+    /// 出错位置在输入中的字节偏移
+    pub pos: usize,
+    pub kind: ParseErrorKind,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ParseErrorKind {
+    /// 引号没有闭合，位置为左引号
+    UnclosedQuote,
+    /// 括号没有闭合，位置为左括号
+    UnclosedParen,
+    /// 多余的右括号
+    UnmatchedParen,
+    /// 运算符缺少操作数，如 `a |`、`!`、`()`，位置为运算符
+    MissingOperand(char),
+    /// 正则表达式无效，位置为表达式开头
+    InvalidRegex(String),
+}
+
+impl Display for ParseError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "位置 {}：", self.pos)?;
+        match &self.kind {
+            ParseErrorKind::UnclosedQuote => write!(f, "引号没有闭合"),
+            ParseErrorKind::UnclosedParen => write!(f, "括号没有闭合"),
+            ParseErrorKind::UnmatchedParen => write!(f, "多余的右括号"),
+            ParseErrorKind::MissingOperand(op) => write!(f, "`{op}` 缺少关键词"),
+            ParseErrorKind::InvalidRegex(e) => write!(f, "正则表达式无效：{e}"),
+        }
+    }
+}
+
+impl Error for ParseError {}
+
+impl Query {
+    pub fn parse(input: &str) -> Result<Self, ParseError> {
+        let (kind, path, pinyin, rest) = modifiers(input);
+        if let Some(pattern) = strip_modifier(rest, "regex:") {
+            return Self::regex(pattern, path)
+                .map(|query| Self { kind, ..query })
+                .map_err(|e| ParseError {
+                    pos: input.len() - pattern.len(),
+                    ..e
+                });
+        }
+        let mut parser = Parser {
+            input,
+            pos: input.len() - rest.len(),
+        };
+        let expr = parser.or()?;
+        match parser.peek() {
+            Some(')') => Err(parser.error(ParseErrorKind::UnmatchedParen)),
+            _ => Ok(Self {
+                kind,
+                path,
+                pinyin,
+                expr,
+            }),
+        }
+    }
+
+    /// 正则表达式查询，`path` 为真时匹配完整路径
+    pub fn regex(pattern: &str, path: bool) -> Result<Self, ParseError> {
+        build_regex(pattern).map_err(|e| ParseError {
+            pos: 0,
+            kind: ParseErrorKind::InvalidRegex(e.to_string()),
+        })?;
+        Ok(Self {
+            kind: Kind::Any,
+            path,
+            pinyin: false,
+            expr: Expr::Regex(pattern.to_string()),
+        })
+    }
+
+    /// 整个输入作为一个关键词，只识别开头的修饰符
+    pub fn literal(input: &str) -> Self {
+        let (kind, path, pinyin, rest) = modifiers(input);
+        Self {
+            kind,
+            path,
+            pinyin,
+            expr: Expr::Term(rest.to_string()),
+        }
+    }
+}
+
+impl FromStr for Query {
+    type Err = ParseError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Self::parse(s)
+    }
+}
+
+/// 与输入等价的规范形式，可以重新解析
+impl Display for Query {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self.kind {
+            Kind::Any => {}
+            Kind::File => write!(f, "file:")?,
+            Kind::Folder => write!(f, "folder:")?,
+        }
+        if self.path {
+            write!(f, "path:")?;
+        }
+        if self.pinyin {
+            write!(f, "pinyin:")?;
+        }
+        write!(f, "{}", self.expr)
+    }
+}
+
+impl Display for Expr {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Term(term) => {
+                let plain = !term.is_empty()
+                    && !term.starts_with(['!', '-'])
+                    && !term.contains(|c: char| c.is_whitespace() || "|()\"".contains(c));
+                match plain {
+                    true => write!(f, "{term}"),
+                    false => write!(f, "\"{term}\""),
+                }
+            }
+            Self::And(items) if items.is_empty() => Ok(()),
+            Self::And(items) => {
+                for (i, item) in items.iter().enumerate() {
+                    if i > 0 {
+                        write!(f, " ")?;
+                    }
+                    match item {
+                        Self::Or(_) | Self::And(_) => write!(f, "({item})")?,
+                        _ => write!(f, "{item}")?,
+                    }
+                }
+                Ok(())
+            }
+            Self::Or(items) => {
+                for (i, item) in items.iter().enumerate() {
+                    if i > 0 {
+                        write!(f, " | ")?;
+                    }
+                    match item {
+                        Self::Or(_) => write!(f, "({item})")?,
+                        _ => write!(f, "{item}")?,
+                    }
+                }
+                Ok(())
+            }
+            Self::Not(item) => match **item {
+                Self::Term(_) | Self::Not(_) => write!(f, "!{item}"),
+                _ => write!(f, "!({item})"),
+            },
+            Self::Regex(pattern) => write!(f, "regex:{pattern}"),
+        }
+    }
+}
+
+/// 递归下降：`or := and ('|' and)*`，`and := unary*`，
+/// `unary := ('!' | '-') unary | '(' or ')' | '"' … '"' | word`
+struct Parser<'a> {
+    input: &'a str,
+    pos: usize,
+}
+
+impl Parser<'_> {
+    fn error(&self, kind: ParseErrorKind) -> ParseError {
+        ParseError {
+            pos: self.pos,
+            kind,
+        }
+    }
+
+    /// 跳过空白，返回下一个字符
+    fn peek(&mut self) -> Option<char> {
+        let rest = &self.input[self.pos..];
+        self.pos += rest.len() - rest.trim_start().len();
+        self.input[self.pos..].chars().next()
+    }
+
+    fn bump(&mut self) {
+        self.pos += self.input[self.pos..]
+            .chars()
+            .next()
+            .map_or(0, char::len_utf8);
+    }
+
+    fn or(&mut self) -> Result<Expr, ParseError> {
+        let mut items = vec![self.and()?];
+        while self.peek() == Some('|') {
+            let missing = self.error(ParseErrorKind::MissingOperand('|'));
+            self.bump();
+            let item = self.and()?;
+            if is_empty(&items[items.len() - 1]) || is_empty(&item) {
+                return Err(missing);
+            }
+            items.push(item);
+        }
+        Ok(match items.len() {
+            1 => items.pop().unwrap(),
+            _ => Expr::Or(items),
+        })
+    }
+
+    fn and(&mut self) -> Result<Expr, ParseError> {
+        let mut items = Vec::new();
+        while !matches!(self.peek(), None | Some('|') | Some(')')) {
+            items.push(self.unary()?);
+        }
+        Ok(match items.len() {
+            1 => items.pop().unwrap(),
+            _ => Expr::And(items),
+        })
+    }
+
+    fn unary(&mut self) -> Result<Expr, ParseError> {
+        let next = self.peek();
+        let start = self.pos;
+        match next {
+            Some(op @ ('!' | '-')) => {
+                self.bump();
+                let next = self.input[self.pos..].chars().next();
+                // 单独的 `-` 是普通的关键词，如 `a - b.mp3`
+                if op == '-' && next.is_none_or(char::is_whitespace) {
+                    return Ok(Expr::Term("-".to_string()));
+                }
+                if matches!(self.peek(), None | Some('|') | Some(')')) {
+                    return Err(ParseError {
+                        pos: start,
+                        kind: ParseErrorKind::MissingOperand(op),
+                    });
+                }
+                Ok(Expr::Not(Box::new(self.unary()?)))
+            }
+            Some('(') => {
+                let unclosed = self.error(ParseErrorKind::UnclosedParen);
+                self.bump();
+                let expr = self.or()?;
+                if self.peek() != Some(')') {
+                    return Err(unclosed);
+                }
+                if is_empty(&expr) {
+                    return Err(ParseError {
+                        pos: unclosed.pos,
+                        kind: ParseErrorKind::MissingOperand('('),
+                    });
+                }
+                self.bump();
+                Ok(expr)
+            }
+            Some('"') => {
+                let unclosed = self.error(ParseErrorKind::UnclosedQuote);
+                self.bump();
+                let rest = &self.input[self.pos..];
+                let len = rest.find('"').ok_or(unclosed)?;
+                self.pos += len + 1;
+                Ok(Expr::Term(rest[..len].to_string()))
+            }
+            _ => {
+                let rest = &self.input[self.pos..];
+                let len = rest
+                    .find(|c: char| c.is_whitespace() || "|()\"".contains(c))
+                    .unwrap_or(rest.len());
+                self.pos += len;
+                Ok(Expr::Term(rest[..len].to_string()))
+            }
+        }
+    }
+}
+
+fn is_empty(expr: &Expr) -> bool {
+    matches!(expr, Expr::And(items) if items.is_empty())
+}
+
+/// 去掉开头的类型修饰符、`path:` 和 `pinyin:`，顺序任意，不区分大小写。
+/// 返回类型、是否匹配完整路径、是否按拼音匹配和其余部分。
+fn modifiers(mut sub: &str) -> (Kind, bool, bool, &str) {
+    let mut kind = Kind::Any;
+    let mut path = false;
+    let mut pinyin = false;
+    loop {
+        let (next, rest) = Kind::parse(sub);
+        if rest.len() < sub.len() {
+            kind = next;
+            sub = rest;
+            continue;
+        }
+        if let Some(rest) = strip_modifier(sub, "path:") {
+            path = true;
+            sub = rest.trim_start();
+        } else if let Some(rest) = strip_modifier(sub, "pinyin:") {
+            pinyin = true;
+            sub = rest.trim_start();
+        } else {
+            return (kind, path, pinyin, sub);
+        }
+    }
+}
+
+fn strip_modifier<'a>(sub: &'a str, modifier: &str) -> Option<&'a str> {
+    sub.get(..modifier.len())
+        .filter(|prefix| prefix.eq_ignore_ascii_case(modifier))
+        .map(|_| &sub[modifier.len()..])
+}
+
+fn build_regex(pattern: &str) -> Result<Regex, regex::Error> {
+    RegexBuilder::new(pattern).case_insensitive(true).build()
+}
+
+/// 折叠后的范围在 `original` 中对应的范围
+fn unfold_span(original: &str, span: Range<usize>) -> Range<usize> {
+    let (start, end) = unfold(original, span.start, span.end);
+    start..end
+}
+
+/// 把所有非空匹配的位置加入 `spans`，返回是否匹配
+fn regex_spans(regex: &Regex, haystack: &str, spans: &mut Vec<Range<usize>>) -> bool {
+    let mut matched = false;
+    for m in regex.find_iter(haystack) {
+        matched = true;
+        if !m.is_empty() {
+            spans.push(m.range());
+        }
+    }
+    matched
+}
+
+/// 编译后的查询，关键词已经折叠
+pub(super) struct Matcher {
+    pub kind: Kind,
+    /// 匹配折叠后的完整路径，此时不能按名称预筛选，需要检查所有名称
+    pub path: bool,
+    node: Node,
+    /// 所有匹配的名称（或完整路径）都包含的关键词，最长的在前
+    required: Vec<Finder<'static>>,
+    /// 查询只是一个关键词，预筛选的位置就是高亮的位置
+    single: bool,
+}
+
+enum Node {
+    Term(Box<Finder<'static>>),
+    Glob(Box<Glob>),
+    Pinyin(Box<Pinyin>),
+    Regex(Box<Regex>),
+    And(Vec<Node>),
+    Or(Vec<Node>),
+    Not(Box<Node>),
+}
+
+impl Matcher {
+    pub fn new(query: &Query) -> Self {
+        let node = Node::new(&query.expr, query.pinyin);
+        let mut required = Vec::new();
+        node.required(&mut required);
+        required.sort_by_key(|finder: &Finder| std::cmp::Reverse(finder.needle().len()));
+        Self {
+            kind: query.kind,
+            path: query.path,
+            single: !query.path && matches!(node, Node::Term(_)),
+            node,
+            required,
+        }
+    }
+
+    /// 解析失败时把整个输入作为一个关键词
+    pub fn parse(sub: &str) -> Self {
+        Self::new(&Query::parse(sub).unwrap_or_else(|_| Query::literal(sub)))
+    }
+
+    /// 名称必须包含的关键词，匹配完整路径时关键词可能在目录中，所以为空
+    fn name_required(&self) -> &[Finder<'static>] {
+        match self.path {
+            true => &[],
+            false => &self.required,
+        }
+    }
+
+    /// 用来在折叠名称区中预筛选的关键词，没有必须包含的关键词时为空，匹配所有名称
+    pub fn prefilter(&self) -> Finder<'static> {
+        match self.name_required().first() {
+            Some(finder) => finder.clone(),
+            None => Finder::new(b"").into_owned(),
+        }
+    }
+
+    /// 名称是否包含所有必须包含的关键词
+    pub fn has_required(&self, folded: &[u8]) -> bool {
+        self.name_required()
+            .iter()
+            .all(|f| f.find(folded).is_some())
+    }
+
+    /// 必须包含的关键词，折叠后的
+    pub fn required(&self) -> impl Iterator<Item = &[u8]> {
+        self.name_required().iter().map(Finder::needle)
+    }
+
+    /// 所有匹配都包含的关键词，匹配完整路径时用来按目录预筛选
+    pub fn literals(&self) -> &[Finder<'static>] {
+        &self.required
+    }
+
+    /// 检查预筛选时在 `offset` 处匹配的折叠名称（或完整路径），`original` 是折叠前的，
+    /// 匹配时返回在 `original` 中高亮的范围
+    pub fn eval(&self, folded: &[u8], original: &str, offset: usize) -> Option<Vec<Range<usize>>> {
+        let mut spans = Vec::new();
+        if self.single {
+            let len = self.required.first().map_or(0, |f| f.needle().len());
+            spans.push(unfold_span(original, offset..offset + len));
+        } else if !self.node.eval(folded, original, &mut spans) {
+            return None;
+        }
+        spans.retain(|span| !span.is_empty());
+        Some(spans)
+    }
+}
+
+impl Node {
+    fn new(expr: &Expr, pinyin: bool) -> Self {
+        let new = |item| Self::new(item, pinyin);
+        match expr {
+            Expr::Term(term) if Glob::is_glob(term) => Self::Glob(Box::new(Glob::new(term))),
+            Expr::Term(term) if pinyin && Pinyin::is_pinyin(term) => {
+                Self::Pinyin(Box::new(Pinyin::new(&fold(term))))
+            }
+            Expr::Term(term) => Self::Term(Box::new(Finder::new(&fold(term)).into_owned())),
+            Expr::And(items) => Self::And(items.iter().map(new).collect()),
+            Expr::Or(items) => Self::Or(items.iter().map(new).collect()),
+            Expr::Not(item) => Self::Not(Box::new(new(item))),
+            // 正则表达式在解析时已经检查过，无效时不匹配任何名称
+            Expr::Regex(pattern) => match build_regex(pattern) {
+                Ok(regex) => Self::Regex(Box::new(regex)),
+                Err(_) => Self::Or(Vec::new()),
+            },
+        }
+    }
+
+    fn required(&self, out: &mut Vec<Finder<'static>>) {
+        match self {
+            Self::Term(finder) => out.push((**finder).clone()),
+            // 通配符中的普通字符也必须包含
+            Self::Glob(glob) => match glob.longest_literal() {
+                "" => {}
+                literal => out.push(Finder::new(literal).into_owned()),
+            },
+            Self::And(items) => items.iter().for_each(|item| item.required(out)),
+            // 按拼音匹配时名称中可能没有关键词本身
+            Self::Pinyin(_) | Self::Regex(_) | Self::Or(_) | Self::Not(_) => {}
+        }
+    }
+
+    /// 匹配时把用到的关键词在 `original` 中的位置加入 `spans`，不匹配时 `spans` 保持不变。
+    ///
+    /// 正则表达式直接在 `original` 上匹配，其余的在 `folded` 上匹配，位置再映射回去。
+    fn eval(&self, folded: &[u8], original: &str, spans: &mut Vec<Range<usize>>) -> bool {
+        let mark = spans.len();
+        let matched = match self {
+            Self::Term(finder) => match finder.find(folded) {
+                Some(at) => {
+                    spans.push(unfold_span(original, at..at + finder.needle().len()));
+                    true
+                }
+                None => false,
+            },
+            // 折叠名称由 `str` 折叠而来
+            Self::Glob(glob) => {
+                let mark = spans.len();
+                let matched = std::str::from_utf8(folded)
+                    .is_ok_and(|name| glob.matches(name, original, spans));
+                for span in &mut spans[mark..] {
+                    *span = unfold_span(original, span.clone());
+                }
+                matched
+            }
+            Self::Pinyin(pinyin) => match std::str::from_utf8(folded)
+                .ok()
+                .and_then(|name| pinyin.find(name))
+            {
+                Some(span) => {
+                    spans.push(unfold_span(original, span));
+                    true
+                }
+                None => false,
+            },
+            // 在折叠前的名称上匹配，`\p{Lu}`、`ß` 等才有原来的含义
+            Self::Regex(regex) => regex_spans(regex, original, spans),
+            Self::And(items) => items.iter().all(|item| item.eval(folded, original, spans)),
+            // 每个匹配的分支都要高亮
+            Self::Or(items) => {
+                let mut any = false;
+                for item in items {
+                    any |= item.eval(folded, original, spans);
+                }
+                any
+            }
+            Self::Not(item) => !item.eval(folded, original, &mut Vec::new()),
+        };
+        if !matched {
+            spans.truncate(mark);
+        }
+        matched
+    }
+}
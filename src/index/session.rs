@@ -0,0 +1,91 @@
+use memchr::memmem;
+
+use super::{find::FindIter, query::Matcher, FullPath, Index, ParOptions};
+
+/// 一次输入过程中的查找。
+///
+/// 记住包含上一次查询中所有必须包含的关键词的名称，新的查询的每个这样的关键词
+/// 都包含在新的查询的某个关键词中时（如 `rep` → `report`、`rep` → `rep 2024`），
+/// 只重新检查这些名称以及之后新增的名称。索引同步后新增的名称会补充检查，
+/// 被删除的名称会被过滤；索引整理名称区后，或者换成另一个索引时，重新查找。
+#[derive(Default)]
+pub struct Session {
+    cache: Option<Cache>,
+}
+
+struct Cache {
+    /// 折叠后的必须包含的关键词
+    required: Vec<Vec<u8>>,
+    epoch: u64,
+    /// 查找时的名称个数，之后加入的名称还没有检查过
+    cells_len: u32,
+    /// 包含所有这些关键词的名称的序号，查询的其余部分在遍历时检查
+    cells: Vec<u32>,
+}
+
+impl Session {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// 与 [`Index::find_iter`] 的结果相同。
+    pub fn find_iter<'a>(&'a mut self, index: &'a Index, sub: &str) -> FindIter<'a> {
+        let cells = self.update(index, sub);
+        FindIter::with_cells(index, sub, cells)
+    }
+
+    /// 与 [`Index::par_find`] 的结果相同。
+    pub fn par_find<F>(&mut self, index: &Index, sub: &str, options: ParOptions, f: F) -> bool
+    where
+        F: FnMut(FullPath) -> bool,
+    {
+        let cells = self.update(index, sub);
+        index.par_find_cells(sub, cells, options, f)
+    }
+
+    /// 忘记上一次的结果
+    pub fn clear(&mut self) {
+        self.cache = None;
+    }
+
+    /// 上一次的关键词都在 `sub` 的关键词中时，`sub` 的匹配只会在上一次的结果中
+    fn update(&mut self, index: &Index, sub: &str) -> &[u32] {
+        let arena = &index.arena;
+        let matcher = Matcher::parse(sub);
+        let finder = matcher.prefilter();
+        let required: Vec<Vec<u8>> = matcher.required().map(<[u8]>::to_vec).collect();
+        let refines = |cache: &Cache| {
+            cache
+                .required
+                .iter()
+                .all(|old| required.iter().any(|new| memmem::find(new, old).is_some()))
+        };
+        let keep = |&cell: &u32| {
+            arena.find_cell(&finder, cell).is_some()
+                && matcher.has_required(arena.folded_name(cell))
+        };
+        let cells = match self.cache.take() {
+            Some(cache) if cache.epoch == arena.epoch() && refines(&cache) => {
+                let mut cells = cache.cells;
+                // 同时过滤掉已被删除的名称
+                cells.retain(keep);
+                let mut new = arena.find_cells(&finder, cache.cells_len);
+                new.retain(keep);
+                cells.extend(new);
+                cells
+            }
+            _ => {
+                let mut cells = arena.find_cells(&finder, 0);
+                cells.retain(keep);
+                cells
+            }
+        };
+        let cache = self.cache.insert(Cache {
+            epoch: arena.epoch(),
+            cells_len: arena.cells_len(),
+            cells,
+            required,
+        });
+        &cache.cells
+    }
+}
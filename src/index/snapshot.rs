@@ -0,0 +1,218 @@
+use anyhow::{bail, ensure, Context, Result};
+use log::debug;
+use std::{
+    fs::{self, File},
+    io::{BufReader, BufWriter, Read, Write},
+    path::Path,
+};
+
+use super::{FileInfo, Index};
+use crate::{
+    record::UsnRecord,
+    source::{Cursor, RecordSource},
+};
+
+const MAGIC: &[u8; 8] = b"FFDINDEX";
+/// 快照格式的版本，格式变化时递增，旧版本的快照会被丢弃
+const VERSION: u32 = 2;
+/// 名称和驱动器的最大长度，超过时视为损坏
+const MAX_STR_LEN: u32 = 4096;
+
+/// 快照的格式（小端序）：
+///
+/// ```text
+/// MAGIC VERSION:u32
+/// driver_len:u32 driver cursor.id:u64 cursor.usn:i64
+/// count:u64 { frn:u128 parent_frn:u128 name_len:u32 name info }*count
+/// checksum:u64
+/// ```
+///
+/// info 依次是 usn:i64 timestamp:i64 attributes:u32 source_info:u32 security_id:u32，
+/// 硬链接的每一项都带有同样的 info。checksum 是之前所有字节的 FNV-1a 哈希。
+impl Index {
+    /// 将索引写入快照。
+    pub fn save(&self, writer: impl Write) -> Result<()> {
+        let mut w = Hashed::new(writer);
+        w.write_all(MAGIC)?;
+        w.write_all(&VERSION.to_le_bytes())?;
+        write_str(&mut w, &self.driver)?;
+        w.write_all(&self.cursor.id.to_le_bytes())?;
+        w.write_all(&self.cursor.usn.to_le_bytes())?;
+
+        // 硬链接的每个名称各占一项，主名称在前
+        let (_, count) = self.arena.len();
+        w.write_all(&(count as u64).to_le_bytes())?;
+        for (frn, parent_frn, name, info) in self.arena.entries() {
+            w.write_all(&frn.to_le_bytes())?;
+            w.write_all(&parent_frn.to_le_bytes())?;
+            write_str(&mut w, name)?;
+            write_info(&mut w, info)?;
+        }
+
+        let checksum = w.hash;
+        w.inner.write_all(&checksum.to_le_bytes())?;
+        w.inner.flush()?;
+        Ok(())
+    }
+
+    /// 从快照中读取索引，格式、版本或校验和不符时返回错误。
+    pub fn load(reader: impl Read) -> Result<Self> {
+        let mut r = Hashed::new(reader);
+        let magic: [u8; 8] = read_array(&mut r)?;
+        ensure!(&magic == MAGIC, "不是索引快照");
+        let version = u32::from_le_bytes(read_array(&mut r)?);
+        ensure!(version == VERSION, "不支持的快照版本：{version}");
+        let driver = read_str(&mut r)?;
+        let cursor = Cursor {
+            id: u64::from_le_bytes(read_array(&mut r)?),
+            usn: i64::from_le_bytes(read_array(&mut r)?),
+        };
+
+        let count = u64::from_le_bytes(read_array(&mut r)?);
+        // 数量本身可能已经损坏，不能完全相信
+        let mut index = Self::with_capacity(driver, cursor, count.min(1 << 20) as usize);
+        for _ in 0..count {
+            let frn = u128::from_le_bytes(read_array(&mut r)?);
+            let parent_frn = u128::from_le_bytes(read_array(&mut r)?);
+            let filename = read_str(&mut r)?;
+            let info = read_info(&mut r)?;
+            index.add_link(UsnRecord {
+                frn,
+                parent_frn,
+                filename,
+                usn: info.usn,
+                timestamp: info.timestamp,
+                attributes: info.attributes,
+                source_info: info.source_info,
+                security_id: info.security_id,
+                ..Default::default()
+            });
+        }
+        index.shrink_to_fit();
+
+        let hash = r.hash;
+        let checksum = u64::from_le_bytes(read_array(&mut r.inner)?);
+        ensure!(hash == checksum, "快照校验和不符");
+        let mut rest = [0];
+        ensure!(r.inner.read(&mut rest)? == 0, "快照末尾有多余的数据");
+
+        Ok(index)
+    }
+
+    /// 将快照写入文件，先写到临时文件再替换，中途失败不会破坏旧的快照。
+    pub fn save_file(&self, path: impl AsRef<Path>) -> Result<()> {
+        let path = path.as_ref();
+        let tmp = path.with_extension("tmp");
+        self.save(BufWriter::new(File::create(&tmp)?))?;
+        fs::rename(&tmp, path)?;
+        Ok(())
+    }
+
+    pub fn load_file(path: impl AsRef<Path>) -> Result<Self> {
+        let path = path.as_ref();
+        Self::load(BufReader::new(File::open(path)?))
+            .with_context(|| format!("读取快照 {path:?} 失败"))
+    }
+
+    /// 读取快照并从记录的位置同步；快照不存在、已损坏或者已过期时，
+    /// 重新从 `src` 建立索引。变更流不会保留时（见 [`RecordSource::persistent`]）不读取快照。
+    pub fn load_or_build<S: RecordSource>(src: &S, path: impl AsRef<Path>) -> Result<Self> {
+        if !src.persistent() {
+            return Self::try_from_source(src);
+        }
+        match Self::load_file(path) {
+            Ok(mut index) if index.driver == src.driver() => {
+                index.sync_or_rebuild(src)?;
+                debug!("{} 盘索引共 {} 个文件", src.driver(), index.len());
+                return Ok(index);
+            }
+            Ok(index) => debug!("快照属于 {:?}，而不是 {:?}", index.driver, src.driver()),
+            Err(e) => debug!("{e:#}"),
+        }
+
+        Self::try_from_source(src)
+    }
+}
+
+/// 在读写的同时计算 FNV-1a 哈希
+struct Hashed<T> {
+    inner: T,
+    hash: u64,
+}
+
+impl<T> Hashed<T> {
+    fn new(inner: T) -> Self {
+        Self {
+            inner,
+            hash: 0xcbf2_9ce4_8422_2325,
+        }
+    }
+
+    fn update(&mut self, buf: &[u8]) {
+        for &b in buf {
+            self.hash ^= b as u64;
+            self.hash = self.hash.wrapping_mul(0x100_0000_01b3);
+        }
+    }
+}
+
+impl<W: Write> Write for Hashed<W> {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        let n = self.inner.write(buf)?;
+        self.update(&buf[..n]);
+        Ok(n)
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+impl<R: Read> Read for Hashed<R> {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        let n = self.inner.read(buf)?;
+        self.update(&buf[..n]);
+        Ok(n)
+    }
+}
+
+fn write_str(w: &mut impl Write, s: &str) -> Result<()> {
+    w.write_all(&(s.len() as u32).to_le_bytes())?;
+    w.write_all(s.as_bytes())?;
+    Ok(())
+}
+
+fn write_info(w: &mut impl Write, info: &FileInfo) -> Result<()> {
+    w.write_all(&info.usn.to_le_bytes())?;
+    w.write_all(&info.timestamp.to_le_bytes())?;
+    w.write_all(&info.attributes.bits().to_le_bytes())?;
+    w.write_all(&info.source_info.to_le_bytes())?;
+    w.write_all(&info.security_id.to_le_bytes())?;
+    Ok(())
+}
+
+fn read_array<const N: usize>(r: &mut impl Read) -> Result<[u8; N]> {
+    let mut buf = [0; N];
+    r.read_exact(&mut buf).context("快照被截断")?;
+    Ok(buf)
+}
+
+fn read_str(r: &mut impl Read) -> Result<String> {
+    let len = u32::from_le_bytes(read_array(r)?);
+    if len > MAX_STR_LEN {
+        bail!("快照中的名称过长：{len}B");
+    }
+    let mut buf = vec![0; len as usize];
+    r.read_exact(&mut buf).context("快照被截断")?;
+    Ok(String::from_utf8(buf)?)
+}
+
+fn read_info(r: &mut impl Read) -> Result<FileInfo> {
+    Ok(FileInfo {
+        usn: i64::from_le_bytes(read_array(r)?),
+        timestamp: i64::from_le_bytes(read_array(r)?),
+        attributes: u32::from_le_bytes(read_array(r)?).into(),
+        source_info: u32::from_le_bytes(read_array(r)?),
+        security_id: u32::from_le_bytes(read_array(r)?),
+    })
+}
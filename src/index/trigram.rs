@@ -0,0 +1,66 @@
+use std::{collections::HashMap, mem::size_of};
+
+use super::arena::hash_map_bytes;
+
+/// 折叠名称的三字节组到名称的倒排表。
+///
+/// 名称用它在折叠名称区中的序号表示，只会追加，所以每个列表都是递增的。
+/// 被删除的名称仍留在列表中，由查找时的检查过滤，整理名称区时重建。
+#[derive(Default)]
+pub(super) struct Trigrams {
+    postings: HashMap<u32, Vec<u32>>,
+}
+
+fn trigram(bytes: &[u8]) -> u32 {
+    u32::from_le_bytes([bytes[0], bytes[1], bytes[2], 0])
+}
+
+impl Trigrams {
+    /// 关键词短于三个字节时无法使用倒排表
+    pub const MIN_LEN: usize = 3;
+
+    /// 加入序号为 `cell` 的折叠名称
+    pub fn add(&mut self, cell: u32, folded: &[u8]) {
+        for window in folded.windows(3) {
+            let list = self.postings.entry(trigram(window)).or_default();
+            // 同一个名称中重复的三字节组只记一次
+            if list.last() != Some(&cell) {
+                list.push(cell);
+            }
+        }
+    }
+
+    /// 包含关键词所有三字节组的名称序号，按序号递增。
+    ///
+    /// 结果只是候选，还需要逐个确认。
+    pub fn candidates(&self, needle: &[u8]) -> Vec<u32> {
+        debug_assert!(needle.len() >= Self::MIN_LEN);
+        let mut keys: Vec<_> = needle.windows(3).map(trigram).collect();
+        keys.sort_unstable();
+        keys.dedup();
+        let mut lists = Vec::with_capacity(keys.len());
+        for key in keys {
+            match self.postings.get(&key) {
+                Some(list) => lists.push(list),
+                None => return Vec::new(),
+            }
+        }
+        // 从最短的列表开始，依次在其余列表中二分查找
+        lists.sort_by_key(|list| list.len());
+        let (first, rest) = lists.split_first().expect("关键词至少有一个三字节组");
+        first
+            .iter()
+            .copied()
+            .filter(|cell| rest.iter().all(|list| list.binary_search(cell).is_ok()))
+            .collect()
+    }
+
+    pub fn memory_usage(&self) -> usize {
+        hash_map_bytes(self.postings.capacity(), size_of::<(u32, Vec<u32>)>())
+            + self
+                .postings
+                .values()
+                .map(|list| list.capacity() * size_of::<u32>())
+                .sum::<usize>()
+    }
+}
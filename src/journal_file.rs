@@ -0,0 +1,121 @@
+use anyhow::{anyhow, Result};
+use log::debug;
+use std::{fs::File, io::Read, path::Path};
+
+use crate::record::{read_u16, read_u32, UsnRecord};
+
+/// 最长的记录，超过时视为损坏
+const MAX_RECORD_LENGTH: usize = 64 * 1024;
+
+/// 逐条解析从卷上导出的 `$UsnJrnl:$J` 数据流，每条记录的 `usn` 和 `timestamp` 都来自日志。
+///
+/// 被回收的日志在 $J 开头表现为大片的 0，页尾也会以 0 填充，这些都会被跳过；
+/// 遇到损坏的数据时按 8 字节对齐向后寻找下一条有效记录。
+/// V4 记录只描述数据范围的变化，不含名称，同一次变更总有对应的 V2/V3 记录，所以也会被跳过。
+pub struct JournalFile<R> {
+    reader: R,
+    buf: Vec<u8>,
+    start: usize,
+    end: usize,
+    /// `buf[start]` 在 $J 中的偏移
+    offset: u64,
+    eof: bool,
+}
+
+impl JournalFile<File> {
+    pub fn open(path: impl AsRef<Path>) -> Result<Self> {
+        Ok(Self::new(File::open(path)?))
+    }
+}
+
+impl<R: Read> JournalFile<R> {
+    pub fn new(reader: R) -> Self {
+        Self {
+            reader,
+            buf: vec![0; 1024 * 1024],
+            start: 0,
+            end: 0,
+            offset: 0,
+            eof: false,
+        }
+    }
+
+    /// 保证缓冲区中至少有 `n` 字节，到达末尾时返回 `false`。
+    fn fill(&mut self, n: usize) -> Result<bool> {
+        while self.end - self.start < n && !self.eof {
+            if self.start > 0 {
+                self.buf.copy_within(self.start..self.end, 0);
+                self.end -= self.start;
+                self.start = 0;
+            }
+            let read = self.reader.read(&mut self.buf[self.end..])?;
+            self.eof = read == 0;
+            self.end += read;
+        }
+        Ok(self.end - self.start >= n)
+    }
+
+    fn advance(&mut self, n: usize) {
+        self.start += n;
+        self.offset += n as u64;
+    }
+}
+
+impl<R: Read> Iterator for JournalFile<R> {
+    type Item = Result<UsnRecord>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            match self.fill(8) {
+                Ok(true) => {}
+                // 末尾不足 8 字节的部分只可能是填充
+                Ok(false) => return None,
+                Err(e) => return Some(Err(e)),
+            }
+
+            let buf = &self.buf[self.start..self.end];
+            let len = read_u32(buf, 0) as usize;
+            if len == 0 {
+                let zeros = buf.chunks_exact(8).take_while(|w| w == &[0; 8]).count();
+                self.advance(zeros.max(1) * 8);
+                continue;
+            }
+
+            let major = read_u16(buf, 4);
+            if !len.is_multiple_of(8) || len > MAX_RECORD_LENGTH || !(2..=4).contains(&major) {
+                debug!("JournalFile：{} 处的记录无效", self.offset);
+                self.advance(8);
+                continue;
+            }
+
+            match self.fill(len) {
+                Ok(true) => {}
+                Ok(false) => {
+                    let offset = self.offset;
+                    self.advance(self.end - self.start);
+                    return Some(Err(anyhow!("{offset} 处的记录被截断")));
+                }
+                Err(e) => return Some(Err(e)),
+            }
+
+            let offset = self.offset;
+            let buf = &self.buf[self.start..self.start + len];
+            let res = match major {
+                4 => None,
+                _ => Some(UsnRecord::parse(buf).map(|(record, _)| record)),
+            };
+            match res {
+                Some(Ok(record)) => {
+                    self.advance(len);
+                    return Some(Ok(record));
+                }
+                // 长度也可能是损坏的，从下一个 8 字节处重新寻找
+                Some(Err(e)) => {
+                    debug!("JournalFile：{offset} 处的记录无效 {e}");
+                    self.advance(8);
+                }
+                None => self.advance(len),
+            }
+        }
+    }
+}
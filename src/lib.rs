@@ -1,5 +1,23 @@
+mod attributes;
 mod index;
+mod journal_file;
+#[cfg(target_os = "linux")]
+mod linux;
+#[cfg(windows)]
 mod ntfs;
+mod ntfs_image;
+mod reason;
+mod record;
+mod source;
 
+pub use attributes::*;
 pub use index::*;
+pub use journal_file::*;
+#[cfg(target_os = "linux")]
+pub use linux::*;
+#[cfg(windows)]
 pub use ntfs::*;
+pub use ntfs_image::*;
+pub use reason::*;
+pub use record::*;
+pub use source::*;
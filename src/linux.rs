@@ -0,0 +1,238 @@
+mod dir_records;
+mod fanotify;
+mod inotify;
+mod watcher;
+
+use anyhow::{anyhow, ensure, Result};
+use log::debug;
+use std::{
+    collections::VecDeque,
+    fs,
+    os::unix::fs::MetadataExt,
+    path::{Path, PathBuf},
+    sync::Mutex,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+pub use dir_records::DirRecords;
+pub use watcher::MountChanges;
+
+use crate::{
+    attributes::FileAttributes,
+    record::{to_filetime, UsnRecord},
+    source::{Cursor, JournalRange, RecordSource},
+};
+use fanotify::Fanotify;
+use inotify::Inotify;
+use watcher::Watcher;
+
+/// 支持的文件系统，它们的 inode 号在同一个设备内唯一且稳定
+const SUPPORTED_FS: [&str; 6] = ["ext2", "ext3", "ext4", "btrfs", "xfs", "f2fs"];
+
+/// 一个挂载点（或其中的某个目录）下的文件树。
+///
+/// inode 号相当于 NTFS 的 FRN，父目录的 inode 号相当于 parent_frn，
+/// 遍历时不会跨越挂载边界。
+///
+/// 打开时即开始监听变更：整个文件系统优先使用 fanotify，
+/// 没有权限或者只是其中的某个目录时退回到 inotify。
+pub struct Mount {
+    driver: String,
+    dev: u64,
+    /// 本次监听的 ID，重新打开后会改变
+    id: u64,
+    state: Mutex<State>,
+}
+
+struct State {
+    watcher: Watcher,
+    /// 已读取但还未被取走的记录
+    records: VecDeque<UsnRecord>,
+    usn: i64,
+}
+
+impl Mount {
+    pub fn open(driver: String) -> Result<Self> {
+        let meta = fs::metadata(&driver)?;
+        ensure!(meta.is_dir(), "不是目录：{}", driver);
+        let path = Path::new(&driver).canonicalize()?;
+        let info = find_mount(&path)?;
+        ensure!(
+            SUPPORTED_FS.contains(&&*info.fs),
+            "不支持的文件系统：{}",
+            info.fs
+        );
+
+        let watcher = if info.mount_point == path {
+            match Fanotify::new(&path) {
+                Ok(fanotify) => Watcher::Fanotify(fanotify),
+                Err(e) => {
+                    debug!("Mount({driver:?})：无法使用 fanotify {e}");
+                    Watcher::Inotify(Inotify::new(meta.dev())?)
+                }
+            }
+        } else {
+            Watcher::Inotify(Inotify::new(meta.dev())?)
+        };
+
+        Ok(Self {
+            dev: meta.dev(),
+            id: SystemTime::now().duration_since(UNIX_EPOCH)?.as_nanos() as u64,
+            state: Mutex::new(State {
+                watcher,
+                records: VecDeque::new(),
+                usn: 0,
+            }),
+            driver,
+        })
+    }
+
+    pub fn dir_records(&self) -> DirRecords<'_> {
+        DirRecords::new(self)
+    }
+
+    pub fn driver(&self) -> &str {
+        &self.driver
+    }
+
+    /// 遍历时每进入一个目录都要调用，inotify 需要逐个目录监听。
+    fn watch_dir(&self, ino: u64, path: &Path) {
+        if let Watcher::Inotify(inotify) = &mut self.state.lock().unwrap().watcher {
+            inotify.watch(ino, path);
+        }
+    }
+}
+
+impl RecordSource for Mount {
+    type Records<'a> = DirRecords<'a>;
+    type Changes<'a> = MountChanges<'a>;
+
+    fn driver(&self) -> &str {
+        &self.driver
+    }
+
+    fn cursor(&self) -> Result<Cursor> {
+        Ok(Cursor {
+            id: self.id,
+            usn: self.state.lock().unwrap().usn,
+        })
+    }
+
+    /// 事件读取后即被消耗，只能从当前位置继续
+    fn range(&self) -> Result<JournalRange> {
+        let usn = self.state.lock().unwrap().usn;
+        Ok(JournalRange {
+            id: self.id,
+            lowest_valid_usn: usn,
+            next_usn: usn,
+        })
+    }
+
+    /// 只在打开期间监听变更
+    fn persistent(&self) -> bool {
+        false
+    }
+
+    fn records(&self) -> Self::Records<'_> {
+        self.dir_records()
+    }
+
+    fn changes_from(&self, cursor: Cursor) -> Self::Changes<'_> {
+        MountChanges::new(self, cursor)
+    }
+}
+
+/// 事件产生的记录，时间为读取事件的时间。
+fn event_record(frn: u64, parent_frn: u64, filename: String, is_dir: bool) -> UsnRecord {
+    UsnRecord {
+        frn: frn.into(),
+        parent_frn: parent_frn.into(),
+        attributes: attributes(&filename, is_dir),
+        timestamp: to_filetime(SystemTime::now()),
+        filename,
+        ..Default::default()
+    }
+}
+
+/// 没有隐藏属性，按照惯例以 `.` 开头的文件视为隐藏
+fn attributes(filename: &str, is_dir: bool) -> FileAttributes {
+    let mut attributes = FileAttributes::empty();
+    if is_dir {
+        attributes |= FileAttributes::DIRECTORY;
+    }
+    if filename.starts_with('.') {
+        attributes |= FileAttributes::HIDDEN;
+    }
+    attributes
+}
+
+/// 扫描所有支持的本地文件系统的挂载点，同一设备只取第一个挂载点。
+pub fn scan_mounts() -> Vec<String> {
+    let mut res = Vec::new();
+    let mut devs = Vec::new();
+    for info in mount_infos().unwrap_or_default() {
+        if SUPPORTED_FS.contains(&&*info.fs) && !devs.contains(&info.dev) {
+            devs.push(info.dev);
+            res.push(info.mount_point.to_string_lossy().into_owned());
+        }
+    }
+    res
+}
+
+struct MountInfo {
+    dev: String,
+    mount_point: PathBuf,
+    fs: String,
+}
+
+// https://man7.org/linux/man-pages/man5/proc_pid_mountinfo.5.html
+fn mount_infos() -> Result<Vec<MountInfo>> {
+    let content = fs::read_to_string("/proc/self/mountinfo")?;
+    let mut res = Vec::new();
+    for line in content.lines() {
+        // 36 35 98:0 /mnt1 /mnt2 rw,noatime master:1 - ext3 /dev/root rw,errors=continue
+        let (left, right) = line
+            .split_once(" - ")
+            .ok_or_else(|| anyhow!("无法解析 mountinfo：{line}"))?;
+        let mut left = left.split(' ');
+        let mut right = right.split(' ');
+        let (Some(dev), Some(mount_point), Some(fs)) = (left.nth(2), left.nth(1), right.next())
+        else {
+            return Err(anyhow!("无法解析 mountinfo：{line}"));
+        };
+        res.push(MountInfo {
+            dev: dev.to_string(),
+            mount_point: unescape(mount_point).into(),
+            fs: fs.to_string(),
+        });
+    }
+    Ok(res)
+}
+
+/// 查找路径所在的挂载点，后出现的挂载会覆盖先前的。
+fn find_mount(path: &Path) -> Result<MountInfo> {
+    mount_infos()?
+        .into_iter()
+        .filter(|info| path.starts_with(&info.mount_point))
+        .max_by_key(|info| info.mount_point.components().count())
+        .ok_or_else(|| anyhow!("找不到挂载点：{}", path.display()))
+}
+
+/// mountinfo 中的空格、制表符、换行和反斜杠会被转义为 `\ooo` 形式。
+fn unescape(s: &str) -> String {
+    let mut res = Vec::with_capacity(s.len());
+    let bytes = s.as_bytes();
+    let mut i = 0;
+    while i < bytes.len() {
+        if bytes[i] == b'\\' {
+            if let Some(Ok(ch)) = s.get(i + 1..i + 4).map(|o| u8::from_str_radix(o, 8)) {
+                res.push(ch);
+                i += 4;
+                continue;
+            }
+        }
+        res.push(bytes[i]);
+        i += 1;
+    }
+    String::from_utf8_lossy(&res).into_owned()
+}
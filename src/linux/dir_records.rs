@@ -0,0 +1,102 @@
+use anyhow::Result;
+use log::debug;
+use std::{
+    fs::{self, ReadDir},
+    os::unix::fs::{DirEntryExt, MetadataExt},
+    path::Path,
+};
+
+use super::{attributes, Mount};
+use crate::record::UsnRecord;
+
+/// 深度优先遍历目录树，逐条产生文件记录。
+///
+/// 无法读取的子目录会被跳过，其他设备上的目录（挂载点、btrfs 子卷）不会进入。
+pub struct DirRecords<'a> {
+    mount: &'a Mount,
+    /// 正在遍历的目录及其 inode 号
+    dirs: Vec<(u64, ReadDir)>,
+    err: Option<anyhow::Error>,
+}
+
+impl<'a> DirRecords<'a> {
+    pub fn new(mount: &'a Mount) -> Self {
+        let mut res = Self {
+            mount,
+            dirs: Vec::new(),
+            err: None,
+        };
+        match fs::metadata(mount.driver()).and_then(|meta| {
+            mount.watch_dir(meta.ino(), Path::new(mount.driver()));
+            let dir = fs::read_dir(mount.driver())?;
+            Ok((meta.ino(), dir))
+        }) {
+            Ok(dir) => res.dirs.push(dir),
+            Err(e) => res.err = Some(e.into()),
+        }
+        res
+    }
+
+    /// 进入子目录，无法读取的目录本身仍会被记录。
+    fn enter(&mut self, frn: u64, path: &Path) {
+        // 先监听再读取，以免漏掉期间的变更
+        self.mount.watch_dir(frn, path);
+        match fs::read_dir(path) {
+            Ok(dir) => self.dirs.push((frn, dir)),
+            Err(e) => debug!("Mount({:?})：跳过 {path:?} {e}", self.mount.driver()),
+        }
+    }
+}
+
+impl Iterator for DirRecords<'_> {
+    type Item = Result<UsnRecord>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if let Some(e) = self.err.take() {
+            return Some(Err(e));
+        }
+
+        loop {
+            let (parent_frn, dir) = self.dirs.last_mut()?;
+            let parent_frn = *parent_frn;
+            let entry = match dir.next() {
+                Some(Ok(entry)) => entry,
+                Some(Err(e)) => {
+                    debug!("Mount({:?})：读取目录项失败 {e}", self.mount.driver());
+                    continue;
+                }
+                None => {
+                    self.dirs.pop();
+                    continue;
+                }
+            };
+
+            let frn = entry.ino();
+            let is_dir = entry.file_type().is_ok_and(|ty| ty.is_dir());
+            if is_dir {
+                match entry.metadata() {
+                    Ok(meta) if meta.dev() != self.mount.dev => continue,
+                    Ok(_) => self.enter(frn, &entry.path()),
+                    Err(e) => {
+                        debug!(
+                            "Mount({:?})：跳过 {:?} {e}",
+                            self.mount.driver(),
+                            entry.path()
+                        );
+                        continue;
+                    }
+                }
+            }
+
+            let filename = entry.file_name().to_string_lossy().into_owned();
+            // 与枚举 MFT 一样不带时间，以免逐个读取元数据
+            return Some(Ok(UsnRecord {
+                frn: frn.into(),
+                parent_frn: parent_frn.into(),
+                attributes: attributes(&filename, is_dir),
+                filename,
+                ..Default::default()
+            }));
+        }
+    }
+}
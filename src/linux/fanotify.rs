@@ -0,0 +1,223 @@
+use anyhow::Result;
+use log::debug;
+use std::{
+    collections::VecDeque,
+    ffi::CString,
+    io,
+    mem::{self, MaybeUninit},
+    os::{
+        fd::{AsRawFd, FromRawFd, OwnedFd},
+        unix::ffi::OsStrExt,
+    },
+    path::Path,
+    ptr,
+};
+
+use super::event_record;
+use crate::{reason::UsnReason, record::UsnRecord, source::IndexStale};
+
+/// 监听整个文件系统的目录项变更，需要 CAP_SYS_ADMIN 权限和 5.17 以上的内核。
+///
+/// 事件中带有父目录和目标的文件句柄，可以直接从中解析出 inode 号。
+pub struct Fanotify {
+    fd: OwnedFd,
+    fsid: [i32; 2],
+    buf: Box<[u8]>,
+}
+
+impl Fanotify {
+    pub fn new(path: &Path) -> Result<Self> {
+        // https://man7.org/linux/man-pages/man2/fanotify_init.2.html
+        let fd = unsafe {
+            libc::fanotify_init(
+                libc::FAN_CLASS_NOTIF
+                    | libc::FAN_CLOEXEC
+                    | libc::FAN_NONBLOCK
+                    | libc::FAN_REPORT_DFID_NAME
+                    | libc::FAN_REPORT_TARGET_FID,
+                libc::O_RDONLY as _,
+            )
+        };
+        if fd < 0 {
+            return Err(io::Error::last_os_error().into());
+        }
+        let fd = unsafe { OwnedFd::from_raw_fd(fd) };
+
+        let path = CString::new(path.as_os_str().as_bytes())?;
+        // FAN_MOVED_FROM 只用来得知重命名前的名称，移出文件系统只能通过复制加删除完成
+        let ret = unsafe {
+            libc::fanotify_mark(
+                fd.as_raw_fd(),
+                libc::FAN_MARK_ADD | libc::FAN_MARK_FILESYSTEM,
+                libc::FAN_CREATE
+                    | libc::FAN_DELETE
+                    | libc::FAN_MOVED_FROM
+                    | libc::FAN_MOVED_TO
+                    | libc::FAN_ONDIR,
+                libc::AT_FDCWD,
+                path.as_ptr(),
+            )
+        };
+        if ret < 0 {
+            return Err(io::Error::last_os_error().into());
+        }
+
+        // btrfs 的每个子卷有不同的 fsid，用它过滤掉其他子卷的事件
+        let mut stat = MaybeUninit::<libc::statfs>::uninit();
+        if unsafe { libc::statfs(path.as_ptr(), stat.as_mut_ptr()) } < 0 {
+            return Err(io::Error::last_os_error().into());
+        }
+        let fsid = unsafe { mem::transmute::<libc::fsid_t, [i32; 2]>(stat.assume_init().f_fsid) };
+
+        Ok(Self {
+            fd,
+            fsid,
+            buf: vec![0; 64 * 1024].into_boxed_slice(),
+        })
+    }
+
+    /// 读取所有已到达的事件，转换为文件记录。
+    pub fn read(&mut self, records: &mut VecDeque<UsnRecord>) -> Result<()> {
+        loop {
+            let len = unsafe {
+                libc::read(
+                    self.fd.as_raw_fd(),
+                    self.buf.as_mut_ptr() as _,
+                    self.buf.len(),
+                )
+            };
+            if len < 0 {
+                let e = io::Error::last_os_error();
+                if e.kind() == io::ErrorKind::WouldBlock {
+                    return Ok(());
+                }
+                return Err(e.into());
+            }
+
+            let mut offset = 0;
+            while offset < len as usize {
+                let meta: libc::fanotify_event_metadata =
+                    unsafe { ptr::read_unaligned(self.buf[offset..].as_ptr() as _) };
+                let event = &self.buf[offset..offset + meta.event_len as usize];
+                offset += meta.event_len as usize;
+
+                if meta.mask & libc::FAN_Q_OVERFLOW != 0 {
+                    return Err(IndexStale::Overflow.into());
+                }
+                self.parse(&meta, &event[meta.metadata_len as usize..], records);
+            }
+        }
+    }
+
+    fn parse(
+        &self,
+        meta: &libc::fanotify_event_metadata,
+        mut infos: &[u8],
+        records: &mut VecDeque<UsnRecord>,
+    ) {
+        let mut dir = None;
+        let mut target = None;
+        while infos.len() >= 4 {
+            let info_type = infos[0];
+            let len = u16::from_ne_bytes([infos[2], infos[3]]) as usize;
+            let Some(info) = infos.get(..len) else {
+                break;
+            };
+            infos = &infos[len..];
+
+            let Some(fid) = Fid::parse(info) else {
+                continue;
+            };
+            if fid.fsid != self.fsid {
+                return;
+            }
+            match info_type {
+                libc::FAN_EVENT_INFO_TYPE_DFID_NAME => dir = Some(fid),
+                libc::FAN_EVENT_INFO_TYPE_FID => target = Some(fid),
+                _ => {}
+            }
+        }
+
+        let (Some(dir), Some(target)) = (dir, target) else {
+            debug!("fanotify：缺少文件句柄的事件 {:#x}", meta.mask);
+            return;
+        };
+        let (Some(parent_frn), Some(frn)) = (dir.ino, target.ino) else {
+            debug!("fanotify：无法解析的文件句柄 {:?}", dir.name);
+            return;
+        };
+
+        // 同一目标的多个事件可能被合并，按照发生的先后顺序展开
+        // 旧名称的记录与 NTFS 一样不带 CLOSE
+        for (mask, reason) in [
+            (libc::FAN_CREATE, UsnReason::FILE_CREATE | UsnReason::CLOSE),
+            (libc::FAN_MOVED_FROM, UsnReason::RENAME_OLD_NAME),
+            (
+                libc::FAN_MOVED_TO,
+                UsnReason::RENAME_NEW_NAME | UsnReason::CLOSE,
+            ),
+            (libc::FAN_DELETE, UsnReason::FILE_DELETE | UsnReason::CLOSE),
+        ] {
+            if meta.mask & mask != 0 {
+                let is_dir = meta.mask & libc::FAN_ONDIR != 0;
+                if mask == libc::FAN_MOVED_TO {
+                    // 移动会替换掉新名称原有的文件，由索引按父目录和名称找到它
+                    let unknown = UsnRecord::UNKNOWN_FRN as u64;
+                    records.push_back(UsnRecord {
+                        reason: UsnReason::FILE_DELETE | UsnReason::CLOSE,
+                        ..event_record(unknown, parent_frn, dir.name.clone(), is_dir)
+                    });
+                }
+                records.push_back(UsnRecord {
+                    reason,
+                    ..event_record(frn, parent_frn, dir.name.clone(), is_dir)
+                });
+            }
+        }
+    }
+}
+
+/// fanotify_event_info_fid 中的文件标识
+struct Fid {
+    fsid: [i32; 2],
+    ino: Option<u64>,
+    /// 仅 FAN_EVENT_INFO_TYPE_DFID_NAME 带有名称
+    name: String,
+}
+
+impl Fid {
+    // https://man7.org/linux/man-pages/man7/fanotify.7.html
+    fn parse(info: &[u8]) -> Option<Self> {
+        // info 头 4 字节，fsid 8 字节，file_handle 头 8 字节
+        let int = |offset: usize| {
+            Some(i32::from_ne_bytes(
+                info.get(offset..offset + 4)?.try_into().ok()?,
+            ))
+        };
+        let fsid = [int(4)?, int(8)?];
+        let handle_bytes = int(12)? as usize;
+        let handle_type = int(16)?;
+        let handle = info.get(20..20 + handle_bytes)?;
+
+        let name = &info[20 + handle_bytes..];
+        let name = name.split(|&b| b == 0).next().unwrap_or_default();
+        Some(Self {
+            fsid,
+            ino: handle_ino(handle_type, handle),
+            name: String::from_utf8_lossy(name).into_owned(),
+        })
+    }
+}
+
+/// 从文件句柄中解析出 inode 号，句柄的格式由文件系统决定。
+fn handle_ino(handle_type: i32, handle: &[u8]) -> Option<u64> {
+    match handle_type {
+        // FILEID_INO32_GEN(_PARENT)：ext2/3/4、f2fs 等
+        1 | 2 => Some(u32::from_ne_bytes(handle.get(..4)?.try_into().ok()?) as u64),
+        // FILEID_BTRFS_*：objectid 即子卷内的 inode 号
+        0x4d..=0x4f => Some(u64::from_ne_bytes(handle.get(..8)?.try_into().ok()?)),
+        // FILEID_INO64_GEN(_PARENT)：xfs
+        0x81 | 0x82 => Some(u64::from_ne_bytes(handle.get(..8)?.try_into().ok()?)),
+        _ => None,
+    }
+}
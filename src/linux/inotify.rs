@@ -0,0 +1,267 @@
+use anyhow::Result;
+use log::{debug, warn};
+use std::{
+    collections::{HashMap, VecDeque},
+    ffi::CString,
+    fs, io, mem,
+    os::{
+        fd::{AsRawFd, FromRawFd, OwnedFd},
+        unix::{ffi::OsStrExt, fs::MetadataExt},
+    },
+    path::{Path, PathBuf},
+    ptr,
+};
+
+use super::event_record;
+use crate::{reason::UsnReason, record::UsnRecord, source::IndexStale};
+
+const MASK: u32 = libc::IN_CREATE
+    | libc::IN_DELETE
+    | libc::IN_MOVED_FROM
+    | libc::IN_MOVED_TO
+    | libc::IN_ONLYDIR
+    | libc::IN_DONT_FOLLOW;
+
+/// 删除和移动事件只带有名称，产生的记录不带 inode 号，由索引按父目录和名称查找
+const UNKNOWN: u64 = UsnRecord::UNKNOWN_FRN as u64;
+
+struct Dir {
+    wd: i32,
+    path: PathBuf,
+}
+
+/// 逐个目录监听变更，不需要特殊权限，但受 max_user_watches 限制。
+pub struct Inotify {
+    fd: OwnedFd,
+    dev: u64,
+    dirs: HashMap<u64, Dir>,
+    wds: HashMap<i32, u64>,
+    /// 等待配对的 IN_MOVED_FROM：cookie => (原目录, 原名称, 是否为目录)
+    moves: HashMap<u32, (u64, String, bool)>,
+    buf: Box<[u8]>,
+}
+
+impl Inotify {
+    pub fn new(dev: u64) -> Result<Self> {
+        let fd = unsafe { libc::inotify_init1(libc::IN_CLOEXEC | libc::IN_NONBLOCK) };
+        if fd < 0 {
+            return Err(io::Error::last_os_error().into());
+        }
+
+        Ok(Self {
+            fd: unsafe { OwnedFd::from_raw_fd(fd) },
+            dev,
+            dirs: HashMap::new(),
+            wds: HashMap::new(),
+            moves: HashMap::new(),
+            buf: vec![0; 64 * 1024].into_boxed_slice(),
+        })
+    }
+
+    /// 监听目录，需要在读取目录内容之前调用，以免漏掉期间的变更。
+    pub fn watch(&mut self, ino: u64, path: &Path) {
+        let wd = match CString::new(path.as_os_str().as_bytes()) {
+            Ok(cpath) => unsafe {
+                libc::inotify_add_watch(self.fd.as_raw_fd(), cpath.as_ptr(), MASK)
+            },
+            Err(_) => return,
+        };
+        if wd < 0 {
+            warn!("inotify：无法监听 {path:?} {}", io::Error::last_os_error());
+            return;
+        }
+
+        self.wds.insert(wd, ino);
+        self.dirs.insert(
+            ino,
+            Dir {
+                wd,
+                path: path.to_path_buf(),
+            },
+        );
+    }
+
+    /// 读取所有已到达的事件，转换为文件记录。
+    pub fn read(&mut self, records: &mut VecDeque<UsnRecord>) -> Result<()> {
+        loop {
+            let len = unsafe {
+                libc::read(
+                    self.fd.as_raw_fd(),
+                    self.buf.as_mut_ptr() as _,
+                    self.buf.len(),
+                )
+            };
+            if len < 0 {
+                let e = io::Error::last_os_error();
+                if e.kind() != io::ErrorKind::WouldBlock {
+                    return Err(e.into());
+                }
+                break;
+            }
+
+            let mut offset = 0;
+            while offset < len as usize {
+                let event: libc::inotify_event =
+                    unsafe { ptr::read_unaligned(self.buf[offset..].as_ptr() as _) };
+                offset += size_of::<libc::inotify_event>();
+                let name = &self.buf[offset..offset + event.len as usize];
+                let name = name.split(|&b| b == 0).next().unwrap_or_default();
+                let name = String::from_utf8_lossy(name).into_owned();
+                offset += event.len as usize;
+
+                if event.mask & libc::IN_Q_OVERFLOW != 0 {
+                    return Err(IndexStale::Overflow.into());
+                }
+                self.handle(&event, name, records);
+            }
+        }
+
+        // 没有配对的 IN_MOVED_FROM 说明被移出了监听范围，或者移进了新目录，
+        // 事件发生时新目录还没有被监听。后者在遍历新目录时已经添加了新名称，
+        // 此时删除旧名称只会删除这一个名称；前者连同目录中的内容一起删除
+        for (dir, name, is_dir) in mem::take(&mut self.moves).into_values() {
+            if is_dir {
+                if let Some(path) = self.dirs.get(&dir).map(|d| d.path.join(&name)) {
+                    self.unwatch(&path);
+                }
+            }
+            records.push_back(record(UNKNOWN, dir, name, is_dir, UsnReason::FILE_DELETE));
+        }
+        Ok(())
+    }
+
+    fn handle(
+        &mut self,
+        event: &libc::inotify_event,
+        name: String,
+        records: &mut VecDeque<UsnRecord>,
+    ) {
+        if event.mask & libc::IN_IGNORED != 0 {
+            if let Some(ino) = self.wds.remove(&event.wd) {
+                self.dirs.remove(&ino);
+            }
+            return;
+        }
+        let Some(&parent) = self.wds.get(&event.wd) else {
+            return;
+        };
+        let is_dir = event.mask & libc::IN_ISDIR != 0;
+
+        if event.mask & libc::IN_MOVED_FROM != 0 {
+            self.moves.insert(event.cookie, (parent, name, is_dir));
+        } else if event.mask & libc::IN_MOVED_TO != 0 {
+            // 移动会替换掉新名称原有的文件
+            records.push_back(record(
+                UNKNOWN,
+                parent,
+                name.clone(),
+                is_dir,
+                UsnReason::FILE_DELETE,
+            ));
+            let Some((old_parent, old_name, is_dir)) = self.moves.remove(&event.cookie) else {
+                // 从监听范围外移入，视为新建
+                self.created(parent, name, records);
+                return;
+            };
+            // 新名称需要 inode 号，已经不存在时只删除旧名称
+            let path = self.dirs.get(&parent).map(|d| d.path.join(&name));
+            let Some(meta) = path.and_then(|path| fs::symlink_metadata(path).ok()) else {
+                let reason = UsnReason::FILE_DELETE;
+                records.push_back(record(UNKNOWN, old_parent, old_name, is_dir, reason));
+                return;
+            };
+            let ino = meta.ino();
+            // 硬链接需要知道被替换的是哪个名称
+            records.push_back(UsnRecord {
+                reason: UsnReason::RENAME_OLD_NAME,
+                ..event_record(UNKNOWN, old_parent, old_name, is_dir)
+            });
+            if is_dir {
+                self.moved(ino, parent, &name);
+            }
+            records.push_back(record(
+                ino,
+                parent,
+                name,
+                is_dir,
+                UsnReason::RENAME_NEW_NAME,
+            ));
+        } else if event.mask & libc::IN_CREATE != 0 {
+            self.created(parent, name, records);
+        } else if event.mask & libc::IN_DELETE != 0 {
+            records.push_back(record(
+                UNKNOWN,
+                parent,
+                name,
+                is_dir,
+                UsnReason::FILE_DELETE,
+            ));
+        }
+    }
+
+    /// 记录新建的文件，新目录中可能已经有了内容，需要一并遍历。
+    fn created(&mut self, parent: u64, name: String, records: &mut VecDeque<UsnRecord>) {
+        let mut stack = vec![(parent, name)];
+        while let Some((parent, name)) = stack.pop() {
+            let Some(path) = self.dirs.get(&parent).map(|d| d.path.join(&name)) else {
+                continue;
+            };
+            let Ok(meta) = fs::symlink_metadata(&path) else {
+                debug!("inotify：{path:?} 已不存在");
+                continue;
+            };
+            let ino = meta.ino();
+            let is_dir = meta.is_dir();
+            records.push_back(record(ino, parent, name, is_dir, UsnReason::FILE_CREATE));
+
+            if is_dir && meta.dev() == self.dev {
+                self.watch(ino, &path);
+                for entry in fs::read_dir(&path).into_iter().flatten().flatten() {
+                    stack.push((ino, entry.file_name().to_string_lossy().into_owned()));
+                }
+            }
+        }
+    }
+
+    /// 目录移动后更新它和所有子目录的路径。
+    fn moved(&mut self, ino: u64, parent: u64, name: &str) {
+        let Some(new) = self.dirs.get(&parent).map(|d| d.path.join(name)) else {
+            return;
+        };
+        let Some(old) = self.dirs.get(&ino).map(|d| d.path.clone()) else {
+            return;
+        };
+        for dir in self.dirs.values_mut() {
+            if let Ok(rest) = dir.path.strip_prefix(&old) {
+                dir.path = new.join(rest);
+            }
+        }
+    }
+
+    /// 停止监听路径为 `path` 的目录及其子目录，其中的记录由索引删除目录时一并删除。
+    fn unwatch(&mut self, path: &Path) {
+        let fd = self.fd.as_raw_fd();
+        let wds = &mut self.wds;
+        self.dirs.retain(|_, dir| {
+            if !dir.path.starts_with(path) {
+                return true;
+            }
+            wds.remove(&dir.wd);
+            unsafe { libc::inotify_rm_watch(fd, dir.wd) };
+            false
+        });
+    }
+}
+
+fn record(
+    frn: u64,
+    parent_frn: u64,
+    filename: String,
+    is_dir: bool,
+    reason: UsnReason,
+) -> UsnRecord {
+    UsnRecord {
+        reason: reason | UsnReason::CLOSE,
+        ..event_record(frn, parent_frn, filename, is_dir)
+    }
+}
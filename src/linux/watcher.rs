@@ -0,0 +1,68 @@
+use anyhow::Result;
+use std::collections::VecDeque;
+
+use super::{Fanotify, Inotify, Mount};
+use crate::{
+    record::UsnRecord,
+    source::{Changes, Cursor, RecordSource},
+};
+
+pub enum Watcher {
+    Fanotify(Fanotify),
+    Inotify(Inotify),
+}
+
+impl Watcher {
+    fn read(&mut self, records: &mut VecDeque<UsnRecord>) -> Result<()> {
+        match self {
+            Self::Fanotify(fanotify) => fanotify.read(records),
+            Self::Inotify(inotify) => inotify.read(records),
+        }
+    }
+}
+
+/// 已到达的变更事件。
+///
+/// 事件读取后即被消耗，无法重放，所以只能从上一次读取结束的位置继续。
+pub struct MountChanges<'a> {
+    mount: &'a Mount,
+    err: Option<anyhow::Error>,
+}
+
+impl<'a> MountChanges<'a> {
+    pub fn new(mount: &'a Mount, cursor: Cursor) -> Self {
+        let err = mount.range().and_then(|r| Ok(r.check(cursor)?)).err();
+        Self { mount, err }
+    }
+}
+
+impl Iterator for MountChanges<'_> {
+    type Item = Result<UsnRecord>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if let Some(e) = self.err.take() {
+            return Some(Err(e));
+        }
+
+        let mut state = self.mount.state.lock().unwrap();
+        let state = &mut *state;
+        if state.records.is_empty() {
+            if let Err(e) = state.watcher.read(&mut state.records) {
+                return Some(Err(e));
+            }
+        }
+        let mut record = state.records.pop_front()?;
+        record.usn = state.usn;
+        state.usn += 1;
+        Some(Ok(record))
+    }
+}
+
+impl Changes for MountChanges<'_> {
+    fn cursor(&self) -> Cursor {
+        Cursor {
+            id: self.mount.id,
+            usn: self.mount.state.lock().unwrap().usn,
+        }
+    }
+}
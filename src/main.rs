@@ -7,41 +7,124 @@ use eframe::{
         TextStyle,
     },
     epaint::text::{FontInsert, FontPriority, InsertFontFamily},
-    App, Frame, NativeOptions,
+    glow, App, Frame, NativeOptions,
 };
+use log::warn;
 use std::{
-    env,
-    fs::File,
+    fs::{create_dir_all, File},
     io::{self, Read},
-    mem::take,
-    path::PathBuf,
-    sync::mpsc::{channel, Receiver, Sender},
+    mem::{replace, take},
+    path::{Path, PathBuf},
+    sync::mpsc::{channel, Receiver, RecvTimeoutError, Sender},
     thread::{spawn, JoinHandle},
+    time::Duration,
 };
 
-use ffd::{scan_drivers, FullPath, Index, Volume};
+#[cfg(windows)]
+use ffd::{scan_drivers as scan_sources, Volume as Source};
+#[cfg(target_os = "linux")]
+use ffd::{scan_mounts as scan_sources, Mount as Source};
+use ffd::{FullPath, Index, ParOptions, ParseError, Query, RecordSource, Session};
+
+#[cfg(not(any(windows, target_os = "linux")))]
+compile_error!("FastFind 只支持 Windows 和 Linux");
 
 fn main() -> eframe::Result {
     eframe::run_native(
         "FastFind",
         NativeOptions::default(),
         Box::new(|cc| {
-            configure_font(&cc.egui_ctx).unwrap();
+            // 缺少中文字体时界面仍然可用，只是中文无法显示
+            if let Err(e) = configure_font(&cc.egui_ctx) {
+                warn!("加载中文字体失败：{e}");
+            }
 
             Ok(Box::<FastFind>::default())
         }),
     )
 }
 
-fn configure_font(ctx: &Context) -> io::Result<()> {
-    let mut path: PathBuf = env::var("SystemRoot")
+#[cfg(windows)]
+fn font_path() -> PathBuf {
+    let mut path: PathBuf = std::env::var("SystemRoot")
         .unwrap_or(r"C:\Windows".to_string())
         .into();
     path.push(r"Fonts\msyh.ttc");
+    path
+}
+
+#[cfg(target_os = "linux")]
+fn font_path() -> PathBuf {
+    // 各发行版中 Noto Sans CJK 的常见位置
+    [
+        "/usr/share/fonts/opentype/noto/NotoSansCJK-Regular.ttc",
+        "/usr/share/fonts/noto-cjk/NotoSansCJK-Regular.ttc",
+        "/usr/share/fonts/google-noto-cjk/NotoSansCJK-Regular.ttc",
+    ]
+    .into_iter()
+    .map(PathBuf::from)
+    .find(|path| path.exists())
+    .unwrap_or_default()
+}
+
+#[cfg(windows)]
+fn cache_dir() -> PathBuf {
+    let mut path: PathBuf = std::env::var("LOCALAPPDATA")
+        .unwrap_or(std::env::temp_dir().to_string_lossy().into_owned())
+        .into();
+    path.push("FastFind");
+    path
+}
+
+#[cfg(target_os = "linux")]
+fn cache_dir() -> PathBuf {
+    let mut path: PathBuf = match std::env::var("XDG_CACHE_HOME") {
+        Ok(dir) => dir.into(),
+        Err(_) => match std::env::var("HOME") {
+            Ok(home) => PathBuf::from(home).join(".cache"),
+            Err(_) => std::env::temp_dir(),
+        },
+    };
+    path.push("fastfind");
+    path
+}
+
+/// 每个盘一个快照文件，文件名由盘符或挂载点转换而来
+fn snapshot_path(driver: &str) -> PathBuf {
+    let name: String = driver
+        .chars()
+        .map(|ch| if ch.is_alphanumeric() { ch } else { '_' })
+        .collect();
+    cache_dir().join(format!("{name}.idx"))
+}
+
+/// 运行期间保存快照的间隔，退出时也会保存
+const SAVE_INTERVAL: Duration = Duration::from_secs(10 * 60);
+
+fn save_snapshot(idx: &Index, path: &Path) -> Result<()> {
+    if let Some(dir) = path.parent() {
+        create_dir_all(dir)?;
+    }
+    idx.save_file(path)
+}
+
+/// 同步后保存每个盘的快照，变更流不会保留的盘不保存
+fn save_snapshots(drvs: &mut [(Source, Index)]) {
+    for (vol, idx) in drvs.iter_mut().filter(|(vol, _)| vol.persistent()) {
+        let res = idx
+            .sync_or_rebuild(vol)
+            .and_then(|_| save_snapshot(idx, &snapshot_path(vol.driver())));
+        if let Err(e) = res {
+            warn!("保存 {} 的索引快照失败：{e}", vol.driver());
+        }
+    }
+}
+
+fn configure_font(ctx: &Context) -> io::Result<()> {
     let mut buf = Vec::new();
-    File::open(path)?.read_to_end(&mut buf)?;
+    File::open(font_path())?.read_to_end(&mut buf)?;
     ctx.add_font(FontInsert::new(
-        "微软雅黑",
+        "中文字体",
         FontData::from_owned(buf),
         vec![InsertFontFamily {
             family: FontFamily::Proportional,
@@ -55,15 +138,22 @@ fn configure_font(ctx: &Context) -> io::Result<()> {
 #[derive(Default)]
 struct FastFind {
     input: String,
+    /// 输入的语法错误，有错误时不搜索，保留上一次的结果以免输入到一半时闪烁
+    error: Option<ParseError>,
     index_state: IndexState,
 }
 
 impl FastFind {
     fn find(&mut self, sub: String) {
+        self.error = Query::parse(&sub).err();
+        if self.error.is_some() {
+            return;
+        }
         if let IndexState::Ready {
             sender,
             receiver,
             paths,
+            ..
         } = &mut self.index_state
         {
             paths.clear();
@@ -85,18 +175,37 @@ impl FastFind {
                     let (find_tx, find_rx) = channel();
                     let (res_tx, res_rx) = channel();
                     find_tx.send((String::new(), res_tx)).unwrap();
-                    spawn(move || loop {
-                        let (sub, res_tx) = find_rx.recv().unwrap();
-                        // 空字符串不做搜索
-                        if sub.is_empty() {
-                            continue;
-                        }
+                    let worker = spawn(move || {
+                        // 每个盘一个会话，继续输入时只需检查上一次的结果
+                        let mut sessions: Vec<_> = drvs.iter().map(|_| Session::new()).collect();
+                        loop {
+                            let (sub, res_tx) = match find_rx.recv_timeout(SAVE_INTERVAL) {
+                                Ok(req) => req,
+                                // 下次启动时只需重放之后的变更
+                                Err(RecvTimeoutError::Timeout) => {
+                                    save_snapshots(&mut drvs);
+                                    continue;
+                                }
+                                // 窗口已关闭
+                                Err(RecvTimeoutError::Disconnected) => {
+                                    save_snapshots(&mut drvs);
+                                    return;
+                                }
+                            };
+                            // 空字符串不做搜索
+                            if sub.is_empty() {
+                                continue;
+                            }
 
-                        'outer: for (vol, idx) in &mut drvs {
-                            idx.sync(vol).unwrap();
-                            for path in idx.find_iter(&sub) {
-                                if res_tx.send(path).is_err() {
-                                    break 'outer;
+                            for ((vol, idx), session) in drvs.iter_mut().zip(&mut sessions) {
+                                if let Err(e) = idx.sync_or_rebuild(vol) {
+                                    warn!("同步 {} 的索引失败：{e}", vol.driver());
+                                }
+                                // 新的搜索开始后接收端被丢弃，发送失败时停止
+                                let options = ParOptions::default();
+                                let send = |path| res_tx.send(path).is_ok();
+                                if !session.par_find(idx, &sub, options, send) {
+                                    break;
                                 }
                             }
                         }
@@ -106,13 +215,12 @@ impl FastFind {
                         sender: find_tx,
                         receiver: res_rx,
                         paths: Vec::new(),
+                        worker,
                     };
                 }
             }
             IndexState::Ready {
-                sender: _,
-                receiver,
-                paths,
+                receiver, paths, ..
             } => {
                 // 一次性接收太多会导致卡死
                 paths.extend(receiver.try_iter().take(10));
@@ -122,6 +230,15 @@ impl FastFind {
 }
 
 impl App for FastFind {
+    /// 等搜索线程保存完快照再退出
+    fn on_exit(&mut self, _: Option<&glow::Context>) {
+        let state = replace(&mut self.index_state, IndexState::Indxing(Vec::new()));
+        if let IndexState::Ready { sender, worker, .. } = state {
+            drop(sender);
+            let _ = worker.join();
+        }
+    }
+
     fn update(&mut self, ctx: &Context, _: &mut Frame) {
         CentralPanel::default().show(ctx, |ui| {
             let text_edit = TextEdit::singleline(&mut self.input).hint_text("输入关键字");
@@ -133,6 +250,10 @@ impl App for FastFind {
                 self.find(self.input.clone());
             }
 
+            if let Some(e) = &self.error {
+                ui.colored_label(Color32::RED, e.to_string());
+            }
+
             ui.separator();
 
             self.sync();
@@ -140,11 +261,7 @@ impl App for FastFind {
                 IndexState::Indxing(_) => {
                     ui.label("索引中...");
                 }
-                IndexState::Ready {
-                    sender: _,
-                    receiver: _,
-                    paths,
-                } => {
+                IndexState::Ready { paths, .. } => {
                     let height = ui.text_style_height(&TextStyle::Body);
                     let total_rows = paths.len();
                     ScrollArea::vertical().show_rows(ui, height, total_rows, |ui, range| {
@@ -164,11 +281,13 @@ impl App for FastFind {
                                 let layout =
                                     Layout::left_to_right(Align::Center).with_main_wrap(true);
                                 ui.with_layout(layout, |ui| {
-                                    let (prefix, sub, suffix) = path.split();
                                     ui.spacing_mut().item_spacing.x = 0.0;
-                                    ui.label(prefix);
-                                    ui.colored_label(Color32::RED, sub);
-                                    ui.label(suffix);
+                                    for (text, highlight) in path.segments() {
+                                        match highlight {
+                                            true => ui.colored_label(Color32::RED, text),
+                                            false => ui.label(text),
+                                        };
+                                    }
                                 });
                             });
                             ui.separator();
@@ -181,22 +300,31 @@ impl App for FastFind {
 }
 
 enum IndexState {
-    Indxing(Vec<JoinHandle<Result<(Volume, Index)>>>),
+    Indxing(Vec<JoinHandle<Result<(Source, Index)>>>),
     Ready {
         sender: Sender<(String, Sender<FullPath>)>,
         receiver: Receiver<FullPath>,
         paths: Vec<FullPath>,
+        /// 搜索线程，输入端关闭后保存快照并退出
+        worker: JoinHandle<()>,
     },
 }
 
 impl Default for IndexState {
     fn default() -> Self {
-        let drvs = scan_drivers();
+        let drvs = scan_sources();
         let mut handles = Vec::with_capacity(drvs.len());
         for drv in drvs {
             let handle = spawn(move || {
-                let vol = Volume::open(drv)?;
-                let idx = Index::try_from_volume(&vol)?;
+                let vol = Source::open(drv)?;
+                let path = snapshot_path(vol.driver());
+                let idx = Index::load_or_build(&vol, &path)?;
+                // 快照只是加速下次启动，保存失败不影响使用
+                if vol.persistent() {
+                    if let Err(e) = save_snapshot(&idx, &path) {
+                        warn!("保存 {} 的索引快照失败：{e}", vol.driver());
+                    }
+                }
                 Ok((vol, idx))
             });
             handles.push(handle);
@@ -18,7 +18,9 @@ use windows::{
 };
 
 pub use usn_journal_data::UsnJournalData;
-pub use usn_record::{FileRecords, UsnRecord, UsnRecords};
+pub use usn_record::{FileRecords, UsnRecords};
+
+use crate::source::{Cursor, JournalRange, RecordSource};
 
 // https://github.com/microsoft/windows-rs/pull/3013
 // 通过Drop自动释放HANDLE
@@ -60,6 +62,35 @@ impl Volume {
     }
 }
 
+impl RecordSource for Volume {
+    type Records<'a> = FileRecords<'a, { 64 * 1024 }>;
+    type Changes<'a> = UsnRecords<'a, 4096>;
+
+    fn driver(&self) -> &str {
+        &self.driver
+    }
+
+    fn cursor(&self) -> Result<Cursor> {
+        let data = self.usn_journal_data()?;
+        Ok(Cursor {
+            id: data.id,
+            usn: data.next_usn,
+        })
+    }
+
+    fn range(&self) -> Result<JournalRange> {
+        Ok(self.usn_journal_data()?.range())
+    }
+
+    fn records(&self) -> Self::Records<'_> {
+        self.file_records()
+    }
+
+    fn changes_from(&self, cursor: Cursor) -> Self::Changes<'_> {
+        self.usn_records_from(cursor.id, cursor.usn)
+    }
+}
+
 pub fn scan_drivers() -> Vec<String> {
     let mut res = Vec::new();
     let mut mask = unsafe { GetLogicalDrives() };
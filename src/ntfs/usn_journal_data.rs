@@ -6,11 +6,20 @@ use windows::Win32::System::{
 };
 
 use super::Volume;
+use crate::source::JournalRange;
 
+// https://learn.microsoft.com/zh-cn/windows/win32/api/winioctl/ns-winioctl-usn_journal_data_v0
 #[derive(Debug)]
 pub struct UsnJournalData {
     pub id: u64,
+    /// 日志中第一条记录的 USN
+    pub first_usn: i64,
     pub next_usn: i64,
+    /// 比它更早的记录已经被回收，通常与 `first_usn` 相同
+    pub lowest_valid_usn: i64,
+    /// USN 到达它时日志必须删除重建
+    pub max_usn: i64,
+    pub max_size: u64,
 }
 
 impl UsnJournalData {
@@ -18,7 +27,19 @@ impl UsnJournalData {
         let data = &*ptr;
         Self {
             id: data.UsnJournalID,
+            first_usn: data.FirstUsn,
             next_usn: data.NextUsn,
+            lowest_valid_usn: data.LowestValidUsn,
+            max_usn: data.MaxUsn,
+            max_size: data.MaximumSize,
+        }
+    }
+
+    pub fn range(&self) -> JournalRange {
+        JournalRange {
+            id: self.id,
+            lowest_valid_usn: self.lowest_valid_usn,
+            next_usn: self.next_usn,
         }
     }
 
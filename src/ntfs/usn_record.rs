@@ -1,43 +1,21 @@
 use anyhow::{anyhow, Result};
-use std::{ffi::c_void, mem::MaybeUninit, ptr, slice};
+use std::ffi::c_void;
 use windows::Win32::{
-    Foundation::ERROR_HANDLE_EOF,
+    Foundation::{ERROR_HANDLE_EOF, ERROR_JOURNAL_ENTRY_DELETED, ERROR_JOURNAL_NOT_ACTIVE},
     System::{
         Ioctl::{
-            FSCTL_ENUM_USN_DATA, FSCTL_READ_USN_JOURNAL, MFT_ENUM_DATA_V1,
-            READ_USN_JOURNAL_DATA_V0, USN_REASON_CLOSE, USN_RECORD_V2,
+            FSCTL_ENUM_USN_DATA, FSCTL_READ_USN_JOURNAL, MFT_ENUM_DATA_V1, READ_USN_JOURNAL_DATA_V1,
         },
         IO::DeviceIoControl,
     },
 };
 
 use super::Volume;
-
-#[derive(Debug)]
-pub struct UsnRecord {
-    pub frn: u64,
-    pub parent_frn: u64,
-    pub filename: String,
-    pub reason: u32,
-    length: u32,
-}
-
-impl UsnRecord {
-    unsafe fn from_raw(ptr: *const USN_RECORD_V2) -> Self {
-        let record = &*ptr;
-        let filename = slice::from_raw_parts(
-            ptr.byte_add(record.FileNameOffset.into()) as *const u16,
-            (record.FileNameLength / 2).into(),
-        );
-        Self {
-            filename: String::from_utf16_lossy(filename),
-            frn: record.FileReferenceNumber,
-            parent_frn: record.ParentFileReferenceNumber,
-            reason: record.Reason,
-            length: record.RecordLength,
-        }
-    }
-}
+use crate::{
+    reason::UsnReason,
+    record::{read_u64, RecordError, UsnRecord},
+    source::{Changes, Cursor},
+};
 
 pub struct FileRecords<'a, const BS: usize> {
     volume: &'a Volume,
@@ -53,10 +31,11 @@ impl<'a, const BS: usize> FileRecords<'a, BS> {
                 StartFileReferenceNumber: 0, // FSCTL_ENUM_USN_DATA要求从0开始
                 LowUsn: 0,
                 HighUsn: i64::MAX,
+                // ReFS 和 FRN 超过 64 位的卷只会返回 V3
                 MinMajorVersion: 2,
-                MaxMajorVersion: 2,
+                MaxMajorVersion: 3,
             },
-            out_buf: RecordBuf::new_uninit(),
+            out_buf: RecordBuf::new(),
         }
     }
 }
@@ -66,7 +45,7 @@ impl<const BS: usize> Iterator for FileRecords<'_, BS> {
 
     fn next(&mut self) -> Option<Self::Item> {
         if let Some(record) = self.out_buf.next() {
-            return Some(Ok(record));
+            return Some(record.map_err(Into::into));
         }
 
         unsafe {
@@ -77,7 +56,7 @@ impl<const BS: usize> Iterator for FileRecords<'_, BS> {
                 size_of_val(&self.in_buf) as _,
                 Some(self.out_buf.buf.as_mut_ptr() as *mut c_void),
                 BS as _,
-                Some(&mut self.out_buf.left_bytes),
+                Some(&mut self.out_buf.len),
                 None,
             ) {
                 // https://learn.microsoft.com/zh-cn/windows/win32/api/winerror/nf-winerror-hresult_code
@@ -87,11 +66,14 @@ impl<const BS: usize> Iterator for FileRecords<'_, BS> {
                     return Some(Err(e.into()));
                 }
             };
-            self.in_buf.StartFileReferenceNumber = self.out_buf.reload();
+        }
+        match self.out_buf.reload() {
+            Some(frn) => self.in_buf.StartFileReferenceNumber = frn,
+            None => return Some(Err(anyhow!("输出过短: {}B", self.out_buf.len))),
         }
 
         match self.out_buf.next() {
-            Some(r) => Some(Ok(r)),
+            Some(r) => Some(r.map_err(Into::into)),
             None => Some(Err(anyhow!("缓冲区过小: {BS}B"))),
         }
     }
@@ -99,7 +81,7 @@ impl<const BS: usize> Iterator for FileRecords<'_, BS> {
 
 pub struct UsnRecords<'a, const BS: usize> {
     volume: &'a Volume,
-    in_buf: READ_USN_JOURNAL_DATA_V0,
+    in_buf: READ_USN_JOURNAL_DATA_V1,
     out_buf: RecordBuf<BS>,
 }
 
@@ -107,16 +89,20 @@ impl<'a, const BS: usize> UsnRecords<'a, BS> {
     pub fn with_start(vol: &'a Volume, id: u64, start: i64) -> Self {
         Self {
             volume: vol,
-            // https://learn.microsoft.com/zh-cn/windows/win32/api/winioctl/ns-winioctl-read_usn_journal_data_v0
-            in_buf: READ_USN_JOURNAL_DATA_V0 {
+            // https://learn.microsoft.com/zh-cn/windows/win32/api/winioctl/ns-winioctl-read_usn_journal_data_v1
+            in_buf: READ_USN_JOURNAL_DATA_V1 {
                 StartUsn: start,
-                ReasonMask: USN_REASON_CLOSE,
-                ReturnOnlyOnClose: 1,
+                // 除了关闭时的记录，还需要重命名前的旧名称来维护硬链接
+                ReasonMask: UsnReason::CLOSE.union(UsnReason::RENAME_OLD_NAME).bits(),
+                ReturnOnlyOnClose: 0,
                 Timeout: 0,
                 BytesToWaitFor: 0,
                 UsnJournalID: id,
+                // V4 只在开启范围跟踪时出现，索引用不到
+                MinMajorVersion: 2,
+                MaxMajorVersion: 3,
             },
-            out_buf: RecordBuf::new_uninit(),
+            out_buf: RecordBuf::new(),
         }
     }
 
@@ -125,12 +111,21 @@ impl<'a, const BS: usize> UsnRecords<'a, BS> {
     }
 }
 
+impl<const BS: usize> Changes for UsnRecords<'_, BS> {
+    fn cursor(&self) -> Cursor {
+        Cursor {
+            id: self.in_buf.UsnJournalID,
+            usn: self.next_usn(),
+        }
+    }
+}
+
 impl<const BS: usize> Iterator for UsnRecords<'_, BS> {
     type Item = Result<UsnRecord>;
 
     fn next(&mut self) -> Option<Self::Item> {
         if let Some(record) = self.out_buf.next() {
-            return Some(Ok(record));
+            return Some(record.map_err(Into::into));
         }
 
         unsafe {
@@ -141,64 +136,89 @@ impl<const BS: usize> Iterator for UsnRecords<'_, BS> {
                 size_of_val(&self.in_buf) as _,
                 Some(self.out_buf.buf.as_mut_ptr() as *mut c_void),
                 BS as _,
-                Some(&mut self.out_buf.left_bytes),
+                Some(&mut self.out_buf.len),
                 None,
             ) {
+                // 读取期间日志被回收或者删除
+                let code = (e.code().0 & 0xFFFF) as u32;
+                if code == ERROR_JOURNAL_ENTRY_DELETED.0 || code == ERROR_JOURNAL_NOT_ACTIVE.0 {
+                    if let Ok(data) = self.volume.usn_journal_data() {
+                        if let Err(stale) = data.range().check(self.cursor()) {
+                            return Some(Err(stale.into()));
+                        }
+                    }
+                }
                 return Some(Err(e.into()));
             }
-            let usn: i64 = self.out_buf.reload();
-            if usn == self.in_buf.StartUsn {
-                return None;
-            }
-            self.in_buf.StartUsn = usn;
         }
+        let usn = match self.out_buf.reload() {
+            Some(usn) => usn as i64,
+            None => return Some(Err(anyhow!("输出过短: {}B", self.out_buf.len))),
+        };
+        if usn == self.in_buf.StartUsn {
+            return None;
+        }
+        self.in_buf.StartUsn = usn;
 
-        Ok(self.out_buf.next()).transpose()
+        self.out_buf.next().map(|r| r.map_err(Into::into))
     }
 }
 
+/// 一次 DeviceIoControl 的输出，所有记录都经过 [`UsnRecord::parse`] 检查。
 struct RecordBuf<const BS: usize> {
-    buf: MaybeUninit<[u8; BS]>,
-    left_bytes: u32,
-    ptr: *const USN_RECORD_V2,
+    buf: Vec<u8>,
+    /// 实际写入的字节数
+    len: u32,
+    pos: usize,
 }
 
 impl<const BS: usize> RecordBuf<BS> {
-    fn new_uninit() -> Self {
+    fn new() -> Self {
         Self {
-            buf: MaybeUninit::uninit(),
-            left_bytes: 0,
-            ptr: ptr::null(),
+            buf: vec![0; BS],
+            len: 0,
+            pos: 0,
         }
     }
 
     /// 当缓冲区被重新装填时，请调用此函数重载。
     ///
-    /// 解析并返回缓冲区的第一个数。
-    unsafe fn reload<T: Copy>(&mut self) -> T {
+    /// 解析并返回缓冲区的第一个数，输出不足 8 字节时返回 `None`。
+    fn reload(&mut self) -> Option<u64> {
         // 缓冲区最前头应该是一个 64 位数，后面跟着尽可能多的 USN 记录
-        let ptr = self.buf.as_ptr();
-        let usn = *(ptr as *const T);
-        self.ptr = ptr.byte_add(size_of::<u64>()) as _;
-        self.left_bytes -= size_of::<T>() as u32;
-        usn
+        let end = self.end();
+        if end < size_of::<u64>() {
+            self.pos = end;
+            return None;
+        }
+        self.pos = size_of::<u64>();
+        Some(read_u64(&self.buf, 0))
+    }
+
+    fn end(&self) -> usize {
+        (self.len as usize).min(BS)
     }
 }
 
 impl<const BS: usize> Iterator for RecordBuf<BS> {
-    type Item = UsnRecord;
+    type Item = Result<UsnRecord, RecordError>;
 
     fn next(&mut self) -> Option<Self::Item> {
-        if self.left_bytes == 0 {
+        let end = self.end();
+        if self.pos >= end {
             return None;
         }
 
-        let record;
-        unsafe {
-            record = UsnRecord::from_raw(self.ptr);
-            self.ptr = self.ptr.byte_add(record.length as _);
+        match UsnRecord::parse(&self.buf[self.pos..end]) {
+            Ok((record, len)) => {
+                self.pos += len;
+                Some(Ok(record))
+            }
+            // 剩下的数据已经不可信了
+            Err(e) => {
+                self.pos = end;
+                Some(Err(e))
+            }
         }
-        self.left_bytes -= record.length;
-        Some(record)
     }
 }
@@ -0,0 +1,458 @@
+use anyhow::{anyhow, bail, ensure, Result};
+use log::debug;
+use std::{
+    collections::VecDeque,
+    fs::File,
+    io::{Read, Seek, SeekFrom},
+    path::Path,
+    sync::Mutex,
+};
+
+use crate::{
+    attributes::FileAttributes,
+    record::UsnRecord,
+    source::{Cursor, NoChanges, RecordSource},
+};
+
+// https://learn.microsoft.com/zh-cn/windows/win32/devnotes/attribute-list-entry
+const ATTR_STANDARD_INFORMATION: u32 = 0x10;
+const ATTR_FILE_NAME: u32 = 0x30;
+const ATTR_DATA: u32 = 0x80;
+const ATTR_END: u32 = 0xFFFF_FFFF;
+
+/// $FILE_NAME 中仅有 DOS 8.3 短名的命名空间
+const NAMESPACE_DOS: u8 = 2;
+
+/// 更新序列数组保护的固定步长，与实际扇区大小无关
+const FIXUP_STRIDE: usize = 512;
+
+/// 一段连续的簇，已经换算为字节，`offset` 为 `None` 表示稀疏
+#[derive(Debug, Clone, Copy)]
+struct Run {
+    offset: Option<u64>,
+    len: u64,
+}
+
+/// 不依赖 Windows API，直接解析 NTFS 分区镜像中的 $MFT。
+///
+/// 产生与 Windows 上的 `FileRecords` 相同的 `(frn, parent_frn, filename)`，
+/// FRN 的高 16 位同样是序列号；硬链接的每个名称各产生一条记录。
+/// 镜像是静态的，不会产生任何变更。
+///
+/// 镜像中的数据都不可信，无效的引导扇区或数据运行会返回错误，而不会溢出。
+pub struct NtfsImage<R> {
+    driver: String,
+    reader: Mutex<R>,
+    record_size: usize,
+    mft_runs: Vec<Run>,
+    mft_size: u64,
+}
+
+impl NtfsImage<File> {
+    pub fn open(driver: String, path: impl AsRef<Path>) -> Result<Self> {
+        Self::new(driver, File::open(path)?)
+    }
+}
+
+impl<R: Read + Seek> NtfsImage<R> {
+    pub fn new(driver: String, mut reader: R) -> Result<Self> {
+        // https://learn.microsoft.com/zh-cn/windows/win32/fileio/ntfs-technical-reference
+        let mut boot = [0; 512];
+        reader.seek(SeekFrom::Start(0))?;
+        reader.read_exact(&mut boot)?;
+        ensure!(&boot[3..11] == b"NTFS    ", "不是 NTFS 分区");
+
+        let sector_size = read_u16(&boot, 0x0B).unwrap_or_default() as u64;
+        let sectors_per_cluster = match boot[0x0D] {
+            // 大于 0x80 时表示 2 的负指数次方
+            n if n > 0x80 => 1u64.checked_shl(256 - n as u32),
+            n => Some(n as u64),
+        };
+        let cluster_size = sectors_per_cluster
+            .and_then(|n| n.checked_mul(sector_size))
+            .filter(|&size| size > 0)
+            .ok_or_else(|| anyhow!("无效的引导扇区：簇大小溢出或为 0"))?;
+        let record_size = match boot[0x40] as i8 {
+            n if n < 0 => 1u64.checked_shl(n.unsigned_abs() as u32),
+            n => (n as u64).checked_mul(cluster_size),
+        }
+        .filter(|size| (FIXUP_STRIDE as u64..=64 * 1024).contains(size))
+        .ok_or_else(|| anyhow!("无效的引导扇区：文件记录大小 {:#x}", boot[0x40]))?
+            as usize;
+
+        // $MFT 本身是第 0 条记录，从它的 $DATA 属性得到 $MFT 的位置
+        let mft_offset = read_u64(&boot, 0x30)
+            .unwrap_or_default()
+            .checked_mul(cluster_size)
+            .ok_or_else(|| anyhow!("无效的引导扇区：$MFT 的位置溢出"))?;
+        let mut buf = vec![0; record_size];
+        reader.seek(SeekFrom::Start(mft_offset))?;
+        reader.read_exact(&mut buf)?;
+        let (runs, mft_size) = apply_fixup(&mut buf)
+            .and_then(|_| {
+                attrs(&buf).find_map(|(ty, attr)| match ty {
+                    ATTR_DATA if attr.get(9) == Some(&0) => non_resident(attr),
+                    _ => None,
+                })
+            })
+            .ok_or_else(|| anyhow!("无法解析 $MFT 的文件记录"))?;
+        let mft_runs = parse_runs(runs, cluster_size)?;
+
+        Ok(Self {
+            driver,
+            reader: Mutex::new(reader),
+            record_size,
+            mft_runs,
+            mft_size,
+        })
+    }
+
+    pub fn mft_records(&self) -> MftRecords<'_, R> {
+        MftRecords::new(self)
+    }
+
+    pub fn driver(&self) -> &str {
+        &self.driver
+    }
+
+    /// 读取 $MFT 中从 `pos` 开始的数据，稀疏部分填 0。
+    fn read_mft(&self, mut pos: u64, mut buf: &mut [u8]) -> Result<()> {
+        let mut reader = self.reader.lock().unwrap();
+        // 所有段的总长度和每段的结束位置在 `parse_runs` 中检查过，不会溢出
+        let mut start = 0;
+        for run in &self.mft_runs {
+            if pos >= start + run.len {
+                start += run.len;
+                continue;
+            }
+
+            let n = buf.len().min((start + run.len - pos) as usize);
+            let (head, tail) = buf.split_at_mut(n);
+            match run.offset {
+                Some(offset) => {
+                    reader.seek(SeekFrom::Start(offset + pos - start))?;
+                    reader.read_exact(head)?;
+                }
+                None => head.fill(0),
+            }
+            buf = tail;
+            if buf.is_empty() {
+                return Ok(());
+            }
+            pos += n as u64;
+            start += run.len;
+        }
+        Err(anyhow!("$MFT 的数据超出了簇的范围"))
+    }
+}
+
+impl<R: Read + Seek> RecordSource for NtfsImage<R> {
+    type Records<'a>
+        = MftRecords<'a, R>
+    where
+        R: 'a;
+    type Changes<'a>
+        = NoChanges
+    where
+        R: 'a;
+
+    fn driver(&self) -> &str {
+        &self.driver
+    }
+
+    fn cursor(&self) -> Result<Cursor> {
+        Ok(Cursor { id: 0, usn: 0 })
+    }
+
+    fn records(&self) -> Self::Records<'_> {
+        self.mft_records()
+    }
+
+    fn changes_from(&self, cursor: Cursor) -> Self::Changes<'_> {
+        NoChanges(cursor)
+    }
+}
+
+/// 按顺序读取 $MFT 中的文件记录。
+///
+/// 跳过未使用和损坏的记录，以及扩展记录（名称只在极多硬链接时才会放在扩展记录里）。
+pub struct MftRecords<'a, R> {
+    image: &'a NtfsImage<R>,
+    /// 当前文件记录中还没有产生的其他名称
+    pending: VecDeque<UsnRecord>,
+    /// 下一条记录的编号
+    index: u64,
+    buf: Vec<u8>,
+    /// `buf` 中第一条记录的编号
+    buf_index: u64,
+}
+
+impl<'a, R: Read + Seek> MftRecords<'a, R> {
+    fn new(image: &'a NtfsImage<R>) -> Self {
+        Self {
+            image,
+            pending: VecDeque::new(),
+            index: 0,
+            buf: Vec::new(),
+            buf_index: 0,
+        }
+    }
+
+    fn total(&self) -> u64 {
+        self.image.mft_size / self.image.record_size as u64
+    }
+
+    /// 一次读入多条记录，减少寻道
+    fn fill(&mut self) -> Result<()> {
+        const BS: usize = 1024 * 1024;
+        let size = self.image.record_size;
+        let n = (BS / size).max(1).min((self.total() - self.index) as usize);
+        self.buf.resize(n * size, 0);
+        self.buf_index = self.index;
+        self.image.read_mft(self.index * size as u64, &mut self.buf)
+    }
+}
+
+impl<R: Read + Seek> Iterator for MftRecords<'_, R> {
+    type Item = Result<UsnRecord>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if let Some(record) = self.pending.pop_front() {
+            return Some(Ok(record));
+        }
+        let size = self.image.record_size;
+        while self.index < self.total() {
+            let offset = (self.index - self.buf_index) as usize * size;
+            if self.buf.is_empty() || offset >= self.buf.len() {
+                if let Err(e) = self.fill() {
+                    self.index = self.total();
+                    return Some(Err(e));
+                }
+                continue;
+            }
+
+            let index = self.index;
+            self.index += 1;
+            let record = &mut self.buf[offset..offset + size];
+            self.pending = parse_file_record(index, record).into();
+            if let Some(record) = self.pending.pop_front() {
+                return Some(Ok(record));
+            }
+        }
+        None
+    }
+}
+
+/// 解析一条 FILE 记录，每个 Win32 或 POSIX 名称产生一条记录，无法产生文件记录时为空。
+fn parse_file_record(index: u64, buf: &mut [u8]) -> Vec<UsnRecord> {
+    parse_names(index, buf).unwrap_or_default()
+}
+
+fn parse_names(index: u64, buf: &mut [u8]) -> Option<Vec<UsnRecord>> {
+    if buf.get(..4)? != b"FILE" {
+        return None;
+    }
+    if apply_fixup(buf).is_none() {
+        debug!("NtfsImage：第 {index} 条记录的更新序列不匹配");
+        return None;
+    }
+
+    let flags = read_u16(buf, 0x16)?;
+    let base = read_u64(buf, 0x20)?;
+    // 未使用或扩展记录
+    if flags & 0x01 == 0 || base != 0 {
+        return None;
+    }
+
+    let seq = read_u16(buf, 0x10)? as u64;
+    let frn = seq << 48 | index;
+    let mut names: Vec<(u64, String)> = Vec::new();
+    for (parent, _, name) in attrs(buf)
+        .filter(|&(ty, attr)| ty == ATTR_FILE_NAME && attr.get(8) == Some(&0))
+        .filter_map(|(_, attr)| file_name(attr))
+        .filter(|&(_, namespace, _)| namespace != NAMESPACE_DOS)
+    {
+        // 根目录的父目录是它自己
+        if parent & 0xFFFF_FFFF_FFFF == index {
+            return None;
+        }
+        if !names.iter().any(|(p, n)| *p == parent && *n == name) {
+            names.push((parent, name));
+        }
+    }
+
+    let (timestamp, mut attributes) = attrs(buf)
+        .find(|&(ty, _)| ty == ATTR_STANDARD_INFORMATION)
+        .and_then(|(_, attr)| standard_information(attr))
+        .unwrap_or_default();
+    // $STANDARD_INFORMATION 中没有目录属性，由记录头的标志决定
+    if flags & 0x02 != 0 {
+        attributes |= FileAttributes::DIRECTORY;
+    }
+
+    let records = names.into_iter().map(|(parent_frn, filename)| UsnRecord {
+        frn: frn.into(),
+        parent_frn: parent_frn.into(),
+        filename,
+        timestamp,
+        attributes,
+        ..Default::default()
+    });
+    Some(records.collect())
+}
+
+/// 将每个扇区末尾的更新序列号还原为原始数据，并校验序列号是否一致。
+fn apply_fixup(buf: &mut [u8]) -> Option<()> {
+    let usa_offset = read_u16(buf, 4)? as usize;
+    let usa_count = read_u16(buf, 6)? as usize;
+    if usa_count == 0 || (usa_count - 1) * FIXUP_STRIDE > buf.len() {
+        return None;
+    }
+
+    let usn: [u8; 2] = buf.get(usa_offset..usa_offset + 2)?.try_into().ok()?;
+    for i in 1..usa_count {
+        let fix: [u8; 2] = buf
+            .get(usa_offset + i * 2..usa_offset + i * 2 + 2)?
+            .try_into()
+            .ok()?;
+        let end = i * FIXUP_STRIDE;
+        if buf[end - 2..end] != usn {
+            return None;
+        }
+        buf[end - 2..end].copy_from_slice(&fix);
+    }
+    Some(())
+}
+
+/// 遍历文件记录中的属性，产生 `(类型, 整个属性)`。
+fn attrs(buf: &[u8]) -> impl Iterator<Item = (u32, &[u8])> {
+    let mut offset = read_u16(buf, 0x14).unwrap_or(u16::MAX) as usize;
+    std::iter::from_fn(move || {
+        let ty = read_u32(buf, offset)?;
+        let len = read_u32(buf, offset + 4)? as usize;
+        if ty == ATTR_END || len == 0 {
+            return None;
+        }
+        let attr = buf.get(offset..offset + len)?;
+        offset += len;
+        Some((ty, attr))
+    })
+}
+
+/// 解析常驻的 $FILE_NAME 属性，返回 `(父目录, 命名空间, 名称)`。
+fn file_name(attr: &[u8]) -> Option<(u64, u8, String)> {
+    let value_len = read_u32(attr, 0x10)? as usize;
+    let value_offset = read_u16(attr, 0x14)? as usize;
+    let value = attr.get(value_offset..value_offset + value_len)?;
+
+    let parent = read_u64(value, 0)?;
+    let name_len = *value.get(0x40)? as usize;
+    let namespace = *value.get(0x41)?;
+    let name: Vec<u16> = value
+        .get(0x42..0x42 + name_len * 2)?
+        .chunks_exact(2)
+        .map(|ch| u16::from_le_bytes([ch[0], ch[1]]))
+        .collect();
+    Some((parent, namespace, String::from_utf16_lossy(&name)))
+}
+
+/// 解析 $STANDARD_INFORMATION 属性，返回 `(修改时间, 文件属性)`。
+fn standard_information(attr: &[u8]) -> Option<(i64, FileAttributes)> {
+    let value_offset = read_u16(attr, 0x14)? as usize;
+    let value = attr.get(value_offset..)?;
+    let modified = read_u64(value, 0x08)? as i64;
+    let attributes = read_u32(value, 0x20)?;
+    Some((modified, attributes.into()))
+}
+
+/// 解析非常驻属性，返回 `(数据运行列表, 数据大小)`。
+fn non_resident(attr: &[u8]) -> Option<(&[u8], u64)> {
+    if *attr.get(8)? == 0 {
+        return None;
+    }
+    let runs_offset = read_u16(attr, 0x20)? as usize;
+    let size = read_u64(attr, 0x30)?;
+    Some((attr.get(runs_offset..)?, size))
+}
+
+/// 解析数据运行列表并换算为字节，保证每段的结束位置和所有段的总长度都不溢出。
+fn parse_runs(mut buf: &[u8], cluster_size: u64) -> Result<Vec<Run>> {
+    let mut runs = Vec::new();
+    let mut lcn: i64 = 0;
+    let mut total: u64 = 0;
+    loop {
+        let Some(&header) = buf.first() else {
+            bail!("无效的数据运行：缺少结束标记");
+        };
+        if header == 0 {
+            return Ok(runs);
+        }
+        // 低 4 位为长度的字节数，高 4 位为偏移的字节数
+        let len_size = (header & 0x0F) as usize;
+        let offset_size = (header >> 4) as usize;
+        ensure!(
+            (1..=8).contains(&len_size) && offset_size <= 8,
+            "无效的数据运行：头部 {header:#x}"
+        );
+        let (Some(len), Some(offset)) = (
+            buf.get(1..1 + len_size),
+            buf.get(1 + len_size..1 + len_size + offset_size),
+        ) else {
+            bail!("无效的数据运行：数据被截断");
+        };
+        buf = &buf[1 + len_size + offset_size..];
+
+        let len = u64::try_from(read_int(len))
+            .ok()
+            .and_then(|len| len.checked_mul(cluster_size))
+            .filter(|&len| total.checked_add(len).is_some())
+            .ok_or_else(|| anyhow!("无效的数据运行：长度溢出"))?;
+        total += len;
+        if offset.is_empty() {
+            runs.push(Run { offset: None, len });
+            continue;
+        }
+        // 偏移是相对上一段的有符号数
+        let offset = lcn
+            .checked_add(read_int(offset))
+            .inspect(|&next| lcn = next)
+            .and_then(|lcn| u64::try_from(lcn).ok())
+            .and_then(|lcn| lcn.checked_mul(cluster_size))
+            .filter(|&offset| offset.checked_add(len).is_some())
+            .ok_or_else(|| anyhow!("无效的数据运行：位置为负或溢出"))?;
+        runs.push(Run {
+            offset: Some(offset),
+            len,
+        });
+    }
+}
+
+/// 读取 1 到 8 字节的小端有符号整数
+fn read_int(bytes: &[u8]) -> i64 {
+    let fill = if bytes.last().is_some_and(|&b| b & 0x80 != 0) {
+        0xFF
+    } else {
+        0
+    };
+    let mut buf = [fill; 8];
+    buf[..bytes.len()].copy_from_slice(bytes);
+    i64::from_le_bytes(buf)
+}
+
+fn read_u16(buf: &[u8], offset: usize) -> Option<u16> {
+    Some(u16::from_le_bytes(
+        buf.get(offset..offset + 2)?.try_into().ok()?,
+    ))
+}
+
+fn read_u32(buf: &[u8], offset: usize) -> Option<u32> {
+    Some(u32::from_le_bytes(
+        buf.get(offset..offset + 4)?.try_into().ok()?,
+    ))
+}
+
+fn read_u64(buf: &[u8], offset: usize) -> Option<u64> {
+    Some(u64::from_le_bytes(
+        buf.get(offset..offset + 8)?.try_into().ok()?,
+    ))
+}
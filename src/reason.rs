@@ -0,0 +1,229 @@
+use std::{
+    fmt::{Debug, Display},
+    ops::{BitAnd, BitAndAssign, BitOr, BitOrAssign, Not, Sub},
+    str::FromStr,
+};
+
+use anyhow::{anyhow, Error};
+
+/// USN 记录中的原因标志，与平台无关。
+///
+/// 关闭时的记录带有这次打开期间累积的所有原因，所以应当用 [`contains`](Self::contains)
+/// 或 [`intersects`](Self::intersects) 判断，而不是比较相等。未定义的位会被原样保留。
+// https://learn.microsoft.com/zh-cn/windows/win32/api/winioctl/ns-winioctl-usn_record_v2
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub struct UsnReason(u32);
+
+macro_rules! reasons {
+    ($($name:ident = $bits:expr,)*) => {
+        impl UsnReason {
+            $(pub const $name: Self = Self($bits);)*
+
+            /// 所有已定义的标志及其名称，按位从低到高排列
+            const NAMES: &[(&str, Self)] = &[$((stringify!($name), Self::$name),)*];
+        }
+    };
+}
+
+reasons! {
+    DATA_OVERWRITE = 0x0000_0001,
+    DATA_EXTEND = 0x0000_0002,
+    DATA_TRUNCATION = 0x0000_0004,
+    NAMED_DATA_OVERWRITE = 0x0000_0010,
+    NAMED_DATA_EXTEND = 0x0000_0020,
+    NAMED_DATA_TRUNCATION = 0x0000_0040,
+    FILE_CREATE = 0x0000_0100,
+    FILE_DELETE = 0x0000_0200,
+    EA_CHANGE = 0x0000_0400,
+    SECURITY_CHANGE = 0x0000_0800,
+    RENAME_OLD_NAME = 0x0000_1000,
+    RENAME_NEW_NAME = 0x0000_2000,
+    INDEXABLE_CHANGE = 0x0000_4000,
+    BASIC_INFO_CHANGE = 0x0000_8000,
+    HARD_LINK_CHANGE = 0x0001_0000,
+    COMPRESSION_CHANGE = 0x0002_0000,
+    ENCRYPTION_CHANGE = 0x0004_0000,
+    OBJECT_ID_CHANGE = 0x0008_0000,
+    REPARSE_POINT_CHANGE = 0x0010_0000,
+    STREAM_CHANGE = 0x0020_0000,
+    TRANSACTED_CHANGE = 0x0040_0000,
+    INTEGRITY_CHANGE = 0x0080_0000,
+    DESIRED_STORAGE_CLASS_CHANGE = 0x0100_0000,
+    CLOSE = 0x8000_0000,
+}
+
+impl UsnReason {
+    pub const fn empty() -> Self {
+        Self(0)
+    }
+
+    pub const fn from_bits(bits: u32) -> Self {
+        Self(bits)
+    }
+
+    pub const fn bits(self) -> u32 {
+        self.0
+    }
+
+    pub const fn is_empty(self) -> bool {
+        self.0 == 0
+    }
+
+    /// 是否包含 `other` 中的所有标志
+    pub const fn contains(self, other: Self) -> bool {
+        self.0 & other.0 == other.0
+    }
+
+    /// 是否包含 `other` 中的任意一个标志
+    pub const fn intersects(self, other: Self) -> bool {
+        self.0 & other.0 != 0
+    }
+
+    /// 与 `|` 相同，可以用在常量中
+    pub const fn union(self, other: Self) -> Self {
+        Self(self.0 | other.0)
+    }
+
+    /// 逐个产生已设置的标志，包括未定义的位
+    pub fn iter(self) -> Iter {
+        Iter(self.0)
+    }
+
+    /// 单个已定义标志的名称
+    pub fn name(self) -> Option<&'static str> {
+        Self::NAMES
+            .iter()
+            .find(|(_, flag)| *flag == self)
+            .map(|(name, _)| *name)
+    }
+}
+
+pub struct Iter(u32);
+
+impl Iterator for Iter {
+    type Item = UsnReason;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.0 == 0 {
+            return None;
+        }
+        let bit = self.0 & self.0.wrapping_neg();
+        self.0 &= !bit;
+        Some(UsnReason(bit))
+    }
+}
+
+impl IntoIterator for UsnReason {
+    type Item = UsnReason;
+    type IntoIter = Iter;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.iter()
+    }
+}
+
+/// 形如 `FILE_CREATE|CLOSE`，未定义的位以十六进制表示，没有任何标志时为 `0`。
+impl Display for UsnReason {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        if self.is_empty() {
+            return write!(f, "0");
+        }
+        for (i, flag) in self.iter().enumerate() {
+            if i > 0 {
+                write!(f, "|")?;
+            }
+            match flag.name() {
+                Some(name) => write!(f, "{name}")?,
+                None => write!(f, "{:#x}", flag.0)?,
+            }
+        }
+        Ok(())
+    }
+}
+
+impl Debug for UsnReason {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "UsnReason({self})")
+    }
+}
+
+/// 解析 [`Display`] 的输出，标志名称不区分大小写，可以省略 `USN_REASON_` 前缀。
+impl FromStr for UsnReason {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut reason = Self::empty();
+        for part in s.split('|').map(str::trim) {
+            let name = part.to_ascii_uppercase();
+            let name = name.strip_prefix("USN_REASON_").unwrap_or(&name);
+            let flag = if let Some(hex) = name.strip_prefix("0X") {
+                u32::from_str_radix(hex, 16).map(Self).ok()
+            } else if name == "0" {
+                Some(Self::empty())
+            } else {
+                Self::NAMES
+                    .iter()
+                    .find(|(n, _)| *n == name)
+                    .map(|(_, flag)| *flag)
+            };
+            reason |= flag.ok_or_else(|| anyhow!("未知的原因：{part:?}"))?;
+        }
+        Ok(reason)
+    }
+}
+
+impl From<u32> for UsnReason {
+    fn from(bits: u32) -> Self {
+        Self(bits)
+    }
+}
+
+impl From<UsnReason> for u32 {
+    fn from(reason: UsnReason) -> Self {
+        reason.0
+    }
+}
+
+impl BitOr for UsnReason {
+    type Output = Self;
+
+    fn bitor(self, rhs: Self) -> Self::Output {
+        Self(self.0 | rhs.0)
+    }
+}
+
+impl BitOrAssign for UsnReason {
+    fn bitor_assign(&mut self, rhs: Self) {
+        self.0 |= rhs.0;
+    }
+}
+
+impl BitAnd for UsnReason {
+    type Output = Self;
+
+    fn bitand(self, rhs: Self) -> Self::Output {
+        Self(self.0 & rhs.0)
+    }
+}
+
+impl BitAndAssign for UsnReason {
+    fn bitand_assign(&mut self, rhs: Self) {
+        self.0 &= rhs.0;
+    }
+}
+
+impl Sub for UsnReason {
+    type Output = Self;
+
+    fn sub(self, rhs: Self) -> Self::Output {
+        Self(self.0 & !rhs.0)
+    }
+}
+
+impl Not for UsnReason {
+    type Output = Self;
+
+    fn not(self) -> Self::Output {
+        Self(!self.0)
+    }
+}
@@ -0,0 +1,367 @@
+use std::{
+    error::Error,
+    fmt::Display,
+    time::{Duration, SystemTime},
+};
+
+use crate::{attributes::FileAttributes, reason::UsnReason};
+
+/// 1601-01-01 到 1970-01-01 之间 100 纳秒的个数
+const FILETIME_UNIX_EPOCH: i64 = 116_444_736_000_000_000;
+
+/// 一条文件记录，与平台无关。
+///
+/// 文件 ID 统一用 128 位表示：NTFS 的 64 位 FRN、Linux 的 inode 号直接零扩展，
+/// ReFS 等卷上的 `FILE_ID_128` 则原样保存。
+#[derive(Debug, Clone, Default)]
+pub struct UsnRecord {
+    pub frn: u128,
+    pub parent_frn: u128,
+    pub filename: String,
+    pub reason: UsnReason,
+    /// 记录在日志中的位置；枚举得到的记录为文件最后一次变更的位置，没有时为 0
+    pub usn: i64,
+    /// FILETIME，即自 1601-01-01 起的 100 纳秒数，没有时为 0
+    pub timestamp: i64,
+    pub attributes: FileAttributes,
+    /// 变更的来源，如数据管理或复制服务，普通的修改为 0
+    pub source_info: u32,
+    pub security_id: u32,
+}
+
+/// V4 记录中数据范围的一项
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct UsnExtent {
+    pub offset: i64,
+    pub length: i64,
+}
+
+/// USN_RECORD_V4，开启范围跟踪后描述文件中被修改的数据范围，不含名称。
+///
+/// 一次变更可能被拆成多条 V4 记录，`remaining_extents` 是之后还有多少项。
+// https://learn.microsoft.com/zh-cn/windows/win32/api/winioctl/ns-winioctl-usn_record_v4
+#[derive(Debug, Clone)]
+pub struct RangeRecord {
+    pub frn: u128,
+    pub parent_frn: u128,
+    pub usn: i64,
+    pub reason: UsnReason,
+    pub remaining_extents: u32,
+    pub extents: Vec<UsnExtent>,
+}
+
+/// 各版本记录中字段的偏移
+pub(crate) struct Layout {
+    /// V2 的 ID 是 64 位的，V3 是 128 位的
+    pub id_len: usize,
+    pub frn: usize,
+    pub parent_frn: usize,
+    pub usn: usize,
+    pub timestamp: usize,
+    /// Reason，其后依次是 SourceInfo、SecurityId 和 FileAttributes
+    pub reason: usize,
+    /// FileNameLength，其后紧跟 FileNameOffset
+    pub name: usize,
+    /// 固定部分的长度，即 FileName 的偏移
+    pub header: usize,
+}
+
+impl Layout {
+    // https://learn.microsoft.com/zh-cn/windows/win32/api/winioctl/ns-winioctl-usn_record_v2
+    const V2: Self = Self {
+        id_len: 8,
+        frn: 8,
+        parent_frn: 16,
+        usn: 24,
+        timestamp: 32,
+        reason: 40,
+        name: 56,
+        header: 60,
+    };
+
+    // https://learn.microsoft.com/zh-cn/windows/win32/api/winioctl/ns-winioctl-usn_record_v3
+    const V3: Self = Self {
+        id_len: 16,
+        frn: 8,
+        parent_frn: 24,
+        usn: 40,
+        timestamp: 48,
+        reason: 56,
+        name: 72,
+        header: 76,
+    };
+
+    pub fn of(major: u16) -> Option<&'static Self> {
+        match major {
+            2 => Some(&Self::V2),
+            3 => Some(&Self::V3),
+            _ => None,
+        }
+    }
+
+    fn read_id(&self, buf: &[u8], offset: usize) -> u128 {
+        match self.id_len {
+            8 => read_u64(buf, offset) as u128,
+            _ => read_u128(buf, offset),
+        }
+    }
+}
+
+impl RangeRecord {
+    const HEADER: usize = 64;
+}
+
+/// 解析 USN 记录时遇到的错误
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum RecordError {
+    /// 缓冲区不足以容纳记录
+    Truncated {
+        need: usize,
+        have: usize,
+    },
+    /// RecordLength 为 0、未按 8 字节对齐或者小于固定部分
+    BadLength(u32),
+    UnsupportedVersion(u16),
+    /// FileNameOffset 或 FileNameLength 不是 UTF-16 对齐的
+    MisalignedName {
+        offset: u16,
+        len: u16,
+    },
+    /// 名称超出了记录的范围，或者与固定部分重叠
+    NameOutOfBounds {
+        offset: u16,
+        len: u16,
+    },
+    /// V4 的范围列表超出了记录，或者 ExtentSize 过小
+    ExtentsOutOfBounds {
+        count: u16,
+        size: u16,
+    },
+}
+
+impl Display for RecordError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Truncated { need, have } => write!(f, "记录被截断：需要 {need}B，只有 {have}B"),
+            Self::BadLength(len) => write!(f, "无效的记录长度：{len}"),
+            Self::UnsupportedVersion(major) => write!(f, "不支持的记录版本：{major}"),
+            Self::MisalignedName { offset, len } => {
+                write!(f, "名称未对齐：{offset}+{len}")
+            }
+            Self::NameOutOfBounds { offset, len } => {
+                write!(f, "名称超出记录范围：{offset}+{len}")
+            }
+            Self::ExtentsOutOfBounds { count, size } => {
+                write!(f, "范围列表超出记录范围：{count}x{size}")
+            }
+        }
+    }
+}
+
+impl Error for RecordError {}
+
+impl UsnRecord {
+    /// 未知的文件 ID，此时由索引按 `parent_frn` 和 `filename` 找到文件。
+    ///
+    /// inotify 的删除和移动事件只带有名称；NTFS 上 FRN 为 0 的 $MFT 不会出现在变更中，
+    /// Linux 上也没有 0 号 inode。
+    pub const UNKNOWN_FRN: u128 = 0;
+
+    /// 从字节切片开头解析一条 USN_RECORD_V2/V3，返回记录和它占用的字节数。
+    ///
+    /// 所有长度和偏移都会经过检查，任何输入都不会导致越界。
+    /// V4 记录请使用 [`RangeRecord::parse`]。
+    pub fn parse(buf: &[u8]) -> Result<(Self, usize), RecordError> {
+        let (major, len) = header(buf)?;
+        let layout = Layout::of(major).ok_or(RecordError::UnsupportedVersion(major))?;
+        let buf = &buf[..len];
+
+        let offset = read_u16(buf, layout.name + 2);
+        let name_len = read_u16(buf, layout.name);
+        if !offset.is_multiple_of(2) || !name_len.is_multiple_of(2) {
+            return Err(RecordError::MisalignedName {
+                offset,
+                len: name_len,
+            });
+        }
+        let start = offset as usize;
+        let end = start + name_len as usize;
+        if start < layout.header || end > len {
+            return Err(RecordError::NameOutOfBounds {
+                offset,
+                len: name_len,
+            });
+        }
+        let filename: Vec<u16> = buf[start..end]
+            .chunks_exact(2)
+            .map(|ch| u16::from_le_bytes([ch[0], ch[1]]))
+            .collect();
+
+        let record = Self {
+            frn: layout.read_id(buf, layout.frn),
+            parent_frn: layout.read_id(buf, layout.parent_frn),
+            filename: String::from_utf16_lossy(&filename),
+            reason: read_u32(buf, layout.reason).into(),
+            usn: read_u64(buf, layout.usn) as i64,
+            timestamp: read_u64(buf, layout.timestamp) as i64,
+            source_info: read_u32(buf, layout.reason + 4),
+            security_id: read_u32(buf, layout.reason + 8),
+            attributes: read_u32(buf, layout.reason + 12).into(),
+        };
+        Ok((record, len))
+    }
+
+    /// 记录的时间，没有时间时返回 `None`。
+    pub fn time(&self) -> Option<SystemTime> {
+        (self.timestamp != 0).then(|| from_filetime(self.timestamp))
+    }
+}
+
+/// 将 FILETIME 转换为 [`SystemTime`]
+pub fn from_filetime(filetime: i64) -> SystemTime {
+    let ticks = filetime as i128 - FILETIME_UNIX_EPOCH as i128;
+    let abs = ticks.unsigned_abs();
+    let duration = Duration::new((abs / 10_000_000) as u64, (abs % 10_000_000 * 100) as u32);
+    if ticks >= 0 {
+        SystemTime::UNIX_EPOCH + duration
+    } else {
+        SystemTime::UNIX_EPOCH - duration
+    }
+}
+
+/// 将 [`SystemTime`] 转换为 FILETIME
+pub fn to_filetime(time: SystemTime) -> i64 {
+    let ticks = match time.duration_since(SystemTime::UNIX_EPOCH) {
+        Ok(d) => (d.as_nanos() / 100) as i64,
+        Err(e) => -((e.duration().as_nanos() / 100) as i64),
+    };
+    FILETIME_UNIX_EPOCH + ticks
+}
+
+impl RangeRecord {
+    /// 从字节切片开头解析一条 USN_RECORD_V4，返回记录和它占用的字节数。
+    pub fn parse(buf: &[u8]) -> Result<(Self, usize), RecordError> {
+        let (major, len) = header(buf)?;
+        if major != 4 {
+            return Err(RecordError::UnsupportedVersion(major));
+        }
+        let buf = &buf[..len];
+
+        let count = read_u16(buf, 60);
+        let size = read_u16(buf, 62);
+        if (size as usize) < 16 || Self::HEADER + count as usize * size as usize > len {
+            return Err(RecordError::ExtentsOutOfBounds { count, size });
+        }
+        let extents = buf[Self::HEADER..]
+            .chunks(size as usize)
+            .take(count as usize)
+            .map(|ext| UsnExtent {
+                offset: read_u64(ext, 0) as i64,
+                length: read_u64(ext, 8) as i64,
+            })
+            .collect();
+
+        let record = Self {
+            frn: read_u128(buf, 8),
+            parent_frn: read_u128(buf, 24),
+            usn: read_u64(buf, 40) as i64,
+            reason: read_u32(buf, 48).into(),
+            remaining_extents: read_u32(buf, 56),
+            extents,
+        };
+        Ok((record, len))
+    }
+}
+
+/// 校验记录头，返回主版本号和记录长度。
+pub(crate) fn header(buf: &[u8]) -> Result<(u16, usize), RecordError> {
+    if buf.len() < 8 {
+        return Err(RecordError::Truncated {
+            need: 8,
+            have: buf.len(),
+        });
+    }
+    let len = read_u32(buf, 0);
+    let major = read_u16(buf, 4);
+    let min = match major {
+        4 => RangeRecord::HEADER,
+        _ => {
+            Layout::of(major)
+                .ok_or(RecordError::UnsupportedVersion(major))?
+                .header
+        }
+    };
+    if !len.is_multiple_of(8) || (len as usize) < min {
+        return Err(RecordError::BadLength(len));
+    }
+    if len as usize > buf.len() {
+        return Err(RecordError::Truncated {
+            need: len as usize,
+            have: buf.len(),
+        });
+    }
+    Ok((major, len as usize))
+}
+
+/// 从字节切片中逐条解析紧密排列的 USN 记录。
+///
+/// V4 记录在校验后被跳过；遇到错误后不再产生任何记录。
+pub struct RawRecords<'a> {
+    buf: &'a [u8],
+}
+
+impl<'a> RawRecords<'a> {
+    pub fn new(buf: &'a [u8]) -> Self {
+        Self { buf }
+    }
+
+    /// 尚未解析的部分
+    pub fn remaining(&self) -> &'a [u8] {
+        self.buf
+    }
+}
+
+impl Iterator for RawRecords<'_> {
+    type Item = Result<UsnRecord, RecordError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            if self.buf.is_empty() {
+                return None;
+            }
+            let res = match header(self.buf) {
+                Ok((4, _)) => RangeRecord::parse(self.buf).map(|(_, len)| (None, len)),
+                _ => UsnRecord::parse(self.buf).map(|(record, len)| (Some(record), len)),
+            };
+            match res {
+                Ok((record, len)) => {
+                    self.buf = &self.buf[len..];
+                    if record.is_some() {
+                        return record.map(Ok);
+                    }
+                }
+                Err(e) => {
+                    self.buf = &[];
+                    return Some(Err(e));
+                }
+            }
+        }
+    }
+}
+
+/// 调用前需要保证范围有效
+pub(crate) fn read_u16(buf: &[u8], offset: usize) -> u16 {
+    u16::from_le_bytes(buf[offset..offset + 2].try_into().unwrap())
+}
+
+pub(crate) fn read_u32(buf: &[u8], offset: usize) -> u32 {
+    u32::from_le_bytes(buf[offset..offset + 4].try_into().unwrap())
+}
+
+pub(crate) fn read_u64(buf: &[u8], offset: usize) -> u64 {
+    u64::from_le_bytes(buf[offset..offset + 8].try_into().unwrap())
+}
+
+pub(crate) fn read_u128(buf: &[u8], offset: usize) -> u128 {
+    u128::from_le_bytes(buf[offset..offset + 16].try_into().unwrap())
+}
@@ -0,0 +1,146 @@
+use anyhow::Result;
+use std::{error::Error, fmt::Display};
+
+use crate::record::UsnRecord;
+
+/// 变更流中的位置，可以从这里继续读取后续的变更。
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Cursor {
+    /// 变更流的 ID，变更流重建后会改变
+    pub id: u64,
+    pub usn: i64,
+}
+
+/// 文件记录的来源。
+///
+/// 提供全部文件记录的初次枚举，以及从某个 [`Cursor`] 开始的增量变更流，
+/// [`Index`](crate::Index) 依靠它建立索引并保持同步。
+pub trait RecordSource {
+    type Records<'a>: Iterator<Item = Result<UsnRecord>>
+    where
+        Self: 'a;
+    type Changes<'a>: Changes
+    where
+        Self: 'a;
+
+    fn driver(&self) -> &str;
+
+    /// 返回变更流当前的末尾位置。
+    fn cursor(&self) -> Result<Cursor>;
+
+    /// 返回变更流中仍然可以读取的范围。
+    ///
+    /// 默认认为从 0 开始的变更都还在，会回收旧变更的来源需要覆盖它。
+    fn range(&self) -> Result<JournalRange> {
+        let cursor = self.cursor()?;
+        Ok(JournalRange {
+            id: cursor.id,
+            lowest_valid_usn: 0,
+            next_usn: cursor.usn,
+        })
+    }
+
+    /// 变更流在程序退出后是否仍然保留。
+    ///
+    /// 不保留时（如 Linux 上的 fanotify/inotify）退出期间的变更会丢失，
+    /// 保存的快照在下次启动时一定已经过期，不必保存也不必读取。
+    fn persistent(&self) -> bool {
+        true
+    }
+
+    fn records(&self) -> Self::Records<'_>;
+
+    fn changes_from(&self, cursor: Cursor) -> Self::Changes<'_>;
+}
+
+/// 变更流中仍然可以读取的范围。
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct JournalRange {
+    pub id: u64,
+    /// 更早的变更已经被回收
+    pub lowest_valid_usn: i64,
+    pub next_usn: i64,
+}
+
+impl JournalRange {
+    /// 检查能否从 `cursor` 继续读取变更。
+    pub fn check(&self, cursor: Cursor) -> Result<(), IndexStale> {
+        if cursor.id != self.id {
+            Err(IndexStale::JournalChanged {
+                expected: cursor.id,
+                found: self.id,
+            })
+        } else if cursor.usn < self.lowest_valid_usn {
+            Err(IndexStale::Deleted {
+                usn: cursor.usn,
+                lowest_valid_usn: self.lowest_valid_usn,
+            })
+        } else if cursor.usn > self.next_usn {
+            Err(IndexStale::Ahead {
+                usn: cursor.usn,
+                next_usn: self.next_usn,
+            })
+        } else {
+            Ok(())
+        }
+    }
+}
+
+/// 索引记录的位置已经无法在变更流中找到，中间的变更丢失了，只能重新建立索引。
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum IndexStale {
+    /// 变更流被删除后重建（NTFS 上 USN 即将回绕时也会如此）
+    JournalChanged { expected: u64, found: u64 },
+    /// 需要的变更已经被回收
+    Deleted { usn: i64, lowest_valid_usn: i64 },
+    /// 位置超出了变更流的末尾，变更流被截断或者重置过
+    Ahead { usn: i64, next_usn: i64 },
+    /// 监听变更的事件队列溢出，其中的事件被丢弃了
+    Overflow,
+}
+
+impl Display for IndexStale {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::JournalChanged { expected, found } => {
+                write!(f, "索引已过期：日志 ID 由 {expected:#x} 变为 {found:#x}")
+            }
+            Self::Deleted {
+                usn,
+                lowest_valid_usn,
+            } => write!(
+                f,
+                "索引已过期：USN {usn} 已被回收，最早的有效 USN 为 {lowest_valid_usn}"
+            ),
+            Self::Ahead { usn, next_usn } => {
+                write!(f, "索引已过期：USN {usn} 超出了日志末尾 {next_usn}")
+            }
+            Self::Overflow => write!(f, "索引已过期：事件队列溢出，部分变更已丢失"),
+        }
+    }
+}
+
+impl Error for IndexStale {}
+
+/// 增量变更流。
+pub trait Changes: Iterator<Item = Result<UsnRecord>> {
+    /// 返回已读取部分之后的位置，下一次从这里继续读取。
+    fn cursor(&self) -> Cursor;
+}
+
+/// 不会产生任何变更的变更流，用于磁盘镜像这类静态的来源。
+pub struct NoChanges(pub Cursor);
+
+impl Iterator for NoChanges {
+    type Item = Result<UsnRecord>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        None
+    }
+}
+
+impl Changes for NoChanges {
+    fn cursor(&self) -> Cursor {
+        self.0
+    }
+}
@@ -0,0 +1,81 @@
+//! 多个测试共用的辅助函数，每个测试只用到其中一部分。
+#![allow(dead_code)]
+
+use std::path::MAIN_SEPARATOR_STR;
+
+use ffd::{Cursor, FileAttributes, FullPath, Index, Query, UsnReason, UsnRecord};
+
+pub mod usn;
+
+pub fn record(frn: u128, parent_frn: u128, filename: &str, reason: UsnReason) -> UsnRecord {
+    UsnRecord {
+        frn,
+        parent_frn,
+        filename: filename.to_string(),
+        reason,
+        ..Default::default()
+    }
+}
+
+/// 只有名称的测试用索引，`parse` 决定关键词是否按查询语法解析
+pub struct Fixture {
+    pub index: Index,
+    parse: fn(&str) -> Query,
+}
+
+impl Fixture {
+    pub fn new(parse: fn(&str) -> Query) -> Self {
+        Self {
+            index: Index::with_capacity("X:".to_string(), Cursor { id: 0, usn: 0 }, 0),
+            parse,
+        }
+    }
+
+    pub fn add_dir(&mut self, frn: u128, parent_frn: u128, name: &str) {
+        self.index.add_link(UsnRecord {
+            attributes: FileAttributes::DIRECTORY,
+            ..record(frn, parent_frn, name, UsnReason::empty())
+        });
+    }
+
+    /// 在 `parent_frn` 中加入文件，FRN 从 `first` 开始依次递增
+    pub fn add_files(&mut self, parent_frn: u128, first: u128, names: &[&str]) {
+        for (i, name) in names.iter().enumerate() {
+            let frn = first + i as u128;
+            self.index
+                .add_link(record(frn, parent_frn, name, UsnReason::empty()));
+        }
+    }
+}
+
+pub fn find(fixture: &Fixture, sub: &str) -> Vec<FullPath> {
+    fixture.index.find_query(&(fixture.parse)(sub)).collect()
+}
+
+pub fn file_name(path: &FullPath) -> &str {
+    path.inner.rsplit(MAIN_SEPARATOR_STR).next().unwrap()
+}
+
+/// 匹配的名称，排序并去重
+pub fn names(fixture: &Fixture, sub: &str) -> Vec<String> {
+    let mut res: Vec<_> = find(fixture, sub)
+        .iter()
+        .map(|p| file_name(p).to_string())
+        .collect();
+    res.sort();
+    res.dedup();
+    res
+}
+
+/// 第一个名称以 `name` 开头的结果中所有高亮的部分
+pub fn highlighted(fixture: &Fixture, sub: &str, name: &str) -> Vec<String> {
+    let res = find(fixture, sub);
+    let path = res
+        .iter()
+        .find(|p| file_name(p).starts_with(name))
+        .unwrap_or_else(|| panic!("{sub} 没有匹配 {name}"));
+    path.segments()
+        .filter(|(_, h)| *h)
+        .map(|(s, _)| s.to_string())
+        .collect()
+}
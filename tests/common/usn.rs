@@ -0,0 +1,67 @@
+//! 按 USN_RECORD_V2/V3/V4 的布局构造记录的字节。
+
+use ffd::UsnReason;
+
+fn utf16(name: &str) -> Vec<u8> {
+    name.encode_utf16()
+        .flat_map(|ch| ch.to_le_bytes())
+        .collect()
+}
+
+/// 补齐到 8 字节并写入记录长度
+fn finish(mut buf: Vec<u8>) -> Vec<u8> {
+    buf.resize(buf.len().next_multiple_of(8), 0);
+    let len = buf.len() as u32;
+    buf[..4].copy_from_slice(&len.to_le_bytes());
+    buf
+}
+
+/// 时间戳为 USN 的 10 倍，方便检查
+pub fn v2(frn: u64, parent: u64, usn: i64, reason: UsnReason, name: &str) -> Vec<u8> {
+    let name = utf16(name);
+    let mut buf = vec![0; 60];
+    buf[4..6].copy_from_slice(&2u16.to_le_bytes());
+    buf[8..16].copy_from_slice(&frn.to_le_bytes());
+    buf[16..24].copy_from_slice(&parent.to_le_bytes());
+    buf[24..32].copy_from_slice(&usn.to_le_bytes());
+    buf[32..40].copy_from_slice(&(usn * 10).to_le_bytes());
+    buf[40..44].copy_from_slice(&reason.bits().to_le_bytes());
+    buf[56..58].copy_from_slice(&(name.len() as u16).to_le_bytes());
+    buf[58..60].copy_from_slice(&60u16.to_le_bytes());
+    buf.extend(name);
+    finish(buf)
+}
+
+/// 时间戳为 USN 的 10 倍，方便检查
+pub fn v3(frn: u128, parent: u128, usn: i64, reason: UsnReason, name: &str) -> Vec<u8> {
+    let name = utf16(name);
+    let mut buf = vec![0; 76];
+    buf[4..6].copy_from_slice(&3u16.to_le_bytes());
+    buf[8..24].copy_from_slice(&frn.to_le_bytes());
+    buf[24..40].copy_from_slice(&parent.to_le_bytes());
+    buf[40..48].copy_from_slice(&usn.to_le_bytes());
+    buf[48..56].copy_from_slice(&(usn * 10).to_le_bytes());
+    buf[56..60].copy_from_slice(&reason.bits().to_le_bytes());
+    buf[72..74].copy_from_slice(&(name.len() as u16).to_le_bytes());
+    buf[74..76].copy_from_slice(&76u16.to_le_bytes());
+    buf.extend(name);
+    finish(buf)
+}
+
+/// 父目录为 5、原因为 DATA_EXTEND 的范围记录
+pub fn v4(frn: u128, usn: i64, remaining: u32, extents: &[(i64, i64)]) -> Vec<u8> {
+    let mut buf = vec![0; 64];
+    buf[4..6].copy_from_slice(&4u16.to_le_bytes());
+    buf[8..24].copy_from_slice(&frn.to_le_bytes());
+    buf[24..40].copy_from_slice(&5u128.to_le_bytes());
+    buf[40..48].copy_from_slice(&usn.to_le_bytes());
+    buf[48..52].copy_from_slice(&UsnReason::DATA_EXTEND.bits().to_le_bytes());
+    buf[56..60].copy_from_slice(&remaining.to_le_bytes());
+    buf[60..62].copy_from_slice(&(extents.len() as u16).to_le_bytes());
+    buf[62..64].copy_from_slice(&16u16.to_le_bytes());
+    for (offset, length) in extents {
+        buf.extend(offset.to_le_bytes());
+        buf.extend(length.to_le_bytes());
+    }
+    finish(buf)
+}
@@ -0,0 +1,1161 @@
+mod common;
+
+use anyhow::Result;
+use std::{
+    path::MAIN_SEPARATOR_STR,
+    slice,
+    time::{Duration, UNIX_EPOCH},
+    vec,
+};
+
+use ffd::{
+    Changes, Cursor, FileAttributes, FileInfo, Index, IndexStale, JournalRange, Kind, ParOptions,
+    Query, RecordSource, Session, UsnReason, UsnRecord,
+};
+
+use common::record;
+
+/// 内存中的文件记录来源，`journal` 中每条记录的下标就是它的 USN。
+struct FakeSource {
+    records: Vec<UsnRecord>,
+    journal: Vec<UsnRecord>,
+    id: u64,
+    /// 之前的记录已被回收
+    lowest: i64,
+    persistent: bool,
+}
+
+struct FakeChanges<'a> {
+    records: slice::Iter<'a, UsnRecord>,
+    id: u64,
+    usn: i64,
+}
+
+impl Iterator for FakeChanges<'_> {
+    type Item = Result<UsnRecord>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let record = self.records.next()?;
+        self.usn += 1;
+        Some(Ok(record.clone()))
+    }
+}
+
+impl Changes for FakeChanges<'_> {
+    fn cursor(&self) -> Cursor {
+        Cursor {
+            id: self.id,
+            usn: self.usn,
+        }
+    }
+}
+
+impl RecordSource for FakeSource {
+    type Records<'a> = vec::IntoIter<Result<UsnRecord>>;
+    type Changes<'a> = FakeChanges<'a>;
+
+    fn driver(&self) -> &str {
+        "X:"
+    }
+
+    fn cursor(&self) -> Result<Cursor> {
+        Ok(Cursor {
+            id: self.id,
+            usn: self.journal.len() as _,
+        })
+    }
+
+    fn range(&self) -> Result<JournalRange> {
+        Ok(JournalRange {
+            id: self.id,
+            lowest_valid_usn: self.lowest,
+            next_usn: self.journal.len() as _,
+        })
+    }
+
+    fn persistent(&self) -> bool {
+        self.persistent
+    }
+
+    fn records(&self) -> Self::Records<'_> {
+        self.records
+            .iter()
+            .cloned()
+            .map(Ok)
+            .collect::<Vec<_>>()
+            .into_iter()
+    }
+
+    fn changes_from(&self, cursor: Cursor) -> Self::Changes<'_> {
+        FakeChanges {
+            records: self.journal[cursor.usn as usize..].iter(),
+            id: self.id,
+            usn: cursor.usn,
+        }
+    }
+}
+
+fn path(parts: &[&str]) -> String {
+    parts.join(MAIN_SEPARATOR_STR)
+}
+
+fn find(index: &Index, sub: &str) -> Vec<String> {
+    let mut res: Vec<_> = index.find_iter(sub).map(|p| p.to_string()).collect();
+    res.sort();
+    res
+}
+
+fn dir(frn: u128, parent_frn: u128, filename: &str) -> UsnRecord {
+    UsnRecord {
+        attributes: FileAttributes::DIRECTORY,
+        ..record(frn, parent_frn, filename, UsnReason::empty())
+    }
+}
+
+fn source() -> FakeSource {
+    FakeSource {
+        records: vec![
+            dir(5, 0, "projects"),
+            dir(6, 5, "fastfind"),
+            record(7, 6, "README.md", UsnReason::empty()),
+            record(8, 6, "readme.txt", UsnReason::empty()),
+        ],
+        journal: Vec::new(),
+        id: 1,
+        lowest: 0,
+        persistent: true,
+    }
+}
+
+#[test]
+fn build_and_find() {
+    let index = Index::try_from_source(&source()).unwrap();
+    assert_eq!(index.driver(), "X:");
+    assert_eq!(index.cursor(), Cursor { id: 1, usn: 0 });
+    assert_eq!(
+        find(&index, "ReadMe"),
+        [
+            path(&["X:", "projects", "fastfind", "README.md"]),
+            path(&["X:", "projects", "fastfind", "readme.txt"]),
+        ]
+    );
+}
+
+#[test]
+fn highlight() {
+    let index = Index::try_from_source(&source()).unwrap();
+    let res: Vec<_> = index.find_iter("fast").collect();
+    assert_eq!(res.len(), 1);
+    let (prefix, sub, suffix) = res[0].split();
+    assert_eq!(prefix, path(&["X:", "projects", ""]));
+    assert_eq!(sub, "fast");
+    assert_eq!(suffix, "find");
+}
+
+#[test]
+fn folded() {
+    let mut index = Index::try_from_source(&source()).unwrap();
+    index.insert(record(9, 5, "Straße.TXT", UsnReason::empty()));
+    index.insert(record(10, 5, "İstanbul", UsnReason::empty()));
+    index.insert(record(11, 5, "ΣΟΦΙΑ", UsnReason::empty()));
+
+    let split = |sub: &str| {
+        let res: Vec<_> = index.find_iter(sub).collect();
+        assert_eq!(res.len(), 1, "{sub}");
+        let (_, sub, suffix) = res[0].split();
+        (sub.to_string(), suffix.to_string())
+    };
+    assert_eq!(split("straße.txt"), ("Straße.TXT".into(), "".into()));
+    assert_eq!(split("ße.t"), ("ße.T".into(), "XT".into()));
+    // 折叠后变长的字符
+    assert_eq!(split("stan"), ("stan".into(), "bul".into()));
+    assert_eq!(split("i\u{307}s"), ("İs".into(), "tanbul".into()));
+    assert_eq!(split("\u{307}"), ("İ".into(), "stanbul".into()));
+    assert_eq!(split("σοφ"), ("ΣΟΦ".into(), "ΙΑ".into()));
+
+    // 一个名称中多处匹配只产生一个结果
+    assert_eq!(find(&index, "e").len(), 4);
+    assert_eq!(find(&index, "").len(), 7);
+}
+
+#[test]
+fn query() {
+    let mut index = Index::try_from_source(&source()).unwrap();
+    index.insert(record(9, 5, "Invoice 2024-03.pdf", UsnReason::empty()));
+    index.insert(record(10, 5, "2024 invoice draft.docx", UsnReason::empty()));
+    index.insert(record(11, 6, "photo.JPG", UsnReason::empty()));
+    index.insert(record(12, 6, "photo.png", UsnReason::empty()));
+    index.insert(record(13, 6, "photo_thumbnail.png", UsnReason::empty()));
+    let names = |sub: &str| -> Vec<String> {
+        find(&index, sub)
+            .into_iter()
+            .map(|p| p.rsplit(MAIN_SEPARATOR_STR).next().unwrap().to_string())
+            .collect()
+    };
+
+    // 关键词可以是任意顺序
+    assert_eq!(
+        names("invoice 2024"),
+        ["2024 invoice draft.docx", "Invoice 2024-03.pdf"]
+    );
+    assert_eq!(names("2024 invoice !draft"), ["Invoice 2024-03.pdf"]);
+    assert_eq!(names("\"invoice 2024\""), ["Invoice 2024-03.pdf"]);
+    assert_eq!(names("(jpg|png) -thumbnail"), ["photo.JPG", "photo.png"]);
+    assert_eq!(
+        names("readme | draft"),
+        ["2024 invoice draft.docx", "README.md", "readme.txt"]
+    );
+    assert_eq!(names("folder:-fast"), ["projects"]);
+    assert_eq!(names("!r !o !e"), ["fastfind"]);
+    // 语法错误时整个输入作为一个关键词
+    assert!(names("photo (").is_empty());
+    assert_eq!(
+        find(&index, "photo"),
+        index
+            .find_query(&Query::parse("photo").unwrap())
+            .map(|p| p.to_string())
+            .collect::<Vec<_>>()
+    );
+
+    // 每个关键词各自高亮，排除的关键词不高亮
+    let segments = |sub: &str| -> Vec<(String, bool)> {
+        let res: Vec<_> = index.find_iter(sub).collect();
+        assert_eq!(res.len(), 1, "{sub}");
+        let name_start = res[0].inner.rfind(MAIN_SEPARATOR_STR).unwrap() + 1;
+        assert!(res[0].spans().iter().all(|span| span.start >= name_start));
+        res[0]
+            .segments()
+            .skip(1)
+            .map(|(s, h)| (s.to_string(), h))
+            .collect()
+    };
+    let seg = |s: &str, h: bool| (s.to_string(), h);
+    assert_eq!(
+        segments("2024 INVOICE !draft"),
+        [
+            seg("Invoice", true),
+            seg(" ", false),
+            seg("2024", true),
+            seg("-03.pdf", false)
+        ]
+    );
+    assert_eq!(
+        segments("photo_ | thumb"),
+        [seg("photo_thumb", true), seg("nail.png", false)]
+    );
+    let res: Vec<_> = index.find_iter("!r !o !e").collect();
+    assert!(res[0].spans().is_empty());
+    assert_eq!(res[0].split().1, "");
+}
+
+#[test]
+fn glob() {
+    let mut index = Index::try_from_source(&source()).unwrap();
+    for (frn, name) in [
+        (9, "IMG_0001.JPG"),
+        (10, "img_12.jpg"),
+        (11, "IMG_0002.jpg.bak"),
+        (12, "main.rs"),
+        (13, "lib.rs.orig"),
+        (14, "Übung.md"),
+        (15, "[draft] a.md"),
+        (16, "Straße.txt"),
+        (17, "İstanbul"),
+    ] {
+        index.insert(record(frn, 6, name, UsnReason::empty()));
+    }
+    let names_in = |index: &Index, sub: &str| -> Vec<String> {
+        find(index, sub)
+            .into_iter()
+            .map(|p| p.rsplit(MAIN_SEPARATOR_STR).next().unwrap().to_string())
+            .collect()
+    };
+    let names = |sub: &str| names_in(&index, sub);
+
+    // 通配符需要匹配整个名称
+    assert_eq!(names("*.rs"), ["main.rs"]);
+    assert_eq!(names("*.rs*"), ["lib.rs.orig", "main.rs"]);
+    assert_eq!(names("img_????.jpg"), ["IMG_0001.JPG"]);
+    assert_eq!(names("IMG_*.jpg"), ["IMG_0001.JPG", "img_12.jpg"]);
+    assert_eq!(names("*_*1*.jpg"), ["IMG_0001.JPG", "img_12.jpg"]);
+    assert_eq!(names("?ain*"), ["main.rs"]);
+    assert_eq!(names("[a-m]*.rs*"), ["lib.rs.orig", "main.rs"]);
+    assert_eq!(names("[!m]*.rs*"), ["lib.rs.orig"]);
+    assert_eq!(names("[ü]bung.?d"), ["Übung.md"]);
+    assert_eq!(names("?bung.md"), ["Übung.md"]);
+    // `?` 匹配一个原字符，即使它折叠后是多个字符
+    assert_eq!(names("stra?e.txt"), ["Straße.txt"]);
+    assert!(names("stra??e.txt").is_empty());
+    assert_eq!(names("?stanbul"), ["İstanbul"]);
+    assert_eq!(names("strasse.*"), ["Straße.txt"]);
+    // 普通字符不能只匹配折叠结果的一部分
+    assert!(names("stras*").is_empty());
+    // 没有闭合的 `[` 和没有通配符的 `[…]` 都是普通字符
+    assert_eq!(names("[draft*"), ["[draft] a.md"]);
+    assert_eq!(names("[draft]"), ["[draft] a.md"]);
+    assert_eq!(names("*.md !readme"), ["[draft] a.md", "Übung.md"]);
+    assert_eq!(names("file:*"), names("file:"));
+    assert!(names("*.r").is_empty());
+    assert!(names("img_???.jpg").is_empty());
+
+    // 通配符中的普通字符高亮
+    let segments = |sub: &str| -> Vec<(String, bool)> {
+        let res: Vec<_> = index.find_iter(sub).collect();
+        assert_eq!(res.len(), 1, "{sub}");
+        let name_start = res[0].inner.rfind(MAIN_SEPARATOR_STR).unwrap() + 1;
+        let mut segments: Vec<_> = res[0].segments().map(|(s, h)| (s.to_string(), h)).collect();
+        // 去掉目录部分
+        segments[0].0.drain(..name_start);
+        segments.retain(|(s, _)| !s.is_empty());
+        segments
+    };
+    let seg = |s: &str, h: bool| (s.to_string(), h);
+    assert_eq!(
+        segments("img_????.jpg"),
+        [seg("IMG_", true), seg("0001", false), seg(".JPG", true)]
+    );
+    assert_eq!(
+        segments("*.rs.*"),
+        [seg("lib", false), seg(".rs.", true), seg("orig", false)]
+    );
+    assert_eq!(
+        segments("?bung*"),
+        [seg("Ü", false), seg("bung", true), seg(".md", false)]
+    );
+    assert_eq!(
+        segments("stra?e.txt"),
+        [seg("Stra", true), seg("ß", false), seg("e.txt", true)]
+    );
+
+    // 使用倒排表时结果相同
+    let expected = names("*_*1*.jpg");
+    index.set_trigrams(true);
+    assert_eq!(names_in(&index, "*_*1*.jpg"), expected);
+    assert_eq!(names_in(&index, "*.rs"), ["main.rs"]);
+}
+
+#[test]
+fn regex() {
+    let mut index = Index::try_from_source(&source()).unwrap();
+    for (frn, name) in [
+        (9, "v1.2.3.zip"),
+        (10, "V10.0.12.ZIP"),
+        (11, "v1.2.zip"),
+        (12, "v1.2.3.zip.part"),
+        (13, "Straße.txt"),
+        (14, "報告 2024.txt"),
+    ] {
+        index.insert(record(frn, 6, name, UsnReason::empty()));
+    }
+    let names = |sub: &str| -> Vec<String> {
+        find(&index, sub)
+            .into_iter()
+            .map(|p| p.rsplit(MAIN_SEPARATOR_STR).next().unwrap().to_string())
+            .collect()
+    };
+
+    // 不区分大小写，可以锚定名称的两端
+    assert_eq!(
+        names(r"regex:^v\d+\.\d+\.\d+\.zip$"),
+        ["V10.0.12.ZIP", "v1.2.3.zip"]
+    );
+    assert_eq!(names(r"file:regex:^read"), ["README.md", "readme.txt"]);
+    assert_eq!(names("folder:regex:t"), ["projects", "fastfind"]);
+    assert_eq!(names("regex:^$"), Vec::<String>::new());
+    // 无效的正则表达式作为普通关键词
+    assert!(names("regex:(").is_empty());
+    // 在折叠前的名称上匹配
+    assert_eq!(names("regex:straße"), ["Straße.txt"]);
+    assert_eq!(names("regex:STRASSE"), Vec::<String>::new());
+    assert_eq!(names(r"regex:^stra\w{2}\."), ["Straße.txt"]);
+    assert_eq!(
+        names(r"file:regex:(?-i)^\p{Lu}"),
+        ["README.md", "Straße.txt", "V10.0.12.ZIP"]
+    );
+    assert_eq!(names(r"regex:^[^\p{Lu}\p{Ll}]"), ["報告 2024.txt"]);
+
+    // 每个匹配都高亮
+    let res: Vec<_> = index.find_iter(r"regex:\d+").collect();
+    let res = res
+        .iter()
+        .find(|p| p.inner.ends_with("V10.0.12.ZIP"))
+        .unwrap();
+    let highlighted: Vec<_> = res.segments().filter(|(_, h)| *h).map(|(s, _)| s).collect();
+    assert_eq!(highlighted, ["10", "0", "12"]);
+    // 高亮的范围直接来自折叠前的名称
+    let highlighted = |sub: &str| -> Vec<String> {
+        let res: Vec<_> = index.find_iter(sub).collect();
+        assert_eq!(res.len(), 1, "{sub}");
+        let segments = res[0].segments().filter(|(_, h)| *h);
+        segments.map(|(s, _)| s.to_string()).collect()
+    };
+    assert_eq!(highlighted(r"regex:\p{Lu}\w*ß"), ["Straß"]);
+    assert_eq!(highlighted(r"regex:(?-i)\p{Lu}.*\.ZIP"), ["V10.0.12.ZIP"]);
+
+    // 匹配完整路径时高亮可以在目录中
+    let sep = regex::escape(MAIN_SEPARATOR_STR);
+    let sub = format!("path:regex:PROJECTS{sep}fast.*{sep}readme");
+    let res: Vec<_> = index.find_iter(&sub).collect();
+    assert_eq!(res.len(), 2);
+    for path in &res {
+        let (dir, matched, _) = path.split();
+        assert_eq!(dir, "X:".to_string() + MAIN_SEPARATOR_STR);
+        assert!(matched.starts_with("projects"));
+    }
+    assert!(index.find_iter("regex:projects.*readme").next().is_none());
+
+    // 并行查找和会话中的结果相同
+    let mut expected = find(&index, r"regex:\.zip");
+    let mut res = Vec::new();
+    let options = ParOptions {
+        threads: 2,
+        ordered: true,
+    };
+    index.par_find(r"regex:\.zip", options, |path| {
+        res.push(path.to_string());
+        true
+    });
+    res.sort();
+    assert_eq!(res, expected);
+    let mut session = Session::new();
+    let mut res: Vec<_> = session
+        .find_iter(&index, r"regex:\.zip$")
+        .map(|p| p.to_string())
+        .collect();
+    res.sort();
+    expected.retain(|p| !p.ends_with(".part"));
+    assert_eq!(res, expected);
+}
+
+#[test]
+fn path_modifier() {
+    let mut index = Index::try_from_source(&source()).unwrap();
+    index.insert(dir(20, 5, "ffd"));
+    index.insert(dir(21, 20, "src"));
+    index.insert(record(22, 21, "main.rs", UsnReason::empty()));
+    index.insert(dir(23, 5, "node_modules"));
+    index.insert(dir(24, 23, "Lodash"));
+    index.insert(record(25, 24, "index.js", UsnReason::empty()));
+    index.insert(record(26, 23, "lodash.md", UsnReason::empty()));
+
+    // 关键词可以跨越分隔符，也可以分别出现在目录和名称中
+    let sub = format!("path:projects{MAIN_SEPARATOR_STR}FFD{MAIN_SEPARATOR_STR}src");
+    assert_eq!(
+        find(&index, &sub),
+        [
+            path(&["X:", "projects", "ffd", "src"]),
+            path(&["X:", "projects", "ffd", "src", "main.rs"]),
+        ]
+    );
+    assert_eq!(
+        find(&index, "path:node_modules lodash"),
+        [
+            path(&["X:", "projects", "node_modules", "Lodash"]),
+            path(&["X:", "projects", "node_modules", "Lodash", "index.js"]),
+            path(&["X:", "projects", "node_modules", "lodash.md"]),
+        ]
+    );
+    assert_eq!(
+        find(&index, "file:path:node_modules !lodash.md"),
+        [path(&[
+            "X:",
+            "projects",
+            "node_modules",
+            "Lodash",
+            "index.js"
+        ])]
+    );
+    // 不加 `path:` 时只匹配名称
+    assert!(find(&index, "node_modules lodash").is_empty());
+    // 通配符需要匹配整个路径
+    let sub = format!("path:*{MAIN_SEPARATOR_STR}src{MAIN_SEPARATOR_STR}*.rs");
+    assert_eq!(
+        find(&index, &sub),
+        [path(&["X:", "projects", "ffd", "src", "main.rs"])]
+    );
+    assert_eq!(
+        find(&index, "path:regex:^x:.ProJects.ffd"),
+        find(&index, "path:x:*ffd*")
+    );
+
+    // 高亮的范围可以在目录中
+    let res: Vec<_> = index.find_iter("path:lodash index").collect();
+    assert_eq!(res.len(), 1);
+    let highlighted: Vec<_> = res[0]
+        .segments()
+        .filter(|(_, h)| *h)
+        .map(|(s, _)| s)
+        .collect();
+    assert_eq!(highlighted, ["Lodash", "index"]);
+
+    // 并行查找、倒排表和会话中的结果相同
+    let expected = find(&index, "path:node_modules lodash");
+    let mut res = Vec::new();
+    let options = ParOptions {
+        threads: 2,
+        ordered: true,
+    };
+    index.par_find("path:node_modules lodash", options, |path| {
+        res.push(path.to_string());
+        true
+    });
+    res.sort();
+    assert_eq!(res, expected);
+    let mut session = Session::new();
+    let mut res: Vec<_> = session
+        .find_iter(&index, "path:node_modules")
+        .map(|p| p.to_string())
+        .collect();
+    assert_eq!(res.len(), 4);
+    res = session
+        .find_iter(&index, "path:node_modules lodash")
+        .map(|p| p.to_string())
+        .collect();
+    res.sort();
+    assert_eq!(res, expected);
+    index.set_trigrams(true);
+    assert_eq!(find(&index, "path:node_modules lodash"), expected);
+
+    // 新加入的文件使用最新的目录
+    index.insert(record(27, 24, "fp.js", UsnReason::empty()));
+    assert_eq!(find(&index, "path:lodash fp").len(), 1);
+
+    // 目录的路径在查找之间共用，新建、重命名、移动或删除目录后仍然正确
+    index.insert(dir(28, 24, "dist"));
+    index.insert(record(29, 28, "lodash.min.js", UsnReason::empty()));
+    assert_eq!(
+        find(&index, "path:dist min"),
+        [path(&[
+            "X:",
+            "projects",
+            "node_modules",
+            "Lodash",
+            "dist",
+            "lodash.min.js"
+        ])]
+    );
+    index.insert(dir(24, 23, "lodash-es"));
+    assert_eq!(
+        find(&index, "path:lodash-es fp"),
+        [path(&[
+            "X:",
+            "projects",
+            "node_modules",
+            "lodash-es",
+            "fp.js"
+        ])]
+    );
+    assert_eq!(
+        find(&index, "path:dist min"),
+        [path(&[
+            "X:",
+            "projects",
+            "node_modules",
+            "lodash-es",
+            "dist",
+            "lodash.min.js"
+        ])]
+    );
+    index.insert(dir(23, 20, "vendor"));
+    assert_eq!(
+        find(&index, "path:vendor fp"),
+        [path(&[
+            "X:",
+            "projects",
+            "ffd",
+            "vendor",
+            "lodash-es",
+            "fp.js"
+        ])]
+    );
+    assert!(find(&index, "path:node_modules").is_empty());
+    index.remove(29);
+    index.remove(28);
+    index.insert(dir(30, 21, "bin"));
+    index.insert(record(31, 30, "ffd.exe", UsnReason::empty()));
+    assert_eq!(
+        find(&index, "path:*src*.exe"),
+        [path(&["X:", "projects", "ffd", "src", "bin", "ffd.exe"])]
+    );
+}
+
+#[test]
+fn kind() {
+    let mut index = Index::try_from_source(&source()).unwrap();
+    index.insert(record(9, 5, "fastfind.md", UsnReason::empty()));
+    assert_eq!(
+        find(&index, "folder:fast"),
+        [path(&["X:", "projects", "fastfind"])]
+    );
+    assert_eq!(
+        find(&index, "file: FAST"),
+        [path(&["X:", "projects", "fastfind.md"])]
+    );
+    assert_eq!(find(&index, "Folder:").len(), 2);
+    assert_eq!(find(&index, "file:").len(), 3);
+    // 以最后一个修饰符为准
+    assert_eq!(find(&index, "folder: file:readme").len(), 2);
+
+    let res: Vec<_> = index.find_iter("folder:fast").collect();
+    assert!(res[0].is_dir());
+    assert_eq!(res[0].split().1, "fast");
+    assert!(index.find_iter("read").all(|p| !p.is_dir()));
+
+    assert_eq!(Kind::parse("folder:src"), (Kind::Folder, "src"));
+    assert_eq!(Kind::parse(" file:src"), (Kind::Any, " file:src"));
+    assert_eq!(Kind::parse("c:src"), (Kind::Any, "c:src"));
+}
+
+#[test]
+fn storage() {
+    // 子项可以先于父目录加入
+    let mut index = Index::with_capacity("X:".to_string(), Cursor { id: 0, usn: 0 }, 0);
+    index.insert(record(7, 6, "README.md", UsnReason::empty()));
+    assert_eq!(find(&index, "read"), [path(&["X:", "README.md"])]);
+    index.insert(dir(6, 5, "fastfind"));
+    index.insert(dir(5, 0, "projects"));
+    assert_eq!(
+        find(&index, "read"),
+        [path(&["X:", "projects", "fastfind", "README.md"])]
+    );
+    assert_eq!(index.len(), 3);
+
+    // 目录被删除后子项的路径从根开始，重新加入后恢复
+    index.remove(6);
+    assert_eq!(find(&index, "read"), [path(&["X:", "README.md"])]);
+    assert!(index.info(6).is_none());
+    index.insert(dir(6, 5, "fastfind"));
+    assert_eq!(
+        find(&index, "read"),
+        [path(&["X:", "projects", "fastfind", "README.md"])]
+    );
+
+    // 反复新建和删除会复用槽位
+    let churn = |index: &mut Index| {
+        for i in 0..1000 {
+            index.insert(record(100 + i, 6, &format!("{i}.tmp"), UsnReason::empty()));
+        }
+        for i in 0..1000 {
+            index.remove(100 + i);
+        }
+    };
+    churn(&mut index);
+    let slots = index.memory_usage().slots;
+    churn(&mut index);
+    assert_eq!(index.memory_usage().slots, slots);
+    assert_eq!(index.len(), 3);
+
+    // 旧名称积累过多时会被整理
+    let long = "n".repeat(1000);
+    for i in 0..3000 {
+        index.insert(record(7, 6, &format!("{long}{i}"), UsnReason::empty()));
+    }
+    let usage = index.memory_usage();
+    assert!(usage.garbage < 2 << 20, "{usage:?}");
+    assert_eq!(
+        index.names(7).collect::<Vec<_>>(),
+        [(6, &*format!("{long}2999"))]
+    );
+    index.shrink_to_fit();
+    let usage = index.memory_usage();
+    assert_eq!(usage.garbage, 0);
+    assert_eq!((usage.files, usage.names), (3, 3));
+    assert!(usage.name_bytes >= 1004 + "fastfind".len() + "projects".len());
+    assert!(usage.to_string().contains("3 个文件"));
+    // 旧名称不会再被找到
+    assert_eq!(find(&index, "n2999").len(), 1);
+    assert!(find(&index, "n2998").is_empty());
+    assert!(find(&index, ".tmp").is_empty());
+}
+
+#[test]
+fn par_find() {
+    let mut index = Index::with_capacity("X:".to_string(), Cursor { id: 0, usn: 0 }, 0);
+    index.insert(dir(5, 0, "projects"));
+    for i in 0..20000 {
+        let name = format!("Report {i}{}", ["", ".txt", ".md"][i % 3]);
+        index.insert(record(100 + i as u128, 5, &name, UsnReason::empty()));
+    }
+    // 删除的名称不会出现在结果中
+    for i in (0..20000).step_by(7) {
+        index.remove(100 + i);
+    }
+
+    // 扫描和使用倒排表时分别按段和按候选名称划分
+    for trigrams in [false, true] {
+        index.set_trigrams(trigrams);
+        let index = &index;
+        let seq = |sub: &str| -> Vec<_> { index.find_iter(sub).map(|p| p.to_string()).collect() };
+        let par = |sub: &str, ordered: bool| {
+            let mut res = Vec::new();
+            let options = ParOptions {
+                threads: 4,
+                ordered,
+            };
+            assert!(index.par_find(sub, options, |path| {
+                res.push(path.to_string());
+                true
+            }));
+            res
+        };
+        for sub in ["report 1", ".TXT", "file:", "folder:", "not found"] {
+            let expected = seq(sub);
+            assert_eq!(par(sub, true), expected, "{sub}");
+            let mut res = par(sub, false);
+            res.sort();
+            assert_eq!(res, find(index, sub), "{sub}");
+        }
+
+        // 回调返回 false 时停止
+        for ordered in [true, false] {
+            let mut count = 0;
+            let options = ParOptions {
+                threads: 4,
+                ordered,
+            };
+            assert!(!index.par_find("report", options, |_| {
+                count += 1;
+                count < 10
+            }));
+            assert_eq!(count, 10);
+        }
+    }
+}
+
+#[test]
+fn trigrams() {
+    let mut plain = Index::try_from_source(&source()).unwrap();
+    let mut indexed = Index::try_from_source(&source()).unwrap();
+    indexed.set_trigrams(true);
+    assert!(indexed.has_trigrams() && !plain.has_trigrams());
+    assert!(indexed.memory_usage().trigrams > 0);
+    assert_eq!(plain.memory_usage().trigrams, 0);
+
+    let queries = [
+        "",
+        "r",
+        "me",
+        "read",
+        "README.MD",
+        "fastfind",
+        "ind",
+        "e.t",
+        "文档",
+        "档",
+        "新建",
+        "folder:ind",
+        "file:read",
+        "not found",
+    ];
+    let check = |plain: &Index, indexed: &Index| {
+        for sub in queries {
+            assert_eq!(find(indexed, sub), find(plain, sub), "{sub}");
+            let options = ParOptions {
+                threads: 4,
+                ordered: true,
+            };
+            let mut res = Vec::new();
+            indexed.par_find(sub, options, |path| {
+                res.push(path.to_string());
+                true
+            });
+            let expected: Vec<_> = indexed.find_iter(sub).map(|p| p.to_string()).collect();
+            assert_eq!(res, expected, "{sub}");
+        }
+    };
+    check(&plain, &indexed);
+
+    // 倒排表随增删和重命名更新
+    for index in [&mut plain, &mut indexed] {
+        index.insert(record(9, 6, "新建文档.txt", UsnReason::empty()));
+        index.insert(record(8, 5, "readme.old", UsnReason::empty()));
+        index.add_link(record(8, 6, "Index.md", UsnReason::empty()));
+        index.remove(7);
+    }
+    check(&plain, &indexed);
+    assert_eq!(
+        find(&indexed, "me.old"),
+        [path(&["X:", "projects", "readme.old"])]
+    );
+    assert!(find(&indexed, "me.txt").is_empty());
+
+    // 整理名称区后重建
+    indexed.shrink_to_fit();
+    check(&plain, &indexed);
+    indexed.set_trigrams(false);
+    assert_eq!(indexed.memory_usage().trigrams, 0);
+    check(&plain, &indexed);
+}
+
+#[test]
+fn session() {
+    let check = |session: &mut Session, index: &Index, sub: &str| {
+        let expected: Vec<_> = index.find_iter(sub).map(|p| p.to_string()).collect();
+        let res: Vec<_> = session
+            .find_iter(index, sub)
+            .map(|p| p.to_string())
+            .collect();
+        assert_eq!(res, expected, "{sub}");
+        let mut res = Vec::new();
+        let options = ParOptions {
+            threads: 4,
+            ordered: true,
+        };
+        assert!(session.par_find(index, sub, options, |path| {
+            res.push(path.to_string());
+            true
+        }));
+        assert_eq!(res, expected, "{sub}");
+    };
+
+    let mut index = Index::try_from_source(&source()).unwrap();
+    let mut session = Session::new();
+    // 延长关键词、切换类型、换成无关的关键词
+    for sub in [
+        "r",
+        "re",
+        "REA",
+        "read",
+        "readme.",
+        "readme. md",
+        "readme. !md",
+        "file:readme.",
+        "folder:read",
+        "fast",
+        "(a|e) me",
+        "me",
+        "fas",
+        "",
+    ] {
+        check(&mut session, &index, sub);
+    }
+
+    // 同步后新增的名称会被找到，删除的名称不会
+    check(&mut session, &index, "read");
+    index.insert(record(9, 6, "readme2.md", UsnReason::empty()));
+    index.remove(8);
+    index.insert(record(7, 5, "README.md", UsnReason::empty()));
+    index.add_link(record(7, 6, "Readme.link", UsnReason::empty()));
+    for sub in ["read", "readm", "readme", "readme.md"] {
+        check(&mut session, &index, sub);
+    }
+
+    // 整理名称区后序号改变
+    check(&mut session, &index, "read");
+    index.shrink_to_fit();
+    check(&mut session, &index, "readme");
+
+    // 换成另一个索引
+    let mut other = Index::try_from_source(&source()).unwrap();
+    other.set_trigrams(true);
+    check(&mut session, &other, "readme");
+    other.insert(record(10, 5, "readme.old", UsnReason::empty()));
+    check(&mut session, &other, "readme.");
+    session.clear();
+    check(&mut session, &index, "readme.");
+}
+
+#[test]
+fn sync() {
+    let mut src = source();
+    let mut index = Index::try_from_source(&src).unwrap();
+    src.journal = vec![
+        record(9, 6, "main.rs", UsnReason::FILE_CREATE | UsnReason::CLOSE),
+        record(
+            8,
+            6,
+            "readme.txt",
+            UsnReason::FILE_DELETE | UsnReason::CLOSE,
+        ),
+        record(
+            7,
+            5,
+            "README.md",
+            UsnReason::RENAME_NEW_NAME | UsnReason::CLOSE,
+        ),
+    ];
+    index.sync(&src).unwrap();
+
+    assert_eq!(index.cursor(), Cursor { id: 1, usn: 3 });
+    assert_eq!(
+        find(&index, "main"),
+        [path(&["X:", "projects", "fastfind", "main.rs"])]
+    );
+    assert_eq!(
+        find(&index, "readme"),
+        [path(&["X:", "projects", "README.md"])]
+    );
+
+    // 再次同步不会重复处理已读取的变更
+    index.sync(&src).unwrap();
+    assert_eq!(index.cursor(), Cursor { id: 1, usn: 3 });
+}
+
+#[test]
+fn sync_unknown_frn() {
+    let unknown = UsnRecord::UNKNOWN_FRN;
+    let mut src = source();
+    let mut index = Index::try_from_source(&src).unwrap();
+    src.journal = vec![
+        // 按父目录和名称找到文件，名称必须完全相同
+        record(unknown, 6, "readme.txt", UsnReason::RENAME_OLD_NAME),
+        record(
+            8,
+            5,
+            "notes.txt",
+            UsnReason::RENAME_NEW_NAME | UsnReason::CLOSE,
+        ),
+        record(
+            unknown,
+            6,
+            "README",
+            UsnReason::FILE_DELETE | UsnReason::CLOSE,
+        ),
+        record(
+            unknown,
+            9,
+            "README.md",
+            UsnReason::FILE_DELETE | UsnReason::CLOSE,
+        ),
+    ];
+    index.sync(&src).unwrap();
+    assert_eq!(
+        find(&index, "notes"),
+        [path(&["X:", "projects", "notes.txt"])]
+    );
+    assert_eq!(
+        find(&index, "readme"),
+        [path(&["X:", "projects", "fastfind", "README.md"])]
+    );
+
+    // 删除目录时连同其中的内容一起删除
+    src.journal.push(record(
+        unknown,
+        5,
+        "fastfind",
+        UsnReason::FILE_DELETE | UsnReason::CLOSE,
+    ));
+    index.sync(&src).unwrap();
+    assert_eq!(find(&index, "fastfind"), Vec::<String>::new());
+    assert_eq!(find(&index, "readme"), Vec::<String>::new());
+    assert_eq!(
+        find(&index, "notes"),
+        [path(&["X:", "projects", "notes.txt"])]
+    );
+}
+
+fn sync_err(index: &mut Index, src: &FakeSource) -> IndexStale {
+    *index.sync(src).unwrap_err().downcast_ref().unwrap()
+}
+
+#[test]
+fn stale() {
+    let create = UsnReason::FILE_CREATE | UsnReason::CLOSE;
+    let mut src = source();
+    src.journal = vec![record(9, 6, "main.rs", create)];
+    let mut index = Index::try_from_source(&src).unwrap();
+    src.journal.push(record(10, 6, "lib.rs", create));
+
+    // 日志被删除重建
+    src.id = 2;
+    assert_eq!(
+        sync_err(&mut index, &src),
+        IndexStale::JournalChanged {
+            expected: 1,
+            found: 2
+        }
+    );
+
+    // 需要的记录已被回收
+    src.id = 1;
+    src.lowest = 2;
+    assert_eq!(
+        sync_err(&mut index, &src),
+        IndexStale::Deleted {
+            usn: 1,
+            lowest_valid_usn: 2
+        }
+    );
+
+    // 日志比索引记录的位置还短
+    src.lowest = 0;
+    src.journal.clear();
+    assert_eq!(
+        sync_err(&mut index, &src),
+        IndexStale::Ahead {
+            usn: 1,
+            next_usn: 0
+        }
+    );
+
+    // 出错时索引保持不变
+    assert_eq!(index.cursor(), Cursor { id: 1, usn: 1 });
+    assert!(find(&index, "lib").is_empty());
+}
+
+#[test]
+fn sync_or_rebuild() {
+    let mut src = source();
+    let mut index = Index::try_from_source(&src).unwrap();
+    index.set_trigrams(true);
+
+    // 正常情况下只是同步
+    src.journal = vec![record(
+        9,
+        6,
+        "main.rs",
+        UsnReason::FILE_CREATE | UsnReason::CLOSE,
+    )];
+    index.sync_or_rebuild(&src).unwrap();
+    assert_eq!(find(&index, "main").len(), 1);
+
+    // 过期后按当前的文件记录重建
+    src.id = 2;
+    src.records.pop();
+    index.sync_or_rebuild(&src).unwrap();
+    assert_eq!(index.cursor(), Cursor { id: 2, usn: 1 });
+    assert!(find(&index, "main").is_empty());
+    assert_eq!(find(&index, "").len(), 3);
+    assert!(index.has_trigrams());
+}
+
+fn snapshot(index: &Index) -> Vec<u8> {
+    let mut buf = Vec::new();
+    index.save(&mut buf).unwrap();
+    buf
+}
+
+#[test]
+fn snapshot_round_trip() {
+    let mut index = Index::try_from_source(&source()).unwrap();
+    index.insert(UsnRecord {
+        usn: 4096,
+        timestamp: 133_000_000_000_000_000,
+        attributes: FileAttributes::HIDDEN | FileAttributes::ARCHIVE,
+        source_info: 1,
+        security_id: 0x105,
+        ..record(1 << 100 | 9, 6, "中文.txt", UsnReason::empty())
+    });
+    let buf = snapshot(&index);
+
+    let loaded = Index::load(buf.as_slice()).unwrap();
+    assert_eq!(loaded.driver(), index.driver());
+    assert_eq!(loaded.cursor(), index.cursor());
+    assert_eq!(find(&loaded, ""), find(&index, ""));
+    assert_eq!(
+        find(&loaded, "中文"),
+        [path(&["X:", "projects", "fastfind", "中文.txt"])]
+    );
+    assert_eq!(loaded.info(1 << 100 | 9), index.info(1 << 100 | 9));
+    assert_eq!(loaded.info(1 << 100 | 9).unwrap().security_id, 0x105);
+}
+
+#[test]
+fn info() {
+    let mut index = Index::try_from_source(&source()).unwrap();
+    assert_eq!(index.info(7), Some(&FileInfo::default()));
+    assert_eq!(index.info(100), None);
+
+    // 只修改数据的记录也会更新元数据
+    let modified = UsnRecord {
+        usn: 8192,
+        timestamp: 133_500_000_000_000_000,
+        attributes: FileAttributes::ARCHIVE,
+        ..record(7, 6, "README.md", UsnReason::DATA_EXTEND | UsnReason::CLOSE)
+    };
+    index.apply(modified.clone());
+    let info = index.info(7).unwrap();
+    assert_eq!((info.usn, info.timestamp), (8192, 133_500_000_000_000_000));
+    assert_eq!(info.attributes, FileAttributes::ARCHIVE);
+    assert_eq!(
+        modified.time().unwrap(),
+        UNIX_EPOCH + Duration::from_secs(1_705_526_400)
+    );
+
+    // 中间记录不会
+    index.apply(UsnRecord {
+        usn: 9000,
+        ..record(7, 6, "README.md", UsnReason::DATA_OVERWRITE)
+    });
+    assert_eq!(index.info(7).unwrap().usn, 8192);
+
+    // 重命名同样会更新元数据
+    index.apply(UsnRecord {
+        attributes: FileAttributes::DIRECTORY,
+        ..record(6, 5, "ffd", UsnReason::RENAME_NEW_NAME | UsnReason::CLOSE)
+    });
+    assert!(index.info(6).unwrap().attributes.is_dir());
+}
+
+#[test]
+fn snapshot_corrupted() {
+    let buf = snapshot(&Index::try_from_source(&source()).unwrap());
+
+    // 任意截断
+    for len in 0..buf.len() {
+        assert!(Index::load(&buf[..len]).is_err(), "截断到 {len}B");
+    }
+    // 任意一个字节被修改
+    for i in 0..buf.len() {
+        let mut buf = buf.clone();
+        buf[i] ^= 0x40;
+        assert!(Index::load(buf.as_slice()).is_err(), "修改第 {i} 字节");
+    }
+    // 末尾多出数据
+    let mut long = buf.clone();
+    long.push(0);
+    assert!(Index::load(long.as_slice()).is_err());
+    // 其他版本
+    let mut buf = buf.clone();
+    buf[8] = 0xFF;
+    let err = Index::load(buf.as_slice()).err().unwrap();
+    assert!(err.to_string().contains("版本"));
+}
+
+#[test]
+fn load_or_build() {
+    let dir = std::path::Path::new(env!("CARGO_TARGET_TMPDIR")).join("index_load_or_build");
+    std::fs::create_dir_all(&dir).unwrap();
+    let file = dir.join("X.idx");
+    let _ = std::fs::remove_file(&file);
+
+    // 没有快照时重新建立
+    let mut src = source();
+    let index = Index::load_or_build(&src, &file).unwrap();
+    assert_eq!(find(&index, "").len(), 4);
+    index.save_file(&file).unwrap();
+
+    // 从快照中恢复后只重放新的变更；records 不再被读取
+    src.records.clear();
+    src.journal = vec![record(
+        9,
+        6,
+        "main.rs",
+        UsnReason::FILE_CREATE | UsnReason::CLOSE,
+    )];
+    let index = Index::load_or_build(&src, &file).unwrap();
+    assert_eq!(index.cursor(), Cursor { id: 1, usn: 1 });
+    assert_eq!(find(&index, "").len(), 5);
+
+    // 日志 ID 变化时重新建立
+    src.id = 2;
+    let index = Index::load_or_build(&src, &file).unwrap();
+    assert_eq!(index.cursor(), Cursor { id: 2, usn: 1 });
+    assert_eq!(find(&index, ""), Vec::<String>::new());
+
+    // 快照损坏时重新建立
+    std::fs::write(&file, b"FFDINDEX").unwrap();
+    let index = Index::load_or_build(&src, &file).unwrap();
+    assert_eq!(find(&index, ""), Vec::<String>::new());
+
+    // 变更流不会保留时不读取快照，即使日志 ID 相同
+    let mut src = source();
+    Index::try_from_source(&src)
+        .unwrap()
+        .save_file(&file)
+        .unwrap();
+    src.records.pop();
+    src.persistent = false;
+    let index = Index::load_or_build(&src, &file).unwrap();
+    assert_eq!(find(&index, "").len(), 3);
+}
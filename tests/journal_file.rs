@@ -0,0 +1,106 @@
+mod common;
+
+use std::{io::Read, path::MAIN_SEPARATOR_STR};
+
+use ffd::{Cursor, Index, JournalFile, UsnReason, UsnRecord};
+
+use common::usn::{v2, v3, v4};
+
+/// 每次最多读取 7 字节，让记录跨越缓冲区边界
+struct SlowReader<'a>(&'a [u8]);
+
+impl Read for SlowReader<'_> {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        let n = buf.len().min(7).min(self.0.len());
+        buf[..n].copy_from_slice(&self.0[..n]);
+        self.0 = &self.0[n..];
+        Ok(n)
+    }
+}
+
+const CREATE: UsnReason = UsnReason::FILE_CREATE.union(UsnReason::CLOSE);
+const RENAME: UsnReason = UsnReason::RENAME_NEW_NAME.union(UsnReason::CLOSE);
+
+fn journal() -> Vec<u8> {
+    // 开头被回收的部分是稀疏的
+    let mut buf = vec![0; 4096];
+    buf.extend(v2(10, 5, 4096, CREATE, "docs"));
+    buf.resize(8192, 0);
+    buf.extend(v3(11, 10, 8192, CREATE, "a.txt"));
+    buf.extend(v4(11, 8192, 0, &[]));
+    // 长度不是 8 的倍数
+    buf.extend([12, 0, 0, 0, 2, 0, 0, 0]);
+    let mut bad = v2(12, 10, 8400, CREATE, "bad.txt");
+    bad[58..60].copy_from_slice(&1000u16.to_le_bytes());
+    buf.extend(bad);
+    buf.extend(v2(11, 10, 8500, RENAME, "b.txt"));
+    buf
+}
+
+fn records(buf: &[u8]) -> Vec<UsnRecord> {
+    JournalFile::new(SlowReader(buf))
+        .map(|r| r.unwrap())
+        .collect()
+}
+
+#[test]
+fn parse() {
+    let records = records(&journal());
+    let res: Vec<_> = records
+        .iter()
+        .map(|r| {
+            (
+                r.usn,
+                r.timestamp,
+                r.frn,
+                r.parent_frn,
+                &*r.filename,
+                r.reason,
+            )
+        })
+        .collect();
+    assert_eq!(
+        res,
+        [
+            (4096, 40960, 10, 5, "docs", CREATE),
+            (8192, 81920, 11, 10, "a.txt", CREATE),
+            (8500, 85000, 11, 10, "b.txt", RENAME),
+        ]
+    );
+}
+
+#[test]
+fn truncated() {
+    let mut buf = journal();
+    let record = v2(13, 10, 9000, CREATE, "c.txt");
+    buf.extend(&record[..record.len() - 8]);
+    let res: Vec<_> = JournalFile::new(SlowReader(&buf)).collect();
+    assert_eq!(res.len(), 4);
+    assert!(res[..3].iter().all(|e| e.is_ok()));
+    assert!(res[3].is_err());
+}
+
+#[test]
+fn corrupted_length() {
+    // 损坏的记录的长度覆盖了下一条记录，解析失败后只跳过 8 字节，下一条记录仍然能找到
+    let mut buf = journal();
+    let next = v2(13, 10, 9000, CREATE, "c.txt");
+    let mut bad = v2(14, 10, 8900, CREATE, "bad.txt");
+    let len = (bad.len() + next.len()) as u32;
+    bad[..4].copy_from_slice(&len.to_le_bytes());
+    bad[58..60].copy_from_slice(&61u16.to_le_bytes());
+    buf.extend(bad);
+    buf.extend(next);
+    let names: Vec<_> = records(&buf).into_iter().map(|r| r.filename).collect();
+    assert_eq!(names, ["docs", "a.txt", "b.txt", "c.txt"]);
+}
+
+#[test]
+fn replay() {
+    let mut index = Index::with_capacity("X:".to_string(), Cursor { id: 0, usn: 0 }, 16);
+    for record in records(&journal()) {
+        index.apply(record);
+    }
+    let res: Vec<_> = index.find_iter(".txt").map(|p| p.to_string()).collect();
+    assert_eq!(res, [["X:", "docs", "b.txt"].join(MAIN_SEPARATOR_STR)]);
+}
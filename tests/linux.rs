@@ -0,0 +1,213 @@
+#![cfg(target_os = "linux")]
+
+use std::{fs, os::unix::fs::symlink, path::PathBuf};
+
+use ffd::{Index, Mount, RecordSource};
+
+fn tree(name: &str) -> PathBuf {
+    let root = PathBuf::from(env!("CARGO_TARGET_TMPDIR")).join(name);
+    let _ = fs::remove_dir_all(&root);
+    fs::create_dir_all(root.join("projects/fastfind/src")).unwrap();
+    fs::write(root.join("projects/fastfind/README.md"), "").unwrap();
+    fs::write(root.join("projects/fastfind/src/main.rs"), "").unwrap();
+    symlink(root.join("projects"), root.join("projects/link")).unwrap();
+    root
+}
+
+fn find(index: &Index, sub: &str) -> Vec<String> {
+    let mut res: Vec<_> = index.find_iter(sub).map(|p| p.to_string()).collect();
+    res.sort();
+    res
+}
+
+#[test]
+fn walk() {
+    let root = tree("walk");
+    let mount = Mount::open(root.to_string_lossy().into_owned()).unwrap();
+    let mut names: Vec<_> = mount
+        .records()
+        .map(|r| {
+            let r = r.unwrap();
+            (r.filename, r.attributes.is_dir())
+        })
+        .collect();
+    names.sort();
+    // 符号链接本身会被记录，但不会跟随
+    assert_eq!(
+        names,
+        [
+            ("README.md".to_string(), false),
+            ("fastfind".to_string(), true),
+            ("link".to_string(), false),
+            ("main.rs".to_string(), false),
+            ("projects".to_string(), true),
+            ("src".to_string(), true),
+        ]
+    );
+}
+
+#[test]
+fn build_and_find() {
+    let root = tree("build_and_find");
+    let driver = root.to_string_lossy().into_owned();
+    let mount = Mount::open(driver.clone()).unwrap();
+    let index = Index::try_from_source(&mount).unwrap();
+    assert_eq!(
+        find(&index, "MAIN"),
+        [format!("{driver}/projects/fastfind/src/main.rs")]
+    );
+
+    let res: Vec<_> = index.find_iter("read").collect();
+    assert_eq!(
+        res[0].split(),
+        (&*format!("{driver}/projects/fastfind/"), "READ", "ME.md")
+    );
+}
+
+#[test]
+fn not_dir() {
+    let root = tree("not_dir");
+    let file = root.join("projects/fastfind/README.md");
+    assert!(Mount::open(file.to_string_lossy().into_owned()).is_err());
+}
+
+#[test]
+fn sync() {
+    let root = tree("sync");
+    let driver = root.to_string_lossy().into_owned();
+    let mount = Mount::open(driver.clone()).unwrap();
+    let mut index = Index::try_from_source(&mount).unwrap();
+
+    fs::write(root.join("projects/fastfind/Cargo.toml"), "").unwrap();
+    fs::create_dir_all(root.join("projects/other/deep")).unwrap();
+    fs::write(root.join("projects/other/deep/lib.rs"), "").unwrap();
+    fs::rename(
+        root.join("projects/fastfind/README.md"),
+        root.join("projects/other/README.md"),
+    )
+    .unwrap();
+    fs::remove_file(root.join("projects/fastfind/src/main.rs")).unwrap();
+    index.sync(&mount).unwrap();
+
+    assert_eq!(
+        find(&index, "cargo"),
+        [format!("{driver}/projects/fastfind/Cargo.toml")]
+    );
+    assert_eq!(
+        find(&index, "lib.rs"),
+        [format!("{driver}/projects/other/deep/lib.rs")]
+    );
+    assert_eq!(
+        find(&index, "readme"),
+        [format!("{driver}/projects/other/README.md")]
+    );
+    assert!(find(&index, "main").is_empty());
+
+    // 目录改名后，其中新建的文件也能被跟踪
+    fs::rename(root.join("projects/other"), root.join("projects/renamed")).unwrap();
+    fs::write(root.join("projects/renamed/deep/new.txt"), "").unwrap();
+    index.sync(&mount).unwrap();
+    assert_eq!(
+        find(&index, "new.txt"),
+        [format!("{driver}/projects/renamed/deep/new.txt")]
+    );
+
+    // 移出监听范围等同于删除
+    let outside = PathBuf::from(env!("CARGO_TARGET_TMPDIR")).join("sync_outside");
+    let _ = fs::remove_dir_all(&outside);
+    fs::rename(root.join("projects/renamed"), &outside).unwrap();
+    index.sync(&mount).unwrap();
+    assert!(find(&index, "new.txt").is_empty());
+    assert!(find(&index, "renamed").is_empty());
+}
+
+#[test]
+fn hard_link() {
+    let root = tree("hard_link");
+    let driver = root.to_string_lossy().into_owned();
+    fs::hard_link(
+        root.join("projects/fastfind/README.md"),
+        root.join("projects/README.md"),
+    )
+    .unwrap();
+    let mount = Mount::open(driver.clone()).unwrap();
+    let mut index = Index::try_from_source(&mount).unwrap();
+    assert_eq!(
+        find(&index, "readme"),
+        [
+            format!("{driver}/projects/README.md"),
+            format!("{driver}/projects/fastfind/README.md"),
+        ]
+    );
+
+    fs::hard_link(
+        root.join("projects/README.md"),
+        root.join("projects/fastfind/src/README.md"),
+    )
+    .unwrap();
+    fs::rename(
+        root.join("projects/README.md"),
+        root.join("projects/RENAMED.md"),
+    )
+    .unwrap();
+    fs::remove_file(root.join("projects/fastfind/README.md")).unwrap();
+    index.sync(&mount).unwrap();
+    assert_eq!(
+        find(&index, ".md"),
+        [
+            format!("{driver}/projects/RENAMED.md"),
+            format!("{driver}/projects/fastfind/src/README.md"),
+        ]
+    );
+}
+
+#[test]
+fn overwrite_rename() {
+    let root = tree("overwrite_rename");
+    let driver = root.to_string_lossy().into_owned();
+    let dir = root.join("projects/fastfind");
+    fs::write(dir.join("a.txt"), "").unwrap();
+    fs::write(dir.join("b.txt"), "").unwrap();
+    let mount = Mount::open(driver.clone()).unwrap();
+    let mut index = Index::try_from_source(&mount).unwrap();
+
+    // 新名称原有的文件被替换
+    fs::rename(dir.join("a.txt"), dir.join("b.txt")).unwrap();
+    index.sync(&mount).unwrap();
+    assert_eq!(
+        find(&index, ".txt"),
+        [format!("{driver}/projects/fastfind/b.txt")]
+    );
+
+    // 从监听范围外移入时也一样
+    let outside = PathBuf::from(env!("CARGO_TARGET_TMPDIR")).join("overwrite_outside.txt");
+    fs::write(&outside, "").unwrap();
+    fs::rename(&outside, dir.join("b.txt")).unwrap();
+    index.sync(&mount).unwrap();
+    assert_eq!(
+        find(&index, ".txt"),
+        [format!("{driver}/projects/fastfind/b.txt")]
+    );
+    assert_eq!(index.len(), 7);
+}
+
+#[test]
+fn overflow() {
+    let root = tree("overflow");
+    let driver = root.to_string_lossy().into_owned();
+    let mount = Mount::open(driver.clone()).unwrap();
+    let mut index = Index::try_from_source(&mount).unwrap();
+
+    // 超出事件队列的长度后重新建立索引
+    let max: usize = fs::read_to_string("/proc/sys/fs/inotify/max_queued_events")
+        .unwrap()
+        .trim()
+        .parse()
+        .unwrap();
+    let dir = root.join("projects/fastfind");
+    for i in 0..=max {
+        fs::write(dir.join(format!("{i}.tmp")), "").unwrap();
+    }
+    index.sync_or_rebuild(&mount).unwrap();
+    assert_eq!(find(&index, ".tmp").len(), max + 1);
+}
@@ -0,0 +1,306 @@
+use std::{io, path::MAIN_SEPARATOR_STR};
+
+use ffd::{Index, NtfsImage, RecordSource};
+
+const CLUSTER: usize = 512;
+const RECORD: usize = 1024;
+const RECORDS: usize = 24;
+const ROOT: u64 = 5 << 48 | 5;
+
+/// $MFT 分为两段，第二段在第一段之前，用来覆盖负的相对偏移
+const RUN1: (usize, usize) = (100, 16);
+const RUN2: (usize, usize) = (4, 32);
+
+fn file_name(parent: u64, namespace: u8, name: &str) -> Vec<u8> {
+    let name: Vec<u16> = name.encode_utf16().collect();
+    let mut value = vec![0; 0x42];
+    value[..8].copy_from_slice(&parent.to_le_bytes());
+    value[0x40] = name.len() as u8;
+    value[0x41] = namespace;
+    value.extend(name.iter().flat_map(|ch| ch.to_le_bytes()));
+
+    let mut attr = vec![0; 0x18];
+    attr[..4].copy_from_slice(&0x30u32.to_le_bytes());
+    attr[0x10..0x14].copy_from_slice(&(value.len() as u32).to_le_bytes());
+    attr[0x14..0x16].copy_from_slice(&0x18u16.to_le_bytes());
+    attr.extend(value);
+    finish_attr(attr)
+}
+
+fn standard_information(modified: i64, attributes: u32) -> Vec<u8> {
+    let mut value = vec![0; 0x48];
+    value[0x08..0x10].copy_from_slice(&modified.to_le_bytes());
+    value[0x20..0x24].copy_from_slice(&attributes.to_le_bytes());
+
+    let mut attr = vec![0; 0x18];
+    attr[..4].copy_from_slice(&0x10u32.to_le_bytes());
+    attr[0x10..0x14].copy_from_slice(&(value.len() as u32).to_le_bytes());
+    attr[0x14..0x16].copy_from_slice(&0x18u16.to_le_bytes());
+    attr.extend(value);
+    finish_attr(attr)
+}
+
+/// 正常的数据运行：第二段的相对偏移为负
+fn runs() -> Vec<u8> {
+    let offset = RUN2.0 as i8 - RUN1.0 as i8;
+    vec![
+        0x11,
+        RUN1.1 as u8,
+        RUN1.0 as u8,
+        0x11,
+        RUN2.1 as u8,
+        offset as u8,
+        0,
+    ]
+}
+
+fn mft_data(runs: &[u8]) -> Vec<u8> {
+    let size = (RECORDS * RECORD) as u64;
+    let mut attr = vec![0; 0x40];
+    attr[..4].copy_from_slice(&0x80u32.to_le_bytes());
+    attr[8] = 1;
+    attr[0x20..0x22].copy_from_slice(&0x40u16.to_le_bytes());
+    attr[0x28..0x30].copy_from_slice(&size.to_le_bytes());
+    attr[0x30..0x38].copy_from_slice(&size.to_le_bytes());
+    attr.extend(runs);
+    finish_attr(attr)
+}
+
+fn finish_attr(mut attr: Vec<u8>) -> Vec<u8> {
+    attr.resize(attr.len().next_multiple_of(8), 0);
+    let len = attr.len() as u32;
+    attr[4..8].copy_from_slice(&len.to_le_bytes());
+    attr
+}
+
+fn file_record(seq: u16, flags: u16, base: u64, attrs: &[Vec<u8>]) -> Vec<u8> {
+    let mut buf = vec![0; RECORD];
+    buf[..4].copy_from_slice(b"FILE");
+    buf[4..6].copy_from_slice(&0x30u16.to_le_bytes());
+    buf[6..8].copy_from_slice(&3u16.to_le_bytes());
+    buf[0x10..0x12].copy_from_slice(&seq.to_le_bytes());
+    buf[0x14..0x16].copy_from_slice(&0x38u16.to_le_bytes());
+    buf[0x16..0x18].copy_from_slice(&flags.to_le_bytes());
+    buf[0x20..0x28].copy_from_slice(&base.to_le_bytes());
+
+    let mut offset = 0x38;
+    for attr in attrs {
+        buf[offset..offset + attr.len()].copy_from_slice(attr);
+        offset += attr.len();
+    }
+    buf[offset..offset + 4].copy_from_slice(&u32::MAX.to_le_bytes());
+
+    // 更新序列号写到每个扇区末尾，原始数据保存到更新序列数组
+    buf[0x30..0x32].copy_from_slice(&[0x01, 0x00]);
+    for i in 1..3 {
+        let end = i * 512;
+        let orig = [buf[end - 2], buf[end - 1]];
+        buf[0x30 + i * 2..0x32 + i * 2].copy_from_slice(&orig);
+        buf[end - 2..end].copy_from_slice(&[0x01, 0x00]);
+    }
+    buf
+}
+
+fn image() -> Vec<u8> {
+    image_with_runs(&runs())
+}
+
+fn image_with_runs(runs: &[u8]) -> Vec<u8> {
+    let mut records = vec![vec![0; RECORD]; RECORDS];
+    records[0] = file_record(1, 0x01, 0, &[mft_data(runs), file_name(ROOT, 3, "$MFT")]);
+    records[5] = file_record(5, 0x03, 0, &[file_name(ROOT, 3, ".")]);
+    records[16] = file_record(2, 0x03, 0, &[file_name(ROOT, 1, "docs")]);
+    records[17] = file_record(
+        1,
+        0x01,
+        0,
+        &[
+            standard_information(133_500_000_000_000_000, 0x22),
+            file_name(2 << 48 | 16, 2, "REPORT~1.DOC"),
+            file_name(2 << 48 | 16, 1, "Report 2024.docx"),
+        ],
+    );
+    records[18] = file_record(1, 0x00, 0, &[file_name(ROOT, 3, "deleted.txt")]);
+    records[19] = file_record(1, 0x01, 1 << 48 | 17, &[file_name(ROOT, 1, "ext.txt")]);
+    records[20] = file_record(1, 0x01, 0, &[file_name(ROOT, 3, "broken.txt")]);
+    records[20][RECORD - 2] = 0xEE;
+    records[21] = file_record(1, 0x01, 0, &[file_name(ROOT, 0, "中文.txt")]);
+    // 硬链接：两个目录中各有一个名称，另有一个 DOS 短名
+    records[22] = file_record(
+        1,
+        0x01,
+        0,
+        &[
+            file_name(ROOT, 1, "link a.txt"),
+            file_name(2 << 48 | 16, 1, "link b.txt"),
+            file_name(2 << 48 | 16, 2, "LINKB~1.TXT"),
+        ],
+    );
+
+    let mut buf = vec![0; (RUN1.0 + RUN1.1) * CLUSTER];
+    buf[3..11].copy_from_slice(b"NTFS    ");
+    buf[0x0B..0x0D].copy_from_slice(&512u16.to_le_bytes());
+    buf[0x0D] = 1;
+    buf[0x30..0x38].copy_from_slice(&(RUN1.0 as u64).to_le_bytes());
+    buf[0x40] = -10i8 as u8;
+
+    let mft = records.concat();
+    let (mft1, mft2) = mft.split_at(RUN1.1 * CLUSTER);
+    buf[RUN1.0 * CLUSTER..][..mft1.len()].copy_from_slice(mft1);
+    buf[RUN2.0 * CLUSTER..][..mft2.len()].copy_from_slice(mft2);
+    buf
+}
+
+#[test]
+fn records() {
+    let image = NtfsImage::new("X:".to_string(), io::Cursor::new(image())).unwrap();
+    let records: Vec<_> = image
+        .records()
+        .map(|r| {
+            let r = r.unwrap();
+            // NTFS 的 FRN 只有 64 位
+            let frn = u64::try_from(r.frn).unwrap();
+            (frn, r.parent_frn as u64, r.filename, r.attributes.bits())
+        })
+        .collect();
+    assert_eq!(
+        records,
+        [
+            (1 << 48, ROOT, "$MFT".to_string(), 0),
+            (2 << 48 | 16, ROOT, "docs".to_string(), 0x10),
+            (
+                1 << 48 | 17,
+                2 << 48 | 16,
+                "Report 2024.docx".to_string(),
+                0x22
+            ),
+            (1 << 48 | 21, ROOT, "中文.txt".to_string(), 0),
+            (1 << 48 | 22, ROOT, "link a.txt".to_string(), 0),
+            (1 << 48 | 22, 2 << 48 | 16, "link b.txt".to_string(), 0),
+        ]
+    );
+
+    let report = image.records().nth(2).unwrap().unwrap();
+    assert_eq!(report.timestamp, 133_500_000_000_000_000);
+}
+
+#[test]
+fn build_and_find() {
+    let image = NtfsImage::new("X:".to_string(), io::Cursor::new(image())).unwrap();
+    let mut index = Index::try_from_source(&image).unwrap();
+    let res: Vec<_> = index.find_iter("report").map(|p| p.to_string()).collect();
+    assert_eq!(
+        res,
+        [["X:", "docs", "Report 2024.docx"].join(MAIN_SEPARATOR_STR)]
+    );
+
+    // 硬链接的两个名称都能找到
+    let res: Vec<_> = index.find_iter("link").map(|p| p.to_string()).collect();
+    assert_eq!(
+        res,
+        [
+            ["X:", "link a.txt"].join(MAIN_SEPARATOR_STR),
+            ["X:", "docs", "link b.txt"].join(MAIN_SEPARATOR_STR),
+        ]
+    );
+    assert_eq!(index.names(1 << 48 | 22).count(), 2);
+
+    // 镜像没有变更
+    index.sync(&image).unwrap();
+    assert_eq!(index.find_iter("").count(), 6);
+}
+
+#[test]
+fn invalid() {
+    let mut buf = image();
+    buf[0x40] = 0;
+    assert!(NtfsImage::new("X:".to_string(), io::Cursor::new(buf)).is_err());
+
+    let mut buf = image();
+    buf[3..11].copy_from_slice(b"EXFAT   ");
+    assert!(NtfsImage::new("X:".to_string(), io::Cursor::new(buf)).is_err());
+
+    // $MFT 记录本身损坏
+    let mut buf = image();
+    buf[RUN1.0 * CLUSTER + 510] = 0xEE;
+    assert!(NtfsImage::new("X:".to_string(), io::Cursor::new(buf)).is_err());
+}
+
+fn open(buf: Vec<u8>) -> anyhow::Result<NtfsImage<io::Cursor<Vec<u8>>>> {
+    NtfsImage::new("X:".to_string(), io::Cursor::new(buf))
+}
+
+#[test]
+fn corrupted_boot_sector() {
+    // 每簇扇区数为 2 的负指数次方时，指数过大会溢出
+    for n in [0x81, 0xA0, 0xC0, 0xC1] {
+        let mut buf = image();
+        buf[0x0D] = n;
+        let e = open(buf).err().unwrap();
+        assert!(e.to_string().contains("引导扇区"), "{n:#x}：{e}");
+    }
+    let mut buf = image();
+    buf[0x0B..0x0D].copy_from_slice(&0u16.to_le_bytes());
+    assert!(open(buf).is_err());
+
+    // 文件记录大小为 2 的 128、64 或 32 次方
+    for n in [0x80, 0xC0, 0xE0] {
+        let mut buf = image();
+        buf[0x40] = n;
+        let e = open(buf).err().unwrap();
+        assert!(e.to_string().contains("引导扇区"), "{n:#x}：{e}");
+    }
+
+    // $MFT 的位置溢出
+    let mut buf = image();
+    buf[0x30..0x38].copy_from_slice(&u64::MAX.to_le_bytes());
+    assert!(open(buf).err().unwrap().to_string().contains("引导扇区"));
+    // 没有溢出但超出镜像
+    let mut buf = image();
+    buf[0x30..0x38].copy_from_slice(&(1u64 << 40).to_le_bytes());
+    assert!(open(buf).is_err());
+}
+
+#[test]
+fn corrupted_runs() {
+    let max = u64::MAX.to_le_bytes();
+    let cases: Vec<Vec<u8>> = vec![
+        // 长度乘以簇大小溢出
+        [&[0x18][..], &max[..7], &[0x7F, 0x00, 0]].concat(),
+        // 长度为负
+        vec![0x11, 0xF0, 0x10, 0],
+        // 第一段的位置为负
+        vec![0x11, 0x10, 0x80, 0],
+        // 相对偏移相加溢出
+        [
+            &[0x81, 0x01],
+            &max[..7],
+            &[0x7F, 0x81, 0x01],
+            &max[..7],
+            &[0x7F, 0][..],
+        ]
+        .concat(),
+        // 位置乘以簇大小溢出
+        [&[0x81, 0x01], &max[..7], &[0x7F, 0][..]].concat(),
+        // 所有段的总长度溢出
+        [&[0x08], &max[..7], &[0x00, 0x08], &max[..7], &[0x00, 0][..]].concat(),
+        // 属性在数据中间结束，以及非法的头部
+        vec![0x11, 0x10, 0x64, 0x11, 0x10, 0x64, 0x11, 0x10],
+        vec![0x09, 0x01, 0],
+        vec![0x10, 0x01, 0],
+    ];
+    for runs in cases {
+        let e = open(image_with_runs(&runs)).err();
+        let e = e.unwrap_or_else(|| panic!("{runs:x?} 没有报错"));
+        assert!(e.to_string().contains("数据运行"), "{runs:x?}：{e}");
+    }
+
+    // 开头 4 个稀疏的簇按 0 读取，之后的记录编号都加 2，根目录也不再是自己的父目录
+    let mut runs = runs();
+    runs.splice(..0, [0x01, 0x04]);
+    let image = open(image_with_runs(&runs)).unwrap();
+    let records: Vec<_> = image.records().map(Result::unwrap).collect();
+    assert_eq!(records[0].frn, 1 << 48 | 2);
+    let names: Vec<_> = records.iter().map(|r| r.filename.as_str()).collect();
+    assert_eq!(names, ["$MFT", ".", "docs", "Report 2024.docx", "中文.txt"]);
+}
@@ -0,0 +1,122 @@
+mod common;
+
+use std::path::MAIN_SEPARATOR_STR;
+
+use common::{find, highlighted, names, Fixture};
+use ffd::Query;
+
+const ROOT: u128 = 5;
+const DIR: u128 = 99;
+
+const NAMES: &[&str] = &[
+    "报告书.docx",
+    "2024年度报告.pdf",
+    "重庆火锅.jpg",
+    "重要文件.txt",
+    "银行流水.xlsx",
+    "行程单.pdf",
+    "长城.png",
+    "绿色.txt",
+    "佛经.txt",
+    "report.txt",
+];
+
+/// `项目资料` 目录中有 `NAMES` 中的名称，另有一个同名的 `报告书.docx` 在根目录中
+fn index() -> Fixture {
+    let mut fixture = Fixture::new(|sub| Query::parse(sub).unwrap());
+    fixture.add_dir(DIR, ROOT, "项目资料");
+    fixture.add_files(DIR, 100, NAMES);
+    fixture.add_files(ROOT, 200, &["报告书.docx"]);
+    fixture
+}
+
+#[test]
+fn initials_and_full() {
+    let index = index();
+    for sub in ["bgs", "baogao shu", "baogs", "BGS", "bgs.docx", "报gs"] {
+        assert_eq!(
+            names(&index, &format!("pinyin:{sub}")),
+            ["报告书.docx"],
+            "{sub}"
+        );
+    }
+    assert_eq!(
+        names(&index, "pinyin:baogao"),
+        ["2024年度报告.pdf", "报告书.docx"]
+    );
+    assert_eq!(names(&index, "pinyin:2024nd"), ["2024年度报告.pdf"]);
+    assert_eq!(names(&index, "pinyin:lvse"), ["绿色.txt"]);
+    // 不按拼音时只匹配原样的关键词
+    assert!(names(&index, "bgs").is_empty());
+    // 按拼音时原样的关键词仍然匹配
+    assert_eq!(names(&index, "pinyin:rep"), ["report.txt"]);
+    assert_eq!(
+        names(&index, "pinyin:bg !docx | zc"),
+        ["2024年度报告.pdf", "长城.png"]
+    );
+    assert_eq!(names(&index, "file:pinyin:xmzl"), Vec::<String>::new());
+    assert_eq!(names(&index, "folder:pinyin:xmzl"), ["项目资料"]);
+}
+
+#[test]
+fn polyphones() {
+    let index = index();
+    assert_eq!(names(&index, "pinyin:chongqing"), ["重庆火锅.jpg"]);
+    assert_eq!(names(&index, "pinyin:zhongyao"), ["重要文件.txt"]);
+    assert_eq!(names(&index, "pinyin:cq"), ["重庆火锅.jpg"]);
+    assert_eq!(names(&index, "pinyin:yinhang"), ["银行流水.xlsx"]);
+    assert_eq!(names(&index, "pinyin:xingcheng"), ["行程单.pdf"]);
+    assert_eq!(names(&index, "pinyin:changcheng"), ["长城.png"]);
+    assert_eq!(names(&index, "pinyin:zhangcheng"), ["长城.png"]);
+    assert_eq!(names(&index, "pinyin:fojing"), ["佛经.txt"]);
+    assert_eq!(names(&index, "pinyin:fujing"), ["佛经.txt"]);
+    // 同一个字的不同读音不能拼在一起
+    assert!(names(&index, "pinyin:chongg").is_empty());
+}
+
+#[test]
+fn path() {
+    let index = index();
+    let res = find(&index, "path:pinyin:xmzl bgs");
+    assert_eq!(res.len(), 1);
+    let expected = ["X:", "项目资料", "报告书.docx"].join(MAIN_SEPARATOR_STR);
+    assert_eq!(res[0].inner, expected);
+    let highlighted: Vec<_> = res[0]
+        .segments()
+        .filter(|(_, h)| *h)
+        .map(|(s, _)| s)
+        .collect();
+    assert_eq!(highlighted, ["项目资料", "报告书"]);
+    assert_eq!(find(&index, "pinyin:bgs").len(), 2);
+}
+
+#[test]
+fn highlight() {
+    let index = index();
+    // 高亮按拼音匹配的整个汉字
+    assert_eq!(
+        highlighted(&index, "pinyin:bg", "2024年度报告.pdf"),
+        ["报告"]
+    );
+    assert_eq!(highlighted(&index, "pinyin:ba", "报告书.docx"), ["报"]);
+    assert_eq!(
+        highlighted(&index, "pinyin:baogs", "报告书.docx"),
+        ["报告书"]
+    );
+    assert_eq!(
+        highlighted(&index, "pinyin:2024nd", "2024年度报告.pdf"),
+        ["2024年度"]
+    );
+    assert_eq!(
+        highlighted(&index, "pinyin:bgs .docx", "报告书.docx"),
+        ["报告书.docx"]
+    );
+    assert_eq!(
+        highlighted(&index, "pinyin:yh lius", "银行流水.xlsx"),
+        ["银行流水"]
+    );
+    assert_eq!(
+        highlighted(&index, "pinyin:shui.x", "银行流水.xlsx"),
+        ["水.x"]
+    );
+}
@@ -0,0 +1,150 @@
+use ffd::{Expr, Kind, ParseError, ParseErrorKind, Query};
+
+fn term(s: &str) -> Expr {
+    Expr::Term(s.to_string())
+}
+
+fn not(expr: Expr) -> Expr {
+    Expr::Not(Box::new(expr))
+}
+
+fn parse(s: &str) -> Expr {
+    let query = Query::parse(s).unwrap();
+    assert_eq!(query.kind, Kind::Any);
+    query.expr
+}
+
+fn error(s: &str) -> (usize, ParseErrorKind) {
+    let ParseError { pos, kind } = Query::parse(s).unwrap_err();
+    (pos, kind)
+}
+
+#[test]
+fn ast() {
+    assert_eq!(parse("report"), term("report"));
+    assert_eq!(parse(""), Expr::And(vec![]));
+    assert_eq!(parse("  "), Expr::And(vec![]));
+    assert_eq!(
+        parse("invoice  2024"),
+        Expr::And(vec![term("invoice"), term("2024")])
+    );
+    // `|` 的优先级最低
+    assert_eq!(
+        parse("report | invoice !draft"),
+        Expr::Or(vec![
+            term("report"),
+            Expr::And(vec![term("invoice"), not(term("draft"))]),
+        ])
+    );
+    assert_eq!(
+        parse("(jpg|png) -thumbnail"),
+        Expr::And(vec![
+            Expr::Or(vec![term("jpg"), term("png")]),
+            not(term("thumbnail")),
+        ])
+    );
+    assert_eq!(
+        parse("\"new folder\" -\"(1)\""),
+        Expr::And(vec![term("new folder"), not(term("(1)"))])
+    );
+    assert_eq!(parse("!!a"), not(not(term("a"))));
+    assert_eq!(parse("-(a b)"), not(Expr::And(vec![term("a"), term("b")])));
+
+    // 词中间的 `!` 和 `-`、单独的 `-` 都是普通字符
+    assert_eq!(parse("a-b!"), term("a-b!"));
+    assert_eq!(
+        parse("a - b.mp3"),
+        Expr::And(vec![term("a"), term("-"), term("b.mp3")])
+    );
+    assert_eq!(
+        parse("文档|报告"),
+        Expr::Or(vec![term("文档"), term("报告")])
+    );
+    // 通配符也是普通的关键词，匹配时才区分
+    assert_eq!(
+        parse("*.rs|[a-c]?.md"),
+        Expr::Or(vec![term("*.rs"), term("[a-c]?.md")])
+    );
+
+    // `regex:` 之后的内容整个作为正则表达式
+    let regex = |pattern: &str| Expr::Regex(pattern.to_string());
+    assert_eq!(parse("regex:^a (b|c)$"), regex("^a (b|c)$"));
+    let query = Query::parse("file: regex:\\d").unwrap();
+    assert_eq!((query.kind, query.path), (Kind::File, false));
+    assert_eq!(query.expr, regex("\\d"));
+    assert!(Query::regex("x+", true).unwrap().path);
+
+    // 修饰符的顺序任意
+    let query = Query::parse("Path:file:REGEX:src/.*\\.rs$").unwrap();
+    assert_eq!((query.kind, query.path), (Kind::File, true));
+    assert_eq!(query.expr, regex("src/.*\\.rs$"));
+    let query = Query::parse("path: node_modules lodash").unwrap();
+    assert!(query.path);
+    assert_eq!(
+        query.expr,
+        Expr::And(vec![term("node_modules"), term("lodash")])
+    );
+    assert!(!Query::parse("a path:b").unwrap().path);
+    let query = Query::parse("pinyin:Path: bgs 2024").unwrap();
+    assert!(query.path && query.pinyin);
+    assert_eq!(query.expr, Expr::And(vec![term("bgs"), term("2024")]));
+    assert!(Query::literal("pinyin:a (").pinyin);
+    assert!(Query::literal("folder:path:a (").path);
+
+    let query = Query::parse("Folder: src | test").unwrap();
+    assert_eq!(query.kind, Kind::Folder);
+    assert_eq!(query.expr, Expr::Or(vec![term("src"), term("test")]));
+}
+
+#[test]
+fn display() {
+    for s in [
+        "report",
+        "invoice 2024",
+        "report | invoice !draft",
+        "(jpg | png) !thumbnail",
+        "\"new folder\" !\"-x\"",
+        "!(a | b) c",
+        "file:a (b c | d)",
+        "regex:^v\\d+ (a|b)$",
+        "folder:path:regex:src",
+        "path:ffd\\src !target",
+        "file:path:pinyin:bgs | \"bg s\"",
+        "IMG_????.jpg | *.[ch] !\"* *\"",
+        "",
+    ] {
+        let query = Query::parse(s).unwrap();
+        assert_eq!(query.to_string(), s);
+        assert_eq!(query.to_string().parse::<Query>().unwrap(), query);
+    }
+    assert_eq!(Query::parse("-a  |b").unwrap().to_string(), "!a | b");
+}
+
+#[test]
+fn errors() {
+    assert_eq!(error("a \"b c"), (2, ParseErrorKind::UnclosedQuote));
+    assert_eq!(error("(a | b"), (0, ParseErrorKind::UnclosedParen));
+    assert_eq!(error("a (b (c)"), (2, ParseErrorKind::UnclosedParen));
+    assert_eq!(error("a b)"), (3, ParseErrorKind::UnmatchedParen));
+    assert_eq!(error("a |"), (2, ParseErrorKind::MissingOperand('|')));
+    assert_eq!(error("| a"), (0, ParseErrorKind::MissingOperand('|')));
+    assert_eq!(error("a || b"), (2, ParseErrorKind::MissingOperand('|')));
+    assert_eq!(error("a  !"), (3, ParseErrorKind::MissingOperand('!')));
+    assert_eq!(error("(a | -)"), (5, ParseErrorKind::MissingOperand('-')));
+    assert_eq!(error("a ( )"), (2, ParseErrorKind::MissingOperand('(')));
+    // 位置包括开头的类型修饰符，按字节计算
+    assert_eq!(error("file: 文档 )"), (13, ParseErrorKind::UnmatchedParen));
+
+    // 正则表达式的错误位置为表达式开头
+    let (pos, kind) = error("file:regex:a(");
+    assert_eq!(pos, 11);
+    assert!(matches!(kind, ParseErrorKind::InvalidRegex(_)));
+    let e = Query::regex("[", false).unwrap_err();
+    assert_eq!(e.pos, 0);
+    assert!(e.to_string().starts_with("位置 0：正则表达式无效"));
+
+    let e = Query::parse("(a").unwrap_err();
+    assert_eq!(e.to_string(), "位置 0：括号没有闭合");
+    let e: Box<dyn std::error::Error> = Box::new(e);
+    assert!(e.to_string().contains("括号"));
+}
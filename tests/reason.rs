@@ -0,0 +1,49 @@
+use ffd::UsnReason;
+
+const CREATE: UsnReason = UsnReason::FILE_CREATE;
+const CLOSE: UsnReason = UsnReason::CLOSE;
+
+#[test]
+fn flags() {
+    let reason = CREATE | UsnReason::DATA_EXTEND | CLOSE;
+    assert!(reason.contains(CREATE | CLOSE));
+    assert!(!reason.contains(CREATE | UsnReason::FILE_DELETE));
+    assert!(reason.intersects(CREATE | UsnReason::FILE_DELETE));
+    assert!(!reason.intersects(UsnReason::FILE_DELETE));
+    assert_eq!(reason - CLOSE, CREATE | UsnReason::DATA_EXTEND);
+    assert_eq!(reason & CLOSE, CLOSE);
+    assert_eq!(u32::from(CREATE | CLOSE), 0x8000_0100);
+    assert_eq!(UsnReason::from(0x8000_0100), CREATE | CLOSE);
+
+    // 从低位到高位，未定义的位也会产生
+    let flags: Vec<_> = UsnReason::from_bits(0x8000_0108).iter().collect();
+    assert_eq!(flags, [UsnReason::from_bits(0x8), CREATE, CLOSE]);
+    assert_eq!(UsnReason::empty().iter().count(), 0);
+}
+
+#[test]
+fn display() {
+    assert_eq!((CREATE | CLOSE).to_string(), "FILE_CREATE|CLOSE");
+    assert_eq!(
+        (UsnReason::RENAME_NEW_NAME | UsnReason::from_bits(0x8)).to_string(),
+        "0x8|RENAME_NEW_NAME"
+    );
+    assert_eq!(UsnReason::empty().to_string(), "0");
+    assert_eq!(format!("{:?}", CLOSE), "UsnReason(CLOSE)");
+}
+
+#[test]
+fn parse() {
+    for bits in [0, 0x100, 0x8000_0100, 0x8000_0108, 0x01ff_ff77] {
+        let reason = UsnReason::from_bits(bits);
+        assert_eq!(reason.to_string().parse::<UsnReason>().unwrap(), reason);
+    }
+    assert_eq!(
+        "usn_reason_file_create | Close"
+            .parse::<UsnReason>()
+            .unwrap(),
+        CREATE | CLOSE
+    );
+    assert!("FILE_CREATE|OPEN".parse::<UsnReason>().is_err());
+    assert!("".parse::<UsnReason>().is_err());
+}
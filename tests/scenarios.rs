@@ -0,0 +1,238 @@
+// 用合成的变更记录流驱动索引，覆盖关闭记录中多种原因组合在一起的情况。
+
+mod common;
+
+use std::path::MAIN_SEPARATOR_STR;
+
+use common::record;
+use ffd::{Cursor, Index, UsnReason, UsnRecord};
+
+const CLOSE: UsnReason = UsnReason::CLOSE;
+const CREATE: UsnReason = UsnReason::FILE_CREATE;
+const DELETE: UsnReason = UsnReason::FILE_DELETE;
+const OLD_NAME: UsnReason = UsnReason::RENAME_OLD_NAME;
+const NEW_NAME: UsnReason = UsnReason::RENAME_NEW_NAME;
+const HARD_LINK: UsnReason = UsnReason::HARD_LINK_CHANGE;
+const DATA_OVERWRITE: UsnReason = UsnReason::DATA_OVERWRITE;
+const DATA_EXTEND: UsnReason = UsnReason::DATA_EXTEND;
+
+const DOCS: u128 = 10;
+const SRC: u128 = 11;
+
+/// 在包含 `docs` 和 `src` 两个目录的索引上依次应用 `stream`，返回所有路径。
+fn replay(stream: &[UsnRecord]) -> Vec<String> {
+    let mut index = Index::with_capacity("X:".to_string(), Cursor { id: 0, usn: 0 }, 16);
+    index.insert(record(DOCS, 5, "docs", UsnReason::empty()));
+    index.insert(record(SRC, 5, "src", UsnReason::empty()));
+    for record in stream {
+        index.apply(record.clone());
+    }
+
+    let mut res: Vec<_> = index.find_iter("").map(|p| p.to_string()).collect();
+    res.sort();
+    // 增删名称时维护的计数与实际的名称一致
+    assert_eq!(index.memory_usage().names, res.len());
+    res
+}
+
+fn paths(paths: &[&str]) -> Vec<String> {
+    let mut res: Vec<_> = ["X:/docs", "X:/src"]
+        .iter()
+        .chain(paths)
+        .map(|p| p.replace('/', MAIN_SEPARATOR_STR))
+        .collect();
+    res.sort();
+    res
+}
+
+#[test]
+fn create() {
+    let stream = [record(20, DOCS, "a.txt", CREATE | CLOSE)];
+    assert_eq!(replay(&stream), paths(&["X:/docs/a.txt"]));
+}
+
+#[test]
+fn create_and_rename() {
+    // 新建后在关闭前重命名，关闭记录中是最终的名称
+    let stream = [
+        record(20, DOCS, "New Text Document.txt", CREATE),
+        record(20, DOCS, "New Text Document.txt", CREATE | OLD_NAME),
+        record(20, DOCS, "notes.txt", CREATE | OLD_NAME | NEW_NAME),
+        record(20, DOCS, "notes.txt", CREATE | OLD_NAME | NEW_NAME | CLOSE),
+    ];
+    assert_eq!(replay(&stream), paths(&["X:/docs/notes.txt"]));
+}
+
+#[test]
+fn overwrite_and_rename() {
+    let stream = [
+        record(20, DOCS, "a.txt", CREATE | CLOSE),
+        record(
+            20,
+            DOCS,
+            "b.txt",
+            DATA_OVERWRITE | OLD_NAME | NEW_NAME | CLOSE,
+        ),
+    ];
+    assert_eq!(replay(&stream), paths(&["X:/docs/b.txt"]));
+}
+
+#[test]
+fn rename_to_new_parent() {
+    let stream = [
+        record(20, DOCS, "a.txt", CREATE | CLOSE),
+        record(
+            20,
+            SRC,
+            "main.rs",
+            OLD_NAME | NEW_NAME | DATA_EXTEND | CLOSE,
+        ),
+    ];
+    assert_eq!(replay(&stream), paths(&["X:/src/main.rs"]));
+}
+
+#[test]
+fn rename_dir() {
+    let stream = [
+        record(20, DOCS, "a.txt", CREATE | CLOSE),
+        record(DOCS, SRC, "documents", OLD_NAME | NEW_NAME | CLOSE),
+    ];
+    assert_eq!(
+        replay(&stream),
+        ["X:/src", "X:/src/documents", "X:/src/documents/a.txt"]
+            .map(|p| p.replace('/', MAIN_SEPARATOR_STR))
+    );
+}
+
+#[test]
+fn create_and_delete() {
+    // 临时文件在关闭前就被删除了
+    let stream = [record(
+        20,
+        DOCS,
+        "~$tmp",
+        CREATE | DATA_EXTEND | DELETE | CLOSE,
+    )];
+    assert_eq!(replay(&stream), paths(&[]));
+}
+
+#[test]
+fn other_reasons() {
+    // 没有 CLOSE 的记录和只涉及数据的记录不影响索引
+    let stream = [
+        record(20, DOCS, "a.txt", CREATE | CLOSE),
+        record(20, DOCS, "b.txt", NEW_NAME),
+        record(20, DOCS, "a.txt", DATA_OVERWRITE | DATA_EXTEND | CLOSE),
+        record(21, DOCS, "c.txt", CREATE),
+    ];
+    assert_eq!(replay(&stream), paths(&["X:/docs/a.txt"]));
+}
+
+#[test]
+fn hard_link() {
+    let create = [
+        record(20, DOCS, "a.txt", CREATE | CLOSE),
+        record(20, SRC, "link.txt", HARD_LINK | CLOSE),
+    ];
+    assert_eq!(
+        replay(&create),
+        paths(&["X:/docs/a.txt", "X:/src/link.txt"])
+    );
+
+    // 同一个名称再次出现说明链接被删除了
+    let remove = [
+        create.as_slice(),
+        &[record(20, DOCS, "a.txt", HARD_LINK | CLOSE)],
+    ]
+    .concat();
+    assert_eq!(replay(&remove), paths(&["X:/src/link.txt"]));
+}
+
+#[test]
+fn hard_link_rename() {
+    // 多个名称时由旧名称的记录决定替换哪一个
+    let stream = [
+        record(20, DOCS, "a.txt", CREATE | CLOSE),
+        record(20, SRC, "link.txt", HARD_LINK | CLOSE),
+        record(20, SRC, "link.txt", OLD_NAME),
+        record(20, SRC, "renamed.txt", OLD_NAME | NEW_NAME),
+        record(20, SRC, "renamed.txt", OLD_NAME | NEW_NAME | CLOSE),
+    ];
+    assert_eq!(
+        replay(&stream),
+        paths(&["X:/docs/a.txt", "X:/src/renamed.txt"])
+    );
+}
+
+#[test]
+fn hard_link_delete() {
+    let stream = [
+        record(20, DOCS, "a.txt", CREATE | CLOSE),
+        record(20, SRC, "link.txt", CREATE | CLOSE),
+        record(20, DOCS, "a.txt", DELETE | CLOSE),
+    ];
+    assert_eq!(replay(&stream), paths(&["X:/src/link.txt"]));
+
+    // 最后一个名称被删除
+    let stream = [&stream[..], &[record(20, SRC, "link.txt", DELETE | CLOSE)]].concat();
+    assert_eq!(replay(&stream), paths(&[]));
+}
+
+#[test]
+fn unknown_frn() {
+    // inotify 的删除和移动记录只有父目录和名称，第一次用到时建立的映射随名称的增删更新
+    let unknown = UsnRecord::UNKNOWN_FRN;
+    let stream = [
+        record(20, DOCS, "a.txt", CREATE | CLOSE),
+        record(unknown, DOCS, "a.txt", OLD_NAME),
+        record(20, SRC, "a.txt", NEW_NAME | CLOSE),
+        record(21, DOCS, "a.txt", CREATE | CLOSE),
+        record(unknown, SRC, "a.txt", DELETE | CLOSE),
+        record(22, DOCS, "b.txt", CREATE | CLOSE),
+        record(22, SRC, "c.txt", HARD_LINK | CLOSE),
+        record(unknown, DOCS, "b.txt", DELETE | CLOSE),
+        record(unknown, DOCS, "missing.txt", DELETE | CLOSE),
+    ];
+    assert_eq!(replay(&stream), paths(&["X:/docs/a.txt", "X:/src/c.txt"]));
+}
+
+#[test]
+fn names() {
+    let mut index = Index::with_capacity("X:".to_string(), Cursor { id: 0, usn: 0 }, 16);
+    assert!(index.add_link(record(20, DOCS, "a.txt", UsnReason::empty())));
+    assert!(index.add_link(record(20, SRC, "b.txt", UsnReason::empty())));
+    assert!(!index.add_link(record(20, SRC, "b.txt", UsnReason::empty())));
+    let names: Vec<_> = index.names(20).collect();
+    assert_eq!(names, [(DOCS, "a.txt"), (SRC, "b.txt")]);
+
+    // 主名称被删除后由其余名称顶替
+    assert!(index.remove_link(20, DOCS, "a.txt"));
+    assert!(!index.remove_link(20, DOCS, "a.txt"));
+    let names: Vec<_> = index.names(20).collect();
+    assert_eq!(names, [(SRC, "b.txt")]);
+
+    // insert 会替换所有名称
+    index.add_link(record(20, DOCS, "c.txt", UsnReason::empty()));
+    index.insert(record(20, DOCS, "d.txt", UsnReason::empty()));
+    assert_eq!(index.names(20).count(), 1);
+    assert!(index.remove(20).is_some());
+    assert_eq!(index.names(20).count(), 0);
+}
+
+#[test]
+fn self_parent() {
+    let mut index = Index::with_capacity("X:".to_string(), Cursor { id: 0, usn: 0 }, 16);
+    // 自己是自己的父目录时，路径只向上拼接有限的层数
+    index.insert(record(5, 5, "a", UsnReason::empty()));
+    assert_eq!(index.find_iter("a").count(), 1);
+
+    // 槽位只被回收一次，不会同时分给两个文件
+    assert!(index.remove(5).is_some());
+    index.insert(record(100, DOCS, "b", UsnReason::empty()));
+    index.insert(record(7, DOCS, "c", UsnReason::empty()));
+    assert_eq!(index.names(100).collect::<Vec<_>>(), [(DOCS, "b")]);
+    assert_eq!(index.names(7).collect::<Vec<_>>(), [(DOCS, "c")]);
+    assert_eq!(index.names(5).count(), 0);
+    assert_eq!(index.names(DOCS).count(), 0);
+    assert_eq!(index.len(), 2);
+}
@@ -0,0 +1,147 @@
+mod common;
+
+use common::{file_name, find, highlighted, names, Fixture};
+use ffd::Query;
+
+/// 大小写折叠容易出错的名称：折叠后变长或变短、大小写不是一一对应、组合字符、连字等
+const CORPUS: &[&str] = &[
+    // `İ` 折叠为 `i̇`，变长；无点的 `ı` 不折叠为 `i`
+    "İstanbul.txt",
+    "ISTANBUL.txt",
+    "ıstanbul.txt",
+    // `ß` 和 `ẞ` 折叠为 `ss`，长 `ſ` 折叠为 `s`
+    "Straße.TXT",
+    "STRAẞE.txt",
+    "ſtraſſe.txt",
+    // 末尾的 `ς` 和 `σ` 相同
+    "ΟΔΟΣ ΚΑΙ ΟΔΟΣ.txt",
+    "οδος και οδος.md",
+    "ΣΟΦΙΑ",
+    // 连字和标题大小写的二合字母
+    "ﬁnal ﬂow.pdf",
+    "Ǆemal ǅ ǆ.txt",
+    // 开尔文、埃和欧姆符号
+    "\u{212a}elvin 5\u{212a}.txt",
+    "\u{212b}ngström.txt",
+    "\u{2126}mega.txt",
+    // 带下标 iota 的希腊字母折叠为两个字符，`ΐ` 折叠为组合字符序列
+    "ᾼ ᾳ ΐ.txt",
+    "ŉ ǰ.txt",
+    // 预组合与分解的字符不做规范化
+    "café.txt",
+    "cafe\u{301} 2.txt",
+    "ᲀ Ꭰꭰ.txt",
+    "新建文件夹 📁.txt",
+    "مرحبا.txt",
+];
+
+fn index() -> Fixture {
+    // 不解析查询语法，整个关键词按原样匹配
+    let mut fixture = Fixture::new(Query::literal);
+    fixture.add_files(5, 100, CORPUS);
+    fixture
+}
+
+#[test]
+fn substrings() {
+    let index = index();
+    for name in CORPUS {
+        let chars: Vec<_> = name.char_indices().map(|(i, _)| i).collect();
+        for (n, &start) in chars.iter().enumerate() {
+            for len in 1..=3 {
+                let end = chars.get(n + len).copied().unwrap_or(name.len());
+                let sub = &name[start..end];
+                for query in [sub.to_string(), sub.to_uppercase(), sub.to_lowercase()] {
+                    let res = find(&index, &query);
+                    for path in &res {
+                        // 高亮的范围都在字符边界上，拼起来就是完整路径
+                        let segments: String = path.segments().map(|(s, _)| s).collect();
+                        assert_eq!(segments, path.inner, "{query:?}");
+                        let (_, matched, _) = path.split();
+                        assert!(!matched.trim().is_empty() || query.trim().is_empty());
+                    }
+                    // 原样的片段一定能找到原名称
+                    if query == sub {
+                        assert!(
+                            res.iter().any(|p| file_name(p) == *name),
+                            "{query:?} 没有匹配 {name:?}"
+                        );
+                    }
+                }
+            }
+        }
+    }
+}
+
+#[test]
+fn folding() {
+    let index = index();
+    assert_eq!(
+        names(&index, "strasse"),
+        ["STRAẞE.txt", "Straße.TXT", "ſtraſſe.txt"]
+    );
+    assert_eq!(names(&index, "STRAẞE"), names(&index, "strasse"));
+    assert_eq!(
+        names(&index, "οδοσ"),
+        ["ΟΔΟΣ ΚΑΙ ΟΔΟΣ.txt", "οδος και οδος.md"]
+    );
+    assert_eq!(names(&index, "ΣΟΦΙΑ"), names(&index, "σοφια"));
+    assert_eq!(names(&index, "final flow"), ["ﬁnal ﬂow.pdf"]);
+    assert_eq!(names(&index, "ǅemal"), ["Ǆemal ǅ ǆ.txt"]);
+    assert_eq!(names(&index, "\u{1c6} \u{1c6}"), ["Ǆemal ǅ ǆ.txt"]);
+    assert_eq!(names(&index, "kelvin 5k"), ["\u{212a}elvin 5\u{212a}.txt"]);
+    assert_eq!(names(&index, "ångström"), ["\u{212b}ngström.txt"]);
+    assert_eq!(names(&index, "ωmega"), ["\u{2126}mega.txt"]);
+    assert_eq!(names(&index, "αι αι"), ["ᾼ ᾳ ΐ.txt"]);
+    assert_eq!(names(&index, "ꭰᎠ"), ["ᲀ Ꭰꭰ.txt"]);
+    assert_eq!(names(&index, "в"), ["ᲀ Ꭰꭰ.txt"]);
+
+    // 不是土耳其语的折叠：`İ` 不等于 `i`，`ı` 也不等于 `i`
+    assert_eq!(names(&index, "istanbul"), ["ISTANBUL.txt"]);
+    assert_eq!(names(&index, "i\u{307}stanbul"), ["İstanbul.txt"]);
+    assert_eq!(names(&index, "stanbul").len(), 3);
+    // 不做规范化
+    assert_eq!(names(&index, "café"), ["café.txt"]);
+    assert_eq!(names(&index, "caf").len(), 2);
+}
+
+#[test]
+fn highlight() {
+    let index = index();
+    // 匹配折叠结果的一部分时高亮整个字符
+    assert_eq!(highlighted(&index, "s", "Straße"), ["S"]);
+    assert_eq!(highlighted(&index, "sse", "Straße"), ["ße"]);
+    assert_eq!(highlighted(&index, "ras", "Straße"), ["raß"]);
+    assert_eq!(highlighted(&index, "sse", "STRAẞE"), ["ẞE"]);
+    assert_eq!(highlighted(&index, "stra", "ſtraſſe"), ["ſtra"]);
+    assert_eq!(highlighted(&index, "in", "ﬁnal"), ["ﬁn"]);
+    assert_eq!(highlighted(&index, "low", "ﬁnal"), ["ﬂow"]);
+    assert_eq!(highlighted(&index, "\u{307}s", "İstanbul"), ["İs"]);
+    assert_eq!(highlighted(&index, "ι", "ᾼ"), ["ᾼ"]);
+    assert_eq!(highlighted(&index, "\u{301}", "ᾼ"), ["ΐ"]);
+    assert_eq!(highlighted(&index, "n ǰ", "ŉ"), ["ŉ ǰ"]);
+    assert_eq!(highlighted(&index, "ΚΑΙ ΟΔΟΣ.", "ΟΔΟΣ"), ["ΚΑΙ ΟΔΟΣ."]);
+    assert_eq!(highlighted(&index, "ς κ", "οδος"), ["ς κ"]);
+    assert_eq!(highlighted(&index, "📁", "新建"), ["📁"]);
+
+    // 多个关键词和通配符的高亮同样映射回原名称
+    let res = index
+        .index
+        .find_query(&Query::parse("strasse .txt").unwrap())
+        .find(|p| file_name(p).starts_with("STRAẞE"))
+        .unwrap();
+    let highlighted: Vec<_> = res.segments().filter(|(_, h)| *h).map(|(s, _)| s).collect();
+    assert_eq!(highlighted, ["STRAẞE.txt"]);
+    let res = find(&index, "*ße.txt");
+    let highlighted: Vec<_> = res
+        .iter()
+        .map(|p| {
+            p.segments()
+                .filter(|(_, h)| *h)
+                .map(|(s, _)| s)
+                .collect::<String>()
+        })
+        .collect();
+    assert_eq!(highlighted.len(), 3);
+    assert!(highlighted.contains(&"ẞE.txt".to_string()));
+}
@@ -0,0 +1,300 @@
+mod common;
+
+use std::time::{Duration, UNIX_EPOCH};
+
+use ffd::{
+    from_filetime, to_filetime, FileAttributes, RangeRecord, RawRecords, RecordError, UsnExtent,
+    UsnReason, UsnRecord,
+};
+
+use common::usn::{v2, v3, v4};
+
+const CREATE: UsnReason = UsnReason::FILE_CREATE.union(UsnReason::CLOSE);
+
+fn fields(r: &UsnRecord) -> (u128, u128, &str, UsnReason) {
+    (r.frn, r.parent_frn, &r.filename, r.reason)
+}
+
+#[test]
+fn parse() {
+    let buf = v2(11, 10, 0, CREATE, "a.txt");
+    let (record, len) = UsnRecord::parse(&buf).unwrap();
+    assert_eq!(len, buf.len());
+    assert_eq!(fields(&record), (11, 10, "a.txt", CREATE));
+
+    let buf = v3(12, 10, 0, CREATE, "中文.txt");
+    let (record, len) = UsnRecord::parse(&buf).unwrap();
+    assert_eq!(len, buf.len());
+    assert_eq!(fields(&record), (12, 10, "中文.txt", CREATE));
+
+    // ReFS 的 ID 会用到高 64 位
+    let frn = 0x0123_4567_89AB_CDEF_0011_2233_4455_6677;
+    let parent = 1 << 100 | 10;
+    let buf = v3(frn, parent, 0, CREATE, "refs.txt");
+    let (record, _) = UsnRecord::parse(&buf).unwrap();
+    assert_eq!(fields(&record), (frn, parent, "refs.txt", CREATE));
+
+    let buf = v4(frn, 4096, 1, &[(0, 4096), (65536, 512)]);
+    let (record, len) = RangeRecord::parse(&buf).unwrap();
+    assert_eq!(len, buf.len());
+    assert_eq!((record.frn, record.parent_frn), (frn, 5));
+    assert_eq!(
+        (record.usn, record.reason, record.remaining_extents),
+        (4096, UsnReason::DATA_EXTEND, 1)
+    );
+    assert_eq!(
+        record.extents,
+        [
+            UsnExtent {
+                offset: 0,
+                length: 4096
+            },
+            UsnExtent {
+                offset: 65536,
+                length: 512
+            },
+        ]
+    );
+    assert_eq!(
+        UsnRecord::parse(&buf).unwrap_err(),
+        RecordError::UnsupportedVersion(4)
+    );
+    assert_eq!(
+        RangeRecord::parse(&v2(11, 10, 0, CREATE, "a.txt")).unwrap_err(),
+        RecordError::UnsupportedVersion(2)
+    );
+
+    // 后面多余的数据不属于这条记录
+    let mut buf = v2(13, 10, 0, CREATE, "");
+    let len = buf.len();
+    buf.extend([0xFF; 16]);
+    let (record, used) = UsnRecord::parse(&buf).unwrap();
+    assert_eq!(used, len);
+    assert_eq!(fields(&record), (13, 10, "", CREATE));
+}
+
+/// 写入 Usn、TimeStamp 以及 Reason 之后的 SourceInfo、SecurityId 和 FileAttributes
+fn with_info(mut buf: Vec<u8>, usn: usize, reason: usize) -> Vec<u8> {
+    buf[usn..usn + 8].copy_from_slice(&0x1234_5678_9000i64.to_le_bytes());
+    buf[usn + 8..usn + 16].copy_from_slice(&133_500_000_000_000_000i64.to_le_bytes());
+    buf[reason + 4..reason + 8].copy_from_slice(&2u32.to_le_bytes());
+    buf[reason + 8..reason + 12].copy_from_slice(&0x105u32.to_le_bytes());
+    buf[reason + 12..reason + 16].copy_from_slice(&0x12u32.to_le_bytes());
+    buf
+}
+
+#[test]
+fn info() {
+    for buf in [
+        with_info(v2(11, 10, 0, CREATE, "docs"), 24, 40),
+        with_info(v3(11, 10, 0, CREATE, "docs"), 40, 56),
+    ] {
+        let (record, _) = UsnRecord::parse(&buf).unwrap();
+        assert_eq!(fields(&record), (11, 10, "docs", CREATE));
+        assert_eq!(record.usn, 0x1234_5678_9000);
+        assert_eq!(record.timestamp, 133_500_000_000_000_000);
+        assert_eq!(record.source_info, 2);
+        assert_eq!(record.security_id, 0x105);
+        assert_eq!(
+            record.attributes,
+            FileAttributes::DIRECTORY | FileAttributes::HIDDEN
+        );
+        assert!(record.attributes.is_dir() && record.attributes.is_hidden());
+        assert_eq!(
+            record.time().unwrap(),
+            UNIX_EPOCH + Duration::from_secs(1_705_526_400)
+        );
+    }
+
+    // 枚举 MFT 得到的记录没有时间
+    let (record, _) = UsnRecord::parse(&v2(11, 10, 0, CREATE, "a.txt")).unwrap();
+    assert_eq!(record.time(), None);
+    assert!(!record.attributes.is_dir());
+}
+
+#[test]
+fn filetime() {
+    for time in [
+        UNIX_EPOCH,
+        UNIX_EPOCH + Duration::new(1_705_526_400, 123_456_700),
+        UNIX_EPOCH - Duration::from_secs(86400 * 365),
+    ] {
+        assert_eq!(from_filetime(to_filetime(time)), time);
+    }
+    assert_eq!(to_filetime(UNIX_EPOCH), 116_444_736_000_000_000);
+    assert_eq!(
+        from_filetime(0),
+        UNIX_EPOCH - Duration::from_secs(11_644_473_600)
+    );
+}
+
+#[test]
+fn raw_records() {
+    // V4 被跳过
+    let buf = [
+        v2(11, 10, 0, CREATE, "a.txt"),
+        v3(12, 10, 0, CREATE, "b.txt"),
+        v4(12, 8192, 0, &[(0, 16)]),
+        v2(13, 10, 0, CREATE, "c.txt"),
+    ]
+    .concat();
+    let res: Vec<_> = RawRecords::new(&buf).map(|r| r.unwrap().filename).collect();
+    assert_eq!(res, ["a.txt", "b.txt", "c.txt"]);
+
+    // 遇到错误后停止
+    let mut buf = [v2(11, 10, 0, CREATE, "a.txt"), vec![0; 8]].concat();
+    buf.extend(v2(12, 10, 0, CREATE, "b.txt"));
+    let mut iter = RawRecords::new(&buf);
+    assert!(iter.next().unwrap().is_ok());
+    assert_eq!(
+        iter.next().unwrap().unwrap_err(),
+        RecordError::UnsupportedVersion(0)
+    );
+    assert!(iter.next().is_none());
+    assert!(iter.remaining().is_empty());
+}
+
+#[test]
+fn errors() {
+    let valid = v2(11, 10, 0, CREATE, "a.txt");
+    let parse = |buf: &[u8]| UsnRecord::parse(buf).unwrap_err();
+
+    assert_eq!(
+        parse(&valid[..4]),
+        RecordError::Truncated { need: 8, have: 4 }
+    );
+    assert_eq!(
+        parse(&valid[..40]),
+        RecordError::Truncated {
+            need: valid.len(),
+            have: 40
+        }
+    );
+
+    let mut buf = valid.clone();
+    buf[..4].copy_from_slice(&0u32.to_le_bytes());
+    assert_eq!(parse(&buf), RecordError::BadLength(0));
+    buf[..4].copy_from_slice(&70u32.to_le_bytes());
+    assert_eq!(parse(&buf), RecordError::BadLength(70));
+    // 比固定部分还短
+    buf[..4].copy_from_slice(&56u32.to_le_bytes());
+    assert_eq!(parse(&buf), RecordError::BadLength(56));
+
+    let mut buf = valid.clone();
+    buf[4..6].copy_from_slice(&5u16.to_le_bytes());
+    assert_eq!(parse(&buf), RecordError::UnsupportedVersion(5));
+
+    let mut buf = valid.clone();
+    buf[58..60].copy_from_slice(&61u16.to_le_bytes());
+    assert_eq!(
+        parse(&buf),
+        RecordError::MisalignedName {
+            offset: 61,
+            len: 10
+        }
+    );
+    buf[58..60].copy_from_slice(&60u16.to_le_bytes());
+    buf[56..58].copy_from_slice(&9u16.to_le_bytes());
+    assert_eq!(
+        parse(&buf),
+        RecordError::MisalignedName { offset: 60, len: 9 }
+    );
+
+    let mut buf = valid.clone();
+    buf[56..58].copy_from_slice(&100u16.to_le_bytes());
+    assert_eq!(
+        parse(&buf),
+        RecordError::NameOutOfBounds {
+            offset: 60,
+            len: 100
+        }
+    );
+    // 名称与固定部分重叠
+    buf[56..58].copy_from_slice(&10u16.to_le_bytes());
+    buf[58..60].copy_from_slice(&40u16.to_le_bytes());
+    assert_eq!(
+        parse(&buf),
+        RecordError::NameOutOfBounds {
+            offset: 40,
+            len: 10
+        }
+    );
+
+    let valid = v4(11, 4096, 0, &[(0, 4096)]);
+    let mut buf = valid.clone();
+    buf[..4].copy_from_slice(&56u32.to_le_bytes());
+    assert_eq!(
+        RangeRecord::parse(&buf).unwrap_err(),
+        RecordError::BadLength(56)
+    );
+    let mut buf = valid.clone();
+    buf[60..62].copy_from_slice(&2u16.to_le_bytes());
+    assert_eq!(
+        RangeRecord::parse(&buf).unwrap_err(),
+        RecordError::ExtentsOutOfBounds { count: 2, size: 16 }
+    );
+    let mut buf = valid.clone();
+    buf[62..64].copy_from_slice(&8u16.to_le_bytes());
+    assert_eq!(
+        RangeRecord::parse(&buf).unwrap_err(),
+        RecordError::ExtentsOutOfBounds { count: 1, size: 8 }
+    );
+}
+
+/// 简单的线性同余生成器，保证测试可以复现
+struct Lcg(u64);
+
+impl Lcg {
+    fn next(&mut self) -> u64 {
+        self.0 = self
+            .0
+            .wrapping_mul(6364136223846793005)
+            .wrapping_add(1442695040888963407);
+        self.0 >> 33
+    }
+}
+
+#[test]
+fn fuzz() {
+    let valid = [
+        v2(11, 10, 0, CREATE, "a.txt"),
+        v3(12, 10, 0, CREATE, "中文.txt"),
+        v4(12, 4096, 0, &[(0, 4096), (8192, 16)]),
+        v2(13, 10, 0, CREATE, ""),
+    ]
+    .concat();
+
+    // 任意截断
+    for end in 0..=valid.len() {
+        let _ = UsnRecord::parse(&valid[..end]);
+        let _ = RangeRecord::parse(&valid[..end]);
+        assert!(RawRecords::new(&valid[..end]).count() <= 3);
+    }
+
+    // 修改任意一个字节
+    for i in 0..valid.len() {
+        for byte in [0x00, 0x01, 0x7F, 0x80, 0xFF] {
+            let mut buf = valid.clone();
+            buf[i] = byte;
+            let _ = UsnRecord::parse(&buf);
+            let _ = RangeRecord::parse(&buf);
+            assert!(RawRecords::new(&buf).count() <= buf.len() / 8);
+        }
+    }
+
+    // 随机数据
+    let mut rng = Lcg(0x5EED);
+    for _ in 0..2000 {
+        let len = rng.next() as usize % 256;
+        let mut buf: Vec<u8> = (0..len).map(|_| rng.next() as u8).collect();
+        if len >= 8 {
+            // 让长度和版本更容易通过检查，走到后面的分支
+            let record_len = (rng.next() as u32 % 32) * 8;
+            buf[..4].copy_from_slice(&record_len.to_le_bytes());
+            buf[4..6].copy_from_slice(&(2 + rng.next() as u16 % 3).to_le_bytes());
+        }
+        let _ = UsnRecord::parse(&buf);
+        let _ = RangeRecord::parse(&buf);
+        assert!(RawRecords::new(&buf).count() <= buf.len() / 8 + 1);
+    }
+}